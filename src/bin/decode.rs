@@ -0,0 +1,140 @@
+/// CLI tool to inspect captured ESC/POS jobs
+use base64::Engine;
+use escpos2mqtt::printer::decode::decode;
+use escpos2mqtt::printer::Command;
+use escpos2mqtt::program::{self, ImageOptions, ImageSource, MAX_IMAGE_WIDTH};
+use escpos2mqtt::{codepage, dither};
+use std::io::Read;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: decode [--model <model>] [--format text|json] [<file>]";
+
+fn usage(message: &str) -> ExitCode {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    eprintln!("Decodes ESC/POS bytes from the file or stdin into commands, one per line");
+    ExitCode::from(2)
+}
+
+/// The command as a line of source text, or as `<...>` if it has none
+fn describe(command: &Command, text: impl Fn(&[u8]) -> String) -> String {
+    match command {
+        Command::EncodedText(bytes) => format!("<text {:?}>", text(bytes)),
+        Command::CodePage(number) => format!("<codepage {}>", number),
+        Command::BitImageFromBytesWithWidth(png, width) => match image::load_from_memory(png) {
+            Ok(image) => format!("<image {}x{}>", width, image.height()),
+            Err(_) => format!("<image {}>", width),
+        },
+        Command::Bytes(bytes) => {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            format!("<bytes {}>", hex.join(" "))
+        }
+        command => program::Command::Raw(command.clone()).to_dsl(),
+    }
+}
+
+/// The commands as a program that can be sent as JSON, with the text decoded
+/// and images as base64 data. Raw bytes can't be sent and are left out.
+fn to_program(commands: &[Command], profile: &escpos_db::Profile) -> Vec<program::Command> {
+    let mut code_page = 0;
+    commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::CodePage(number) => {
+                code_page = *number;
+                None
+            }
+            Command::Bytes(_) => None,
+            Command::EncodedText(bytes) => Some(program::Command::Raw(Command::Write(
+                codepage::decode(profile, code_page, bytes),
+            ))),
+            Command::BitImageFromBytesWithWidth(png, width) => Some(program::Command::Image {
+                source: ImageSource::Base64(base64::engine::general_purpose::STANDARD.encode(png)),
+                options: ImageOptions {
+                    width: Some((*width).min(MAX_IMAGE_WIDTH)),
+                    dither: dither::Options::threshold(),
+                },
+            }),
+            command => Some(program::Command::Raw(command.clone())),
+        })
+        .collect()
+}
+
+fn main() -> ExitCode {
+    let mut model = None;
+    let mut json = false;
+    let mut file = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--model" | "-m" => match args.next() {
+                Some(name) => model = Some(name),
+                None => return usage("--model requires a printer model"),
+            },
+            "--format" | "-f" => match args.next().as_deref() {
+                Some("text") => json = false,
+                Some("json") => json = true,
+                _ => return usage("--format must be text or json"),
+            },
+            "--help" | "-h" => return usage("Decodes ESC/POS jobs"),
+            "-" => file = None,
+            _ if arg.starts_with('-') => return usage(&format!("unknown option {}", arg)),
+            _ if file.is_some() => return usage("only one file can be decoded"),
+            _ => file = Some(arg),
+        }
+    }
+
+    // Used for the code pages of text
+    let profile = match &model {
+        Some(name) => match escpos_db::ALL_PROFILES.get(name.as_str()) {
+            Some(profile) => *profile,
+            None => {
+                eprintln!(
+                    "unknown printer model {}, see the model names of escpos-db",
+                    name
+                );
+                return ExitCode::from(2);
+            }
+        },
+        None => &escpos_db::DEFAULT,
+    };
+
+    let read = match &file {
+        Some(file) => std::fs::read(file),
+        None => {
+            let mut bytes = Vec::new();
+            std::io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+        }
+    };
+    let bytes = match read {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("{}: {}", file.as_deref().unwrap_or("stdin"), err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let commands = decode(&bytes);
+    if json {
+        let program = to_program(&commands, profile);
+        println!("{}", serde_json::to_string_pretty(&program).unwrap());
+        let skipped = commands
+            .iter()
+            .filter(|command| matches!(command, Command::Bytes(_)))
+            .count();
+        if skipped > 0 {
+            eprintln!("left out {} sequences without a command", skipped);
+        }
+    } else {
+        let mut code_page = 0;
+        for command in &commands {
+            if let Command::CodePage(number) = command {
+                code_page = *number;
+            }
+            let text = |bytes: &[u8]| codepage::decode(profile, code_page, bytes);
+            println!("{}", describe(command, text));
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,103 @@
+/// CLI tool to check program files, e.g. in CI
+use escpos2mqtt::program::lint::{lint, Finding};
+use escpos2mqtt::renderer::Severity;
+use serde::Serialize;
+use std::process::ExitCode;
+
+const USAGE: &str =
+    "Usage: escpos2mqtt-lint [--model <model>] [--format text|json] <file.escpos>...";
+
+#[derive(Serialize)]
+struct Report<'a> {
+    file: &'a str,
+    #[serde(flatten)]
+    finding: &'a Finding,
+}
+
+fn usage(message: &str) -> ExitCode {
+    eprintln!("{}", message);
+    eprintln!("{}", USAGE);
+    eprintln!(
+        "Checks the syntax of programs, and their commands against the printer model if given"
+    );
+    ExitCode::from(2)
+}
+
+fn main() -> ExitCode {
+    let mut model = None;
+    let mut json = false;
+    let mut files = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--model" | "-m" => match args.next() {
+                Some(name) => model = Some(name),
+                None => return usage("--model requires a printer model"),
+            },
+            "--format" | "-f" => match args.next().as_deref() {
+                Some("text") => json = false,
+                Some("json") => json = true,
+                _ => return usage("--format must be text or json"),
+            },
+            "--help" | "-h" => return usage("Lints escpos2mqtt programs"),
+            _ if arg.starts_with('-') => return usage(&format!("unknown option {}", arg)),
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        return usage("no files given");
+    }
+
+    let profile = match &model {
+        Some(name) => match escpos_db::ALL_PROFILES.get(name.as_str()) {
+            Some(profile) => Some(*profile),
+            None => {
+                eprintln!(
+                    "unknown printer model {}, see the model names of escpos-db",
+                    name
+                );
+                return ExitCode::from(2);
+            }
+        },
+        None => None,
+    };
+
+    let mut failed = false;
+    let mut reports = Vec::new();
+    for file in &files {
+        let source = match std::fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                failed = true;
+                continue;
+            }
+        };
+        reports.push((file, lint(&source, profile)));
+    }
+
+    let findings = reports
+        .iter()
+        .flat_map(|(file, findings)| findings.iter().map(move |finding| (file, finding)));
+    failed |= findings
+        .clone()
+        .any(|(_, finding)| finding.severity == Severity::Error);
+
+    if json {
+        let reports: Vec<Report> = findings
+            .map(|(file, finding)| Report { file, finding })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    } else {
+        for (file, finding) in findings {
+            println!("{}:{}", file, finding);
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -1,6 +1,6 @@
-use env_logger;
 use envconfig::Envconfig;
 use mqtt_typed_client::{MqttClient, MqttClientConfig};
+use std::path::PathBuf;
 use std::time::Duration;
 use uuid::Uuid;
 
@@ -9,6 +9,7 @@ use escpos2mqtt::mqtt_service::MqttService;
 use escpos2mqtt::mqtt::topics::ServiceAvailableTopic;
 use escpos2mqtt::mqtt::topics::service_available_topic::ServiceAvailableTopicExt;
 use escpos2mqtt::registry::PrinterRegistry;
+use escpos2mqtt::settings::Settings;
 
 #[derive(Envconfig)]
 struct Config {
@@ -29,6 +30,23 @@ struct Config {
 
     #[envconfig(from = "PRINTER_TIMEOUT_SECS", default = "60")]
     pub printer_timeout_secs: u64,
+
+    /// Comma separated ids of printers that get barcodes and QR codes as images, or `all`
+    #[envconfig(from = "RASTERIZE_CODES", default = "")]
+    pub rasterize_codes: String,
+
+    /// How jobs that don't fit a printer are handled: `reject`, `adapt` or `ignore`,
+    /// followed by `<printer id>=<policy>` overrides
+    #[envconfig(from = "VALIDATION_POLICY", default = "adapt")]
+    pub validation_policy: String,
+
+    /// Directory the `image file` command may read images from
+    #[envconfig(from = "IMAGE_DIR")]
+    pub image_dir: Option<PathBuf>,
+
+    /// Directory the `text_image` command may read font files from
+    #[envconfig(from = "FONT_DIR")]
+    pub font_dir: Option<PathBuf>,
 }
 
 pub fn get_client_id(prefix: &str) -> String {
@@ -136,15 +154,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         let manual_profile = escpos_db::ALL_PROFILES
             .get(&manual_printer_model_config)
-            .expect(&format!(
-                "Printer model {} not found!",
-                &manual_printer_model_config
-            ));
+            .unwrap_or_else(|| {
+                panic!("Printer model {} not found!", &manual_printer_model_config)
+            });
 
         registry.add_manual_printer(
             MANUAL_PRINTER_ID.to_string(),
             manual_printer,
-            *manual_profile,
+            manual_profile,
         ).await;
     }
 
@@ -167,6 +184,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         mqtt_service_registry,
         mqtt_service_client,
         registry_event_rx,
+        Settings::new(&config.rasterize_codes, &config.validation_policy)?
+            .with_image_dir(config.image_dir)
+            .with_font_dir(config.font_dir),
     );
 
     log::info!(
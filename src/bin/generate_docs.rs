@@ -1,6 +1,7 @@
 /// CLI tool to generate DSL documentation
 /// Documentation is automatically extracted from parser annotations
-use escpos2mqtt::program::{documentation, Command};
+use escpos2mqtt::mqtt::print_job;
+use escpos2mqtt::program::{documentation, editor, Command};
 use std::fs;
 use std::path::PathBuf;
 
@@ -16,10 +17,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let content = match format {
         "markdown" | "md" => documentation::generate_markdown(),
         "text" | "txt" => documentation::generate_text(),
+        "json" => documentation::generate_json(),
+        "jsonschema" => print_job::generate_json_schema(),
+        "textmate" => {
+            // A grammar and snippets, written to a directory
+            let directory = output_path.unwrap_or_else(|| PathBuf::from("."));
+            fs::create_dir_all(&directory)?;
+            let grammar = directory.join(format!("{}.tmLanguage.json", editor::FILE_TYPE));
+            let snippets = directory.join(format!("{}.code-snippets", editor::FILE_TYPE));
+            fs::write(&grammar, editor::generate_textmate_grammar())?;
+            fs::write(&snippets, editor::generate_snippets())?;
+            println!("Grammar written to: {}", grammar.display());
+            println!("Snippets written to: {}", snippets.display());
+            return Ok(());
+        }
         _ => {
-            eprintln!("Usage: generate_docs [markdown|text] [output_file]");
-            eprintln!("Formats: markdown (default), text");
+            eprintln!(
+                "Usage: generate_docs [markdown|text|json|jsonschema|textmate] [output_file]"
+            );
+            eprintln!("Formats: markdown (default), text, json (command catalogue),");
+            eprintln!("         jsonschema (of JSON print jobs), textmate (grammar and snippets)");
             eprintln!("If output_file is not specified, prints to stdout");
+            eprintln!("textmate writes two files to the directory output_file, the current directory by default");
             std::process::exit(1);
         }
     };
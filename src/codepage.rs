@@ -0,0 +1,337 @@
+//! Code page tables and the encoding of text for the code pages of a printer
+use escpos_db::{Encoding, Profile};
+use oem_cp::code_table::DECODING_TABLE_CP_MAP;
+use oem_cp::code_table_type::TableType;
+
+use crate::printer;
+
+/// Characters 128-255 of a code page, `None` where a code point is undefined
+pub type Table = [Option<char>; 128];
+
+/// Name of a code page as used by the `codepage` command, e.g. `CP858`
+pub fn name(encoding: Encoding) -> String {
+    format!("{:?}", encoding)
+}
+
+/// The characters of a single byte code page, if known
+pub fn table(encoding: Encoding) -> Option<Table> {
+    if let Some(data) = encoding.data() {
+        return Some(data.map(Some));
+    }
+
+    let oem = |number: u16| match DECODING_TABLE_CP_MAP.get(&number)? {
+        TableType::Complete(table) => Some(table.map(Some)),
+        TableType::Incomplete(table) => Some(**table),
+    };
+    // encoding_rs follows the WHATWG standard, which decodes the C1 control
+    // codes of ISO pages as Windows characters. Printers don't.
+    let web = |encoding: &'static encoding_rs::Encoding, iso: bool| {
+        Some(std::array::from_fn(|i| {
+            let byte = 0x80 + i as u8;
+            let bytes = [byte];
+            let (text, malformed) = encoding.decode_without_bom_handling(&bytes);
+            match text.chars().next() {
+                _ if malformed || (iso && byte < 0xA0) => None,
+                // Undefined code points of Windows pages decode to C1 controls
+                Some(character) if character.is_control() => None,
+                character => character,
+            }
+        }))
+    };
+
+    match encoding {
+        Encoding::CP437 => oem(437),
+        Encoding::CP720 => oem(720),
+        Encoding::CP737 => oem(737),
+        Encoding::CP775 => oem(775),
+        Encoding::CP850 => oem(850),
+        Encoding::CP852 => oem(852),
+        Encoding::CP855 => oem(855),
+        Encoding::CP857 => oem(857),
+        Encoding::CP858 => oem(858),
+        Encoding::CP860 => oem(860),
+        Encoding::CP861 => oem(861),
+        Encoding::CP862 => oem(862),
+        Encoding::CP863 => oem(863),
+        Encoding::CP864 => oem(864),
+        Encoding::CP865 => oem(865),
+        Encoding::CP866 => oem(866),
+        Encoding::CP869 => oem(869),
+        Encoding::CP874 => oem(874),
+        Encoding::CP1250 => web(encoding_rs::WINDOWS_1250, false),
+        Encoding::CP1251 => web(encoding_rs::WINDOWS_1251, false),
+        Encoding::CP1252 => web(encoding_rs::WINDOWS_1252, false),
+        Encoding::CP1253 => web(encoding_rs::WINDOWS_1253, false),
+        Encoding::CP1254 => web(encoding_rs::WINDOWS_1254, false),
+        Encoding::CP1255 => web(encoding_rs::WINDOWS_1255, false),
+        Encoding::CP1256 => web(encoding_rs::WINDOWS_1256, false),
+        Encoding::CP1257 => web(encoding_rs::WINDOWS_1257, false),
+        Encoding::CP1258 => web(encoding_rs::WINDOWS_1258, false),
+        // ISO 8859-1 is the first block of Unicode
+        Encoding::ISO_8859_1 => Some(std::array::from_fn(|i| {
+            (i >= 0x20).then(|| char::from(0x80 + i as u8))
+        })),
+        Encoding::ISO_8859_2 => web(encoding_rs::ISO_8859_2, true),
+        Encoding::ISO_8859_3 => web(encoding_rs::ISO_8859_3, true),
+        Encoding::ISO_8859_4 => web(encoding_rs::ISO_8859_4, true),
+        Encoding::ISO_8859_5 => web(encoding_rs::ISO_8859_5, true),
+        Encoding::ISO_8859_6 => web(encoding_rs::ISO_8859_6, true),
+        Encoding::ISO_8859_7 => web(encoding_rs::ISO_8859_7, true),
+        Encoding::ISO_8859_8 => web(encoding_rs::ISO_8859_8, true),
+        Encoding::ISO_8859_9 => web(encoding_rs::WINDOWS_1254, true),
+        Encoding::ISO_8859_15 => web(encoding_rs::ISO_8859_15, true),
+        _ => None,
+    }
+}
+
+/// A code page of the printer and its `ESC t` number
+#[derive(Debug, Clone)]
+struct Page {
+    number: u8,
+    encoding: Encoding,
+    table: Table,
+}
+
+impl Page {
+    fn encode(&self, character: char) -> Option<u8> {
+        self.table
+            .iter()
+            .position(|c| *c == Some(character))
+            .map(|i| 0x80 + i as u8)
+    }
+}
+
+/// Encodes text for a printer, switching between the code pages of its
+/// profile as needed
+///
+/// ASCII text is printed as is. For other characters the encoder stays on the
+/// current code page if it contains them, and otherwise switches to the page
+/// that covers the longest run of the following characters. Characters that no
+/// page covers are transliterated, e.g. `“` to `"`.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    pages: Vec<Page>,
+    /// Page selected on the printer, code page 0 after initialization
+    current: u8,
+    /// Page forced with the `codepage` command
+    forced: Option<u8>,
+}
+
+impl Encoder {
+    pub fn new(profile: &Profile) -> Self {
+        let pages = profile
+            .code_pages
+            .iter()
+            .filter_map(|(number, encoding)| {
+                Some(Page {
+                    number,
+                    encoding: *encoding,
+                    table: table(*encoding)?,
+                })
+            })
+            .collect();
+        Encoder {
+            pages,
+            current: 0,
+            forced: None,
+        }
+    }
+
+    /// Forces the code page with the given name for all following text, or
+    /// returns to automatic selection if `name` is `None`
+    ///
+    /// Returns `false` if the printer has no such code page.
+    pub fn force(&mut self, name: Option<&str>) -> bool {
+        let Some(name) = name else {
+            self.forced = None;
+            return true;
+        };
+
+        let normalize = |name: &str| name.to_uppercase().replace('-', "_");
+        let page = self
+            .pages
+            .iter()
+            .find(|page| normalize(&self::name(page.encoding)) == normalize(name));
+        match page {
+            Some(page) => {
+                self.forced = Some(page.number);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Encodes text, selecting code pages with [`printer::Command::CodePage`]
+    pub fn encode(&mut self, text: &str) -> Vec<printer::Command> {
+        let mut commands = Vec::new();
+        let mut bytes = Vec::new();
+        let characters: Vec<char> = text.chars().collect();
+
+        if let Some(forced) = self.forced {
+            self.select(forced, &mut commands, &mut bytes);
+        }
+
+        for (i, character) in characters.iter().enumerate() {
+            if character.is_ascii() {
+                bytes.push(*character as u8);
+                continue;
+            }
+
+            let page = match self.forced {
+                Some(_) => self.current_page(),
+                None => self
+                    .current_page()
+                    .filter(|page| page.encode(*character).is_some())
+                    .or_else(|| self.best_page(&characters[i..])),
+            };
+            match page.and_then(|page| Some((page.number, page.encode(*character)?))) {
+                Some((number, byte)) => {
+                    self.select(number, &mut commands, &mut bytes);
+                    bytes.push(byte);
+                }
+                None => bytes.extend(transliterate(*character).bytes()),
+            }
+        }
+
+        if !bytes.is_empty() {
+            commands.push(printer::Command::EncodedText(bytes));
+        }
+        commands
+    }
+
+    fn current_page(&self) -> Option<&Page> {
+        self.pages.iter().find(|page| page.number == self.current)
+    }
+
+    /// The page covering the most of the non-ASCII characters at the start of `text`
+    fn best_page(&self, text: &[char]) -> Option<&Page> {
+        let covered = |page: &Page| {
+            text.iter()
+                .filter(|c| !c.is_ascii())
+                .take_while(|c| page.encode(**c).is_some())
+                .count()
+        };
+        // The first page wins ties, max_by_key would pick the last
+        self.pages
+            .iter()
+            .map(|page| (covered(page), page))
+            .filter(|(count, _)| *count > 0)
+            .fold(
+                None,
+                |best: Option<(usize, &Page)>, (count, page)| match best {
+                    Some((best_count, _)) if best_count >= count => best,
+                    _ => Some((count, page)),
+                },
+            )
+            .map(|(_, page)| page)
+    }
+
+    /// Switches to the page, ending the text encoded so far
+    fn select(&mut self, number: u8, commands: &mut Vec<printer::Command>, bytes: &mut Vec<u8>) {
+        if self.current == number {
+            return;
+        }
+        if !bytes.is_empty() {
+            commands.push(printer::Command::EncodedText(std::mem::take(bytes)));
+        }
+        commands.push(printer::Command::CodePage(number));
+        self.current = number;
+    }
+}
+
+/// Decodes text encoded for the code page with the `ESC t` number `number` of a
+/// profile. Bytes the page doesn't define are decoded as `?`.
+pub fn decode(profile: &Profile, number: u8, bytes: &[u8]) -> String {
+    let table = profile
+        .code_pages
+        .get(number)
+        .and_then(|encoding| table(*encoding));
+    bytes
+        .iter()
+        .map(|byte| match byte {
+            0..=0x7F => char::from(*byte),
+            _ => table
+                .as_ref()
+                .and_then(|table| table[usize::from(byte - 0x80)])
+                .unwrap_or('?'),
+        })
+        .collect()
+}
+
+/// ASCII replacement of a character, `?` if there is none
+fn transliterate(character: char) -> &'static str {
+    deunicode::deunicode_char(character)
+        .filter(|text| !text.is_empty())
+        .unwrap_or("?")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printer::Command::{CodePage, EncodedText};
+
+    #[test]
+    fn test_tables() {
+        let cp437 = table(Encoding::CP437).unwrap();
+        assert_eq!(cp437[0x81 - 0x80], Some('ü'));
+        let cp1252 = table(Encoding::CP1252).unwrap();
+        assert_eq!(cp1252[0x80 - 0x80], Some('€'));
+        assert_eq!(cp1252[0x81 - 0x80], None);
+        let iso = table(Encoding::ISO_8859_15).unwrap();
+        assert_eq!(iso[0x80 - 0x80], None);
+        assert_eq!(iso[0xA4 - 0x80], Some('€'));
+        assert!(table(Encoding::CP932).is_none());
+    }
+
+    #[test]
+    fn test_page_selection() {
+        let mut encoder = Encoder::new(&escpos_db::DEFAULT);
+
+        // Umlauts are on the initial code page 437
+        assert_eq!(
+            encoder.encode("Grüße"),
+            vec![EncodedText(b"Gr\x81\xe1e".to_vec())]
+        );
+        // The euro sign needs a switch to the first page that also has the ü,
+        // and the next text stays on that page
+        assert_eq!(
+            encoder.encode("5 € für"),
+            vec![
+                EncodedText(b"5 ".to_vec()),
+                CodePage(16),
+                EncodedText(b"\x80 f\xfcr".to_vec())
+            ]
+        );
+        assert_eq!(encoder.encode("ä"), vec![EncodedText(vec![0xE4])]);
+        // Cyrillic
+        assert_eq!(
+            encoder.encode("Да"),
+            vec![CodePage(17), EncodedText(vec![0x84, 0xA0])]
+        );
+    }
+
+    #[test]
+    fn test_forced_page_and_transliteration() {
+        let mut encoder = Encoder::new(&escpos_db::DEFAULT);
+        assert!(!encoder.force(Some("CP9999")));
+        assert!(encoder.force(Some("cp858")));
+        assert_eq!(
+            encoder.encode("“ß” ✓\u{e000}"),
+            vec![CodePage(19), EncodedText(b"\"\xe1\" OK?".to_vec())]
+        );
+
+        encoder.force(None);
+        assert_eq!(
+            encoder.encode("Ω"),
+            vec![CodePage(0), EncodedText(vec![0xEA])]
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let profile = &escpos_db::DEFAULT;
+        assert_eq!(decode(profile, 0, b"Gr\x81\xe1e"), "Grüße");
+        assert_eq!(decode(profile, 16, b"\x80 f\xfcr"), "€ für");
+        assert_eq!(decode(profile, 250, b"a\x80"), "a?");
+    }
+}
@@ -0,0 +1,330 @@
+//! Conversion of images to the black and white bitmaps printed by receipt printers
+use image::{DynamicImage, GrayImage, ImageFormat, Luma, Rgba, RgbaImage};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::io::Cursor;
+
+/// How gray values are reduced to black and white
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    /// Error diffusion to the neighbouring pixels, good for photos
+    #[default]
+    FloydSteinberg,
+    /// Error diffusion that keeps more contrast, good for logos and icons
+    Atkinson,
+    /// Ordered dithering with an 8x8 Bayer matrix, giving a regular pattern
+    Bayer,
+    /// Every pixel darker than the threshold is black, good for line art and text
+    Threshold,
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::FloydSteinberg => "floyd_steinberg",
+            Algorithm::Atkinson => "atkinson",
+            Algorithm::Bayer => "bayer",
+            Algorithm::Threshold => "threshold",
+        }
+    }
+}
+
+/// Dithering algorithm and tone adjustments applied before dithering
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+#[schemars(rename = "DitherOptions")]
+pub struct Options {
+    pub algorithm: Algorithm,
+    /// Gray value (0-255) below which pixels are black
+    pub threshold: u8,
+    /// Added to every pixel, from -1.0 (black) to 1.0 (white)
+    #[serde(deserialize_with = "deserialize_brightness")]
+    #[schemars(range(min = -1.0, max = 1.0))]
+    pub brightness: f32,
+    /// Factor applied to the distance of every pixel from mid gray
+    #[serde(deserialize_with = "deserialize_contrast")]
+    #[schemars(range(min = 0.0))]
+    pub contrast: f32,
+    /// Gamma correction, values above 1.0 lighten mid tones
+    #[serde(deserialize_with = "deserialize_gamma")]
+    #[schemars(extend("exclusiveMinimum" = 0.0))]
+    pub gamma: f32,
+    /// Swaps black and white
+    pub invert: bool,
+}
+
+// The adjustments are checked like in the parser of the DSL
+
+fn deserialize_brightness<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    match f32::deserialize(deserializer)? {
+        brightness if (-1.0..=1.0).contains(&brightness) => Ok(brightness),
+        _ => Err(serde::de::Error::custom(
+            "brightness must be from -1.0 to 1.0",
+        )),
+    }
+}
+
+fn deserialize_contrast<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    match f32::deserialize(deserializer)? {
+        contrast if contrast >= 0.0 => Ok(contrast),
+        _ => Err(serde::de::Error::custom("contrast must not be negative")),
+    }
+}
+
+fn deserialize_gamma<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    match f32::deserialize(deserializer)? {
+        gamma if gamma > 0.0 => Ok(gamma),
+        _ => Err(serde::de::Error::custom("gamma must be greater than 0")),
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            algorithm: Algorithm::default(),
+            threshold: 128,
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            invert: false,
+        }
+    }
+}
+
+impl Options {
+    /// Plain thresholding without adjustments, for images that are already black and white
+    pub fn threshold() -> Self {
+        Options {
+            algorithm: Algorithm::Threshold,
+            ..Options::default()
+        }
+    }
+}
+
+/// Converts an image to grayscale, flattening transparent pixels onto white paper
+pub fn grayscale(image: &DynamicImage) -> GrayImage {
+    let rgba = image.to_rgba8();
+    GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let luma = (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000;
+        let alpha = u32::from(a);
+        Luma([((luma * alpha + 255 * (255 - alpha)) / 255) as u8])
+    })
+}
+
+/// Converts a pixmap rendered by resvg to grayscale, see [`grayscale`]
+pub fn grayscale_pixmap(pixmap: &resvg::tiny_skia::Pixmap) -> GrayImage {
+    let rgba = RgbaImage::from_fn(pixmap.width(), pixmap.height(), |x, y| {
+        let color = pixmap.pixels()[(y * pixmap.width() + x) as usize].demultiply();
+        Rgba([color.red(), color.green(), color.blue(), color.alpha()])
+    });
+    grayscale(&DynamicImage::ImageRgba8(rgba))
+}
+
+/// Applies the adjustments of `options` and dithers the image to pixels that are
+/// either black (0) or white (255)
+pub fn process(image: &GrayImage, options: &Options) -> GrayImage {
+    let mut image = image.clone();
+    adjust(&mut image, options);
+    dither(&image, options.algorithm, options.threshold)
+}
+
+/// Applies brightness, contrast, gamma and inversion, in that order
+pub fn adjust(image: &mut GrayImage, options: &Options) {
+    let lookup: Vec<u8> = (0..=255u8)
+        .map(|value| {
+            let mut value = f32::from(value) / 255.0 + options.brightness;
+            value = (value - 0.5) * options.contrast + 0.5;
+            value = value.clamp(0.0, 1.0);
+            if options.gamma > 0.0 {
+                value = value.powf(1.0 / options.gamma);
+            }
+            if options.invert {
+                value = 1.0 - value;
+            }
+            (value * 255.0).round() as u8
+        })
+        .collect();
+
+    for pixel in image.pixels_mut() {
+        pixel.0[0] = lookup[pixel.0[0] as usize];
+    }
+}
+
+/// Reduces the image to black and white with the given algorithm
+pub fn dither(image: &GrayImage, algorithm: Algorithm, threshold: u8) -> GrayImage {
+    match algorithm {
+        Algorithm::FloydSteinberg => diffuse(
+            image,
+            threshold,
+            &[
+                (1, 0, 7.0 / 16.0),
+                (-1, 1, 3.0 / 16.0),
+                (0, 1, 5.0 / 16.0),
+                (1, 1, 1.0 / 16.0),
+            ],
+        ),
+        // Only 3/4 of the error is spread, which keeps highlights and shadows clean
+        Algorithm::Atkinson => diffuse(
+            image,
+            threshold,
+            &[
+                (1, 0, 1.0 / 8.0),
+                (2, 0, 1.0 / 8.0),
+                (-1, 1, 1.0 / 8.0),
+                (0, 1, 1.0 / 8.0),
+                (1, 1, 1.0 / 8.0),
+                (0, 2, 1.0 / 8.0),
+            ],
+        ),
+        Algorithm::Bayer => GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            let rank = BAYER[(y % 8) as usize][(x % 8) as usize];
+            // Shift the matrix so that mid gray matches the threshold
+            let offset = (f32::from(rank) + 0.5) / 64.0 * 255.0 - 127.5;
+            let value = f32::from(image.get_pixel(x, y).0[0]);
+            bilevel(value >= f32::from(threshold) + offset)
+        }),
+        Algorithm::Threshold => GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            bilevel(image.get_pixel(x, y).0[0] >= threshold)
+        }),
+    }
+}
+
+/// Encodes a bitmap as PNG, the format accepted by
+/// [`crate::printer::Command::BitImageFromBytesWithWidth`]
+pub fn encode_png(image: GrayImage) -> Result<Vec<u8>, image::ImageError> {
+    let mut png = Vec::new();
+    DynamicImage::ImageLuma8(image).write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+fn bilevel(white: bool) -> Luma<u8> {
+    Luma([if white { 255 } else { 0 }])
+}
+
+/// Error diffusion, spreading the quantization error of every pixel to the
+/// neighbours at the given offsets with the given weights
+fn diffuse(image: &GrayImage, threshold: u8, kernel: &[(i64, i64, f32)]) -> GrayImage {
+    let (width, height) = (i64::from(image.width()), i64::from(image.height()));
+    let mut values: Vec<f32> = image.pixels().map(|pixel| f32::from(pixel.0[0])).collect();
+
+    for y in 0..height {
+        for x in 0..width {
+            let index = (y * width + x) as usize;
+            let old = values[index];
+            let new = if old >= f32::from(threshold) {
+                255.0
+            } else {
+                0.0
+            };
+            values[index] = new;
+
+            let error = old - new;
+            for (dx, dy, weight) in kernel {
+                let (nx, ny) = (x + dx, y + dy);
+                if (0..width).contains(&nx) && ny < height {
+                    values[(ny * width + nx) as usize] += error * weight;
+                }
+            }
+        }
+    }
+
+    GrayImage::from_fn(image.width(), image.height(), |x, y| {
+        bilevel(values[(i64::from(y) * width + i64::from(x)) as usize] >= 128.0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn black_ratio(image: &GrayImage) -> f32 {
+        let black = image.pixels().filter(|pixel| pixel.0[0] == 0).count();
+        black as f32 / (image.width() * image.height()) as f32
+    }
+
+    #[test]
+    fn test_algorithms_preserve_tone() {
+        let gray = GrayImage::from_pixel(32, 32, Luma([64]));
+
+        for algorithm in [
+            Algorithm::FloydSteinberg,
+            Algorithm::Atkinson,
+            Algorithm::Bayer,
+        ] {
+            let bitmap = dither(&gray, algorithm, 128);
+            assert!(bitmap.pixels().all(|p| p.0[0] == 0 || p.0[0] == 255));
+            let ratio = black_ratio(&bitmap);
+            assert!(
+                (0.6..0.9).contains(&ratio),
+                "{} gave {} black pixels",
+                algorithm.name(),
+                ratio
+            );
+        }
+        assert_eq!(black_ratio(&dither(&gray, Algorithm::Threshold, 128)), 1.0);
+        assert_eq!(black_ratio(&dither(&gray, Algorithm::Threshold, 64)), 0.0);
+    }
+
+    #[test]
+    fn test_adjustments() {
+        let adjusted = |value: u8, options: Options| {
+            let mut image = GrayImage::from_pixel(1, 1, Luma([value]));
+            adjust(&mut image, &options);
+            image.get_pixel(0, 0).0[0]
+        };
+
+        assert_eq!(adjusted(100, Options::default()), 100);
+        assert_eq!(
+            adjusted(
+                100,
+                Options {
+                    brightness: 0.2,
+                    ..Options::default()
+                }
+            ),
+            151
+        );
+        assert_eq!(
+            adjusted(
+                100,
+                Options {
+                    contrast: 2.0,
+                    ..Options::default()
+                }
+            ),
+            73
+        );
+        assert!(
+            adjusted(
+                100,
+                Options {
+                    gamma: 2.0,
+                    ..Options::default()
+                }
+            ) > 100
+        );
+        assert_eq!(
+            adjusted(
+                100,
+                Options {
+                    invert: true,
+                    ..Options::default()
+                }
+            ),
+            155
+        );
+    }
+}
@@ -1,4 +1,6 @@
+pub mod codepage;
 pub mod discovery_service;
+pub mod dither;
 pub mod mini_crossword;
 pub mod mqtt;
 pub mod mqtt_service;
@@ -6,3 +8,7 @@ pub mod printer;
 pub mod program;
 pub mod registry;
 pub mod renderer;
+pub mod settings;
+pub mod svg;
+pub mod template_store;
+pub mod truetype;
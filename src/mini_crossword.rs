@@ -1,5 +1,5 @@
+use crate::{dither, svg};
 use jiff::civil::Date;
-use resvg::{tiny_skia, usvg};
 use serde::Deserialize;
 use thiserror::Error;
 
@@ -8,7 +8,7 @@ pub enum Error {
     #[error("request failed")]
     Request(#[from] reqwest::Error),
     #[error("SVG parsing failed")]
-    SVG(#[from] usvg::Error),
+    SVG(#[from] svg::Error),
     #[error("PNG parsing failed")]
     PNGEncoding,
     #[error("Ascii representation can only be rendered for 5x5 crosswords")]
@@ -22,6 +22,7 @@ const NYT_MINI_CROSSWORD_URL: &str = "https://www.nytimes.com/svc/crosswords/v6/
 pub struct MiniCrosswordOptions {
     pub dpi: u16,
     pub target_width: u32,
+    pub dither: dither::Options,
 }
 
 #[derive(Debug, Deserialize)]
@@ -166,8 +167,6 @@ impl Puzzle {
             // Cell separator (7 chars)
             if is_black && above_black {
                 line.push_str("███████");
-            } else if is_black || above_black {
-                line.push_str("───────");
             } else {
                 line.push_str("───────");
             }
@@ -247,24 +246,9 @@ async fn fetch_mini_crossword() -> Result<MiniCrossword, reqwest::Error> {
 }
 
 fn render_crossword(svg: &str, options: MiniCrosswordOptions) -> Result<Vec<u8>, Error> {
-    let mut opt = usvg::Options {
-        dpi: options.dpi as f32,
-        shape_rendering: usvg::ShapeRendering::CrispEdges,
-        ..Default::default()
-    };
-    opt.fontdb_mut().load_system_fonts();
-    let svg = usvg::Tree::from_str(&svg, &opt).map_err(Error::SVG)?;
-    let size = svg.size();
-
-    let scale = options.target_width as f32 / svg.size().width();
-    let canvas_size = usvg::Size::from_wh(options.target_width as f32, size.height() * scale)
-        .unwrap()
-        .to_int_size();
-    let trans = usvg::Transform::from_scale(scale, scale);
-
-    let mut buf = tiny_skia::Pixmap::new(canvas_size.width(), canvas_size.height()).unwrap();
-    resvg::render(&svg, trans, &mut buf.as_mut());
-    buf.encode_png().map_err(|_| Error::PNGEncoding)
+    let pixmap = svg::rasterize(svg, options.dpi, options.target_width)?;
+    let bitmap = dither::process(&dither::grayscale_pixmap(&pixmap), &options.dither);
+    dither::encode_png(bitmap).map_err(|_| Error::PNGEncoding)
 }
 
 pub async fn get(options: MiniCrosswordOptions) -> Result<Crossword, Error> {
@@ -275,7 +259,7 @@ pub async fn get(options: MiniCrosswordOptions) -> Result<Crossword, Error> {
 
     Ok(Crossword {
         image: png,
-        puzzle: puzzle,
+        puzzle,
         constructors: mini.constructors,
         publication_date: mini.publication_date,
     })
@@ -1,15 +1,17 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 pub enum Domain {
     #[default]
     Notify,
+    Image,
 }
 
-impl ToString for Domain {
-    fn to_string(&self) -> String {
-        String::from(match self {
+impl std::fmt::Display for Domain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
             Domain::Notify => "notify",
+            Domain::Image => "image",
         })
     }
 }
@@ -29,7 +31,12 @@ pub struct AvailabilityEntry {
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Configuration {
     name: String,
-    command_topic: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command_topic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    image_topic: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
     availability: Vec<AvailabilityEntry>,
     availability_mode: String,
     unique_id: String,
@@ -39,10 +46,14 @@ pub struct Configuration {
 }
 
 impl Configuration {
+    /// Configuration of an entity, `topic` being the topic notifications are
+    /// sent to for notify entities and the topic of the PNG image for image
+    /// entities
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         domain: Domain,
         name: &str,
-        command_topic: &str,
+        topic: &str,
         service_availability_topic: &str,
         printer_availability_topic: &str,
         unique_id: &str,
@@ -52,7 +63,9 @@ impl Configuration {
     ) -> Configuration {
         Configuration {
             name: String::from(name),
-            command_topic: String::from(command_topic),
+            command_topic: (domain == Domain::Notify).then(|| String::from(topic)),
+            image_topic: (domain == Domain::Image).then(|| String::from(topic)),
+            content_type: (domain == Domain::Image).then(|| String::from("image/png")),
             availability: vec![
                 AvailabilityEntry {
                     topic: String::from(service_availability_topic),
@@ -75,8 +88,37 @@ impl Configuration {
     pub fn configuration_topic(&self) -> String {
         format!(
             "homeassistant/{}/{}/config",
-            self._domain.to_string(),
+            self._domain,
             self.unique_id
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_configuration() {
+        let configuration = Configuration::new(
+            Domain::Image,
+            "Last receipt",
+            "escpos/kitchen/last_receipt",
+            "escpos/available",
+            "escpos/kitchen/available",
+            "kitchen_last_receipt",
+            "kitchen",
+            "Kitchen",
+            "TM-T20II",
+        );
+        assert_eq!(
+            configuration.configuration_topic(),
+            "homeassistant/image/kitchen_last_receipt/config"
+        );
+
+        let payload = serde_json::to_value(&configuration).unwrap();
+        assert_eq!(payload["image_topic"], "escpos/kitchen/last_receipt");
+        assert_eq!(payload["content_type"], "image/png");
+        assert!(payload.get("command_topic").is_none());
+    }
+}
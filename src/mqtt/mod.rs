@@ -1,3 +1,4 @@
 pub mod homeassistant;
+pub mod print_job;
 pub mod string_serializer;
 pub mod topics;
@@ -0,0 +1,342 @@
+use std::borrow::Cow;
+
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::printer;
+use crate::program::documentation;
+use crate::program::template::Variables;
+use crate::program::{Command, Diagnostic, Program};
+use crate::renderer::{self, IncludeError};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid job envelope: {0}")]
+    Envelope(#[from] serde_json::Error),
+    #[error("parse error: {0}")]
+    Parse(Diagnostic),
+    #[error("include error: {0}")]
+    Include(#[from] IncludeError),
+    #[error("{0}")]
+    Rejected(#[from] renderer::Rejected),
+    #[error("render error: {0}")]
+    Render(#[from] renderer::Error),
+    #[error("printer '{0}' not found")]
+    PrinterNotFound(String),
+    #[error("failed to print: {0}")]
+    Print(#[from] printer::Error),
+}
+
+/// Payload published to `escpos/{printer}/error` when a job is rejected or fails
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct JobError {
+    pub job_id: Option<String>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostic: Option<Diagnostic>,
+}
+
+impl JobError {
+    pub fn new(job_id: Option<String>, error: &Error) -> Self {
+        JobError {
+            job_id,
+            message: error.to_string(),
+            diagnostic: match error {
+                Error::Parse(diagnostic) => Some(diagnostic.clone()),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// Priority hint for a print job, only logged: jobs are printed in the order they arrive
+#[derive(Debug, Default, Deserialize, JsonSchema, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// The program of a print job
+#[derive(Debug, PartialEq, Clone)]
+pub enum JobProgram {
+    /// DSL source
+    Source(String),
+    /// A program sent as a JSON array of commands
+    Commands(Program),
+}
+
+impl<'de> Deserialize<'de> for JobProgram {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Decoded by hand to report why commands are invalid, which an
+        // untagged enum would hide
+        match Value::deserialize(deserializer)? {
+            Value::String(source) => Ok(JobProgram::Source(source)),
+            commands => serde_json::from_value(commands)
+                .map(JobProgram::Commands)
+                .map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+impl JsonSchema for JobProgram {
+    fn schema_name() -> Cow<'static, str> {
+        Cow::Borrowed("JobProgram")
+    }
+
+    fn json_schema(generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [
+                {
+                    "description": "Program in the escpos2mqtt DSL",
+                    "type": "string"
+                },
+                generator.subschema_for::<Program>()
+            ]
+        })
+    }
+}
+
+/// A print job received on the `escpos/{printer}/print` topic
+///
+/// The payload is either a plain DSL program, a JSON array of commands or a
+/// JSON envelope such as
+/// `{"program": "...", "variables": {...}, "copies": 2, "job_id": "...", "priority": "high"}`
+/// where the program may also be an array of commands.
+#[derive(Debug, Deserialize, JsonSchema, PartialEq, Clone)]
+pub struct PrintJob {
+    pub program: JobProgram,
+    #[serde(default)]
+    pub variables: Option<Variables>,
+    #[serde(default = "default_copies", deserialize_with = "deserialize_copies")]
+    #[schemars(range(min = 1))]
+    pub copies: u8,
+    #[serde(default)]
+    pub job_id: Option<String>,
+    /// Only reported in the logs, jobs are not reordered by priority
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+fn default_copies() -> u8 {
+    1
+}
+
+/// Rejects jobs of no copies, which would silently print nothing
+fn deserialize_copies<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    match u8::deserialize(deserializer)? {
+        0 => Err(serde::de::Error::custom("copies must be at least 1")),
+        copies => Ok(copies),
+    }
+}
+
+impl PrintJob {
+    /// JSON Schema of the JSON payloads of print jobs
+    pub fn json_schema() -> Schema {
+        /// A print job sent as JSON, published to `escpos/{printer}/print`
+        #[derive(JsonSchema)]
+        #[serde(untagged)]
+        #[allow(dead_code)]
+        enum Payload {
+            Commands(Program),
+            Envelope(PrintJob),
+        }
+
+        let mut schema = schemars::schema_for!(Payload);
+        schema.insert(String::from("title"), Value::from("escpos2mqtt print job"));
+        schema
+    }
+
+    /// Parse a print topic payload, falling back to a plain DSL program if it is
+    /// not a JSON envelope or array
+    pub fn from_payload(payload: &str) -> Result<PrintJob, serde_json::Error> {
+        let trimmed = payload.trim_start();
+        let program = if trimmed.starts_with('{') {
+            return serde_json::from_str(payload);
+        } else if trimmed.starts_with('[') {
+            JobProgram::Commands(serde_json::from_str(payload)?)
+        } else {
+            JobProgram::Source(payload.to_string())
+        };
+        Ok(PrintJob {
+            program,
+            variables: None,
+            copies: default_copies(),
+            job_id: None,
+            priority: Priority::default(),
+        })
+    }
+
+    /// Parse the program and evaluate its blocks and template variables
+    ///
+    /// Placeholders are only expanded if the job supplies variables. Programs
+    /// sent as JSON commands are used as they are.
+    pub fn compile(&self) -> Result<Program, Diagnostic> {
+        match &self.program {
+            JobProgram::Source(source) => Program::compile(source, self.variables()),
+            JobProgram::Commands(program) => Ok(program.clone()),
+        }
+    }
+
+    /// Variables supplied with the job, if any
+    pub fn variables(&self) -> Option<&Variables> {
+        self.variables.as_ref()
+    }
+}
+
+/// Generate a JSON Schema of print jobs sent as JSON
+///
+/// The schema is derived from the serde representation of programs. Commands
+/// are described like the DSL command of the same name, with the examples of
+/// the DSL commands converted to JSON.
+pub fn generate_json_schema() -> String {
+    let commands = documentation::all_commands();
+    let mut schema = Value::from(PrintJob::json_schema());
+
+    let mut examples: Vec<(String, Value)> = Vec::new();
+    for example in commands.iter().flat_map(|cmd| &cmd.examples) {
+        if let Ok(("", command)) = Command::parse(example) {
+            let value = serde_json::to_value(&command).unwrap();
+            if let Some(key) = value.as_object().and_then(single_key) {
+                examples.push((key.to_string(), value));
+            }
+        }
+    }
+
+    let definitions = schema.get_mut("$defs").and_then(Value::as_object_mut);
+    for definition in definitions.into_iter().flat_map(|defs| defs.values_mut()) {
+        for key in ["anyOf", "oneOf"] {
+            let Some(variants) = definition.get_mut(key).and_then(Value::as_array_mut) else {
+                continue;
+            };
+            for variant in variants.iter_mut().filter_map(Value::as_object_mut) {
+                // Commands with arguments are objects with the command as their only key
+                let Some(name) = variant
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .and_then(single_key)
+                    .map(str::to_string)
+                else {
+                    continue;
+                };
+                if let Some(doc) = commands.iter().find(|cmd| cmd.name == name) {
+                    variant.insert(String::from("description"), doc.description.into());
+                }
+                let values: Vec<Value> = examples
+                    .iter()
+                    .filter(|(key, _)| *key == name)
+                    .map(|(_, value)| value.clone())
+                    .collect();
+                if !values.is_empty() {
+                    variant.insert(String::from("examples"), values.into());
+                }
+            }
+        }
+    }
+
+    serde_json::to_string_pretty(&schema).unwrap() + "\n"
+}
+
+/// The key of an object with a single key
+fn single_key(object: &serde_json::Map<String, Value>) -> Option<&str> {
+    match object.keys().collect::<Vec<_>>()[..] {
+        [key] => Some(key),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_payload() {
+        let job = PrintJob::from_payload("writeln \"Hello\"\ncut").unwrap();
+
+        assert_eq!(
+            job.program,
+            JobProgram::Source(String::from("writeln \"Hello\"\ncut"))
+        );
+        assert_eq!(job.copies, 1);
+        assert_eq!(job.job_id, None);
+        assert_eq!(job.priority, Priority::Normal);
+        assert_eq!(job.compile().unwrap().commands.len(), 2);
+    }
+
+    #[test]
+    fn test_envelope_payload() {
+        let job = PrintJob::from_payload(
+            r#"{
+                "program": "writeln \"Hello {{name}}\"",
+                "variables": {"name": "Ada"},
+                "copies": 2,
+                "job_id": "order-42",
+                "priority": "high"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(job.copies, 2);
+        assert_eq!(job.job_id.as_deref(), Some("order-42"));
+        assert_eq!(job.priority, Priority::High);
+        assert_eq!(
+            job.compile().unwrap().commands,
+            vec![crate::program::Command::Raw(printer::Command::Write(
+                String::from("Hello Ada\n")
+            ))]
+        );
+    }
+
+    #[test]
+    fn test_job_error_payload() {
+        let diagnostic = crate::program::Program::parse_complete("bol true").unwrap_err();
+        let error = JobError::new(Some(String::from("order-42")), &Error::Parse(diagnostic));
+
+        let payload = serde_json::to_value(&error).unwrap();
+        assert_eq!(payload["job_id"], "order-42");
+        assert_eq!(payload["diagnostic"]["line"], 1);
+        assert_eq!(payload["diagnostic"]["suggestion"], "bold");
+    }
+
+    #[test]
+    fn test_json_commands_payload() {
+        let job = PrintJob::from_payload(r#"[{"writeln": "x"}]"#);
+        assert!(job.is_err());
+
+        let job = PrintJob::from_payload(r#" [{"write": "Hello\n"}, "cut"]"#).unwrap();
+        assert_eq!(job.copies, 1);
+        assert_eq!(
+            job.compile().unwrap(),
+            Program::parse_complete("writeln \"Hello\"\ncut").unwrap()
+        );
+
+        let job = PrintJob::from_payload(
+            r#"{"program": [{"bold": true}], "copies": 2, "variables": {"x": 1}}"#,
+        )
+        .unwrap();
+        assert_eq!(job.copies, 2);
+        assert_eq!(job.compile().unwrap().commands.len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_envelope() {
+        assert!(PrintJob::from_payload("{\"copies\": 2}").is_err());
+        let error = PrintJob::from_payload(r#"{"program": "cut", "copies": 0}"#).unwrap_err();
+        assert!(error.to_string().contains("copies must be at least 1"));
+    }
+
+    #[test]
+    fn test_published_schema() {
+        // Registers the documentation of all commands
+        let _ = Command::parse("");
+        let published = include_str!("../../book/src/job.schema.json");
+        assert!(
+            generate_json_schema() == published,
+            "book/src/job.schema.json is outdated, update it with `generate_docs jsonschema`"
+        );
+    }
+}
@@ -1,6 +1,8 @@
 use mqtt_typed_client::MessageSerializer;
 
 use crate::mqtt::homeassistant::Configuration;
+use crate::mqtt::print_job::JobError;
+use crate::renderer::Png;
 
 #[derive(Clone, Default)]
 pub struct JsonSerializer;
@@ -62,3 +64,29 @@ impl MessageSerializer<Option<Configuration>> for JsonSerializer {
         serde_json::from_slice(bytes)
     }
 }
+
+impl MessageSerializer<JobError> for JsonSerializer {
+    type SerializeError = serde_json::Error;
+    type DeserializeError = serde_json::Error;
+
+    fn serialize(&self, data: &JobError) -> Result<Vec<u8>, Self::SerializeError> {
+        serde_json::to_vec(data)
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<JobError, Self::DeserializeError> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+impl MessageSerializer<Png> for JsonSerializer {
+    type SerializeError = std::convert::Infallible;
+    type DeserializeError = std::convert::Infallible;
+
+    fn serialize(&self, data: &Png) -> Result<Vec<u8>, Self::SerializeError> {
+        Ok(data.0.clone())
+    }
+
+    fn deserialize(&self, bytes: &[u8]) -> Result<Png, Self::DeserializeError> {
+        Ok(Png(bytes.to_vec()))
+    }
+}
@@ -18,6 +18,49 @@ pub struct PrintJobTopic {
     pub payload: String,
 }
 
+#[mqtt_topic("escpos/{printer}/error")]
+#[derive(Debug)]
+pub struct PrintErrorTopic {
+    pub printer: String,
+    pub payload: crate::mqtt::print_job::JobError,
+}
+
+#[mqtt_topic("escpos/{printer}/last_receipt")]
+#[derive(Debug)]
+pub struct LastReceiptTopic {
+    pub printer: String,
+    pub payload: crate::renderer::Png,
+}
+
+#[mqtt_topic("escpos/{printer}/preview")]
+#[derive(Debug)]
+pub struct PreviewJobTopic {
+    pub printer: String,
+    pub payload: String,
+}
+
+#[mqtt_topic("escpos/{printer}/preview/image")]
+#[derive(Debug)]
+pub struct PreviewImageTopic {
+    pub printer: String,
+    pub payload: crate::renderer::Png,
+}
+
+#[mqtt_topic("escpos/{printer}/preview/image/{job_id}")]
+#[derive(Debug)]
+pub struct JobPreviewImageTopic {
+    pub printer: String,
+    pub job_id: String,
+    pub payload: crate::renderer::Png,
+}
+
+#[mqtt_topic("escpos/templates/{name}")]
+#[derive(Debug)]
+pub struct TemplateTopic {
+    pub name: String,
+    pub payload: String,
+}
+
 #[mqtt_topic("homeassistant/{domain}/{id}/config")]
 #[derive(Debug)]
 pub struct HomeAssistantDiscoveryTopic {
@@ -1,18 +1,32 @@
 use crate::mqtt::homeassistant;
+use crate::mqtt::print_job::{self, JobError, PrintJob};
+use crate::mqtt::topics::print_error_topic::PrintErrorTopicExt;
 use crate::mqtt::topics::home_assistant_discovery_topic::HomeAssistantDiscoveryTopicExt;
+use crate::mqtt::topics::job_preview_image_topic::JobPreviewImageTopicExt;
+use crate::mqtt::topics::last_receipt_topic::LastReceiptTopicExt;
+use crate::mqtt::topics::preview_image_topic::PreviewImageTopicExt;
+use crate::mqtt::topics::preview_job_topic::PreviewJobTopicExt;
 use crate::mqtt::topics::printer_available_topic::PrinterAvailableTopicExt;
 use crate::mqtt::topics::print_job_topic::PrintJobTopicExt;
-use crate::program;
+use crate::mqtt::topics::template_topic::TemplateTopicExt;
+use crate::printer::{self, Printer};
 use crate::registry::PrinterRegistry;
 use crate::registry::RegistryEvent;
-use crate::renderer;
+use crate::renderer::{self, Png};
+use crate::settings::Settings;
+use crate::template_store::TemplateStore;
 use mqtt_typed_client::{QoS, MqttClient};
 use tokio::sync::broadcast;
 
+/// A job rendered for a printer, along with the printer and its profile
+type RenderedJob = (Printer, &'static escpos_db::Profile<'static>, printer::Program);
+
 pub struct MqttService {
     registry: PrinterRegistry,
+    templates: TemplateStore,
     client: MqttClient<crate::mqtt::string_serializer::JsonSerializer>,
     registry_event_rx: broadcast::Receiver<RegistryEvent>,
+    settings: Settings,
 }
 
 impl MqttService {
@@ -20,11 +34,14 @@ impl MqttService {
         registry: PrinterRegistry,
         client: MqttClient<crate::mqtt::string_serializer::JsonSerializer>,
         registry_event_rx: broadcast::Receiver<RegistryEvent>,
+        settings: Settings,
     ) -> Self {
         Self {
             registry,
+            templates: TemplateStore::new(),
             client,
             registry_event_rx,
+            settings,
         }
     }
 
@@ -36,6 +53,12 @@ impl MqttService {
         let topic_client = self.client.print_job_topic();
         let mut subscriber = topic_client.subscribe().await?;
 
+        // Subscribe to preview requests
+        let mut preview_subscriber = self.client.preview_job_topic().subscribe().await?;
+
+        // Subscribe to retained named templates
+        let mut template_subscriber = self.client.template_topic().subscribe().await?;
+
         loop {
             tokio::select! {
                 // Handle incoming print jobs
@@ -54,6 +77,22 @@ impl MqttService {
                     }
                 }
 
+                // Render previews without printing
+                Some(result) = preview_subscriber.receive() => {
+                    match result {
+                        Ok(topic) => self.handle_preview_job(&topic.printer, &topic.payload).await,
+                        Err(e) => log::error!("Could not parse preview request: {:?}", e),
+                    }
+                }
+
+                // Store named templates
+                Some(result) = template_subscriber.receive() => {
+                    match result {
+                        Ok(topic) => self.templates.set(&topic.name, &topic.payload).await,
+                        Err(e) => log::error!("Could not parse template message: {:?}", e),
+                    }
+                }
+
                 // Handle registry events
                 Ok(event) = self.registry_event_rx.recv() => {
                     self.handle_registry_event(event).await;
@@ -98,55 +137,200 @@ impl MqttService {
         }
     }
 
-    /// Handle a single print job
+    /// Handle a single print job, publishing any failure to the printer's error topic
     async fn handle_print_job(&self, printer_id: &str, payload: &str) {
         log::info!("Received print job for printer: {}", printer_id);
 
-        // Look up printer in registry
-        let printer_result = self.registry.get_printer_with_profile(printer_id).await;
+        let job = match PrintJob::from_payload(payload) {
+            Ok(job) => job,
+            Err(err) => {
+                let error = print_job::Error::Envelope(err);
+                self.report_job_error(printer_id, None, error).await;
+                return;
+            }
+        };
 
-        if let Some((mut printer, profile)) = printer_result {
-            // Parse the program
-            let parsed = program::Program::parse(payload);
-
-            match parsed {
-                Ok((remains, program)) => {
-                    if !remains.is_empty() {
-                        log::error!(
-                            "Could not fully parse program. Failed to parse from: {}",
-                            remains
-                        );
-                    } else {
-                        log::info!("Printing program {:?}", program);
-
-                        // Render and print
-                        match printer
-                            .print(renderer::render(program, profile).await)
-                            .await
-                        {
-                            Ok(_) => {
-                                log::info!("Successfully printed to printer: {}", printer_id);
-                            }
-                            Err(err) => {
-                                log::error!("Failed to print to {}: {}", printer_id, err);
-                            }
-                        }
-                    }
-                }
-                Err(err) => {
-                    log::error!("Could not parse program: {}", err);
+        if let Err(err) = self.print_job(printer_id, &job).await {
+            self.report_job_error(printer_id, job.job_id.clone(), err).await;
+        }
+    }
+
+    /// Handle a preview request, publishing the image of the job to
+    /// `escpos/{printer}/preview/image` instead of printing it, and to
+    /// `escpos/{printer}/preview/image/{job_id}` if the job has an id
+    async fn handle_preview_job(&self, printer_id: &str, payload: &str) {
+        log::info!("Received preview request for printer: {}", printer_id);
+
+        let job = match PrintJob::from_payload(payload) {
+            Ok(job) => job,
+            Err(err) => {
+                let error = print_job::Error::Envelope(err);
+                self.report_job_error(printer_id, None, error).await;
+                return;
+            }
+        };
+
+        match self.render_job(printer_id, &job).await {
+            Ok((_, profile, rendered)) => {
+                let Some(preview) = draw_preview(rendered, profile).await else {
+                    return;
+                };
+                if let Err(err) = self
+                    .publish_preview(printer_id, job.job_id.as_deref(), &preview)
+                    .await
+                {
+                    log::error!("Failed to publish preview: {}", err);
                 }
             }
-        } else {
+            Err(err) => self.report_job_error(printer_id, job.job_id.clone(), err).await,
+        }
+    }
+
+    /// Print every copy of a job and publish its preview as the last receipt
+    async fn print_job(&self, printer_id: &str, job: &PrintJob) -> Result<(), print_job::Error> {
+        let job_id = job.job_id.as_deref().unwrap_or("-");
+        let (mut printer, profile, rendered) = self.render_job(printer_id, job).await?;
+
+        for copy in 1..=job.copies {
+            printer.print(rendered.clone()).await?;
+            log::info!(
+                "Successfully printed copy {}/{} of job {} to printer: {}",
+                copy,
+                job.copies,
+                job_id,
+                printer_id
+            );
+        }
+
+        if let Some(preview) = draw_preview(rendered, profile).await {
+            if let Err(err) = self.publish_last_receipt(printer_id, &preview).await {
+                log::error!("Failed to publish last receipt: {}", err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Expand, parse, validate and render a job for a printer
+    async fn render_job(
+        &self,
+        printer_id: &str,
+        job: &PrintJob,
+    ) -> Result<RenderedJob, print_job::Error> {
+        let job_id = job.job_id.as_deref().unwrap_or("-");
+
+        // Parse the program, evaluating blocks and template variables
+        let program = job.compile().map_err(print_job::Error::Parse)?;
+        let templates = self.templates.snapshot().await;
+        let program = renderer::resolve_includes(program, &templates, job.variables())?;
+
+        // Look up printer in registry
+        let printer_result = self.registry.get_printer_with_profile(printer_id).await;
+        let Some((printer, profile)) = printer_result else {
             log::error!(
                 "Printer '{}' not found in registry. Available printers: {:?}",
                 printer_id,
                 self.registry.list_printers().await
             );
+            return Err(print_job::Error::PrinterNotFound(printer_id.to_string()));
+        };
+
+        log::info!(
+            "Rendering job {} ({} copies, {:?} priority): {:?}",
+            job_id,
+            job.copies,
+            job.priority,
+            program
+        );
+
+        // Check the program against the printer and adapt or reject it
+        let settings = self.settings.printer(printer_id);
+        let issues = renderer::validate(&program, profile);
+        for issue in &issues {
+            log::warn!("Job {} on printer {}: {}", job_id, printer_id, issue);
         }
+        let program = settings.validation.apply(program, issues)?;
+
+        // Render once for every copy
+        let rendered = renderer::render(program, profile, &settings).await?;
+
+        Ok((printer, profile, rendered))
+    }
+
+    /// Log a failed job and publish it to `escpos/{printer}/error`
+    async fn report_job_error(
+        &self,
+        printer_id: &str,
+        job_id: Option<String>,
+        error: print_job::Error,
+    ) {
+        log::error!(
+            "Print job {} for {} failed: {}",
+            job_id.as_deref().unwrap_or("-"),
+            printer_id,
+            error
+        );
+
+        let message = JobError::new(job_id, &error);
+        let result = match self.client.print_error_topic().get_publisher(printer_id) {
+            Ok(publisher) => publisher
+                .with_qos(QoS::AtLeastOnce)
+                .publish(&message)
+                .await
+                .map_err(|err| err.to_string()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        if let Err(err) = result {
+            log::error!("Failed to publish job error: {}", err);
+        }
+    }
+
+    /// Publish the retained preview of the last printed job
+    async fn publish_last_receipt(
+        &self,
+        printer_id: &str,
+        preview: &Png,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.client
+            .last_receipt_topic()
+            .get_publisher(printer_id)?
+            .with_qos(QoS::AtLeastOnce)
+            .publish_retain(preview)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Publish the preview of a job that was not printed, also under its job
+    /// id so that clients can tell concurrent previews apart
+    async fn publish_preview(
+        &self,
+        printer_id: &str,
+        job_id: Option<&str>,
+        preview: &Png,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(job_id) = job_id {
+            self.client
+                .job_preview_image_topic()
+                .get_publisher(printer_id, job_id)?
+                .with_qos(QoS::AtLeastOnce)
+                .publish(preview)
+                .await?;
+        }
+
+        self.client
+            .preview_image_topic()
+            .get_publisher(printer_id)?
+            .with_qos(QoS::AtLeastOnce)
+            .publish(preview)
+            .await?;
+
+        Ok(())
     }
 
-    /// Publish Home Assistant discovery message for a printer
+    /// Publish Home Assistant discovery messages for a printer: a notify entity
+    /// to print and image entities of the last receipt and of previews
     async fn publish_discovery(
         &self,
         printer_id: &str,
@@ -154,29 +338,49 @@ impl MqttService {
         printer_description: &str,
         model_name: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let message = homeassistant::Configuration::new(
-            homeassistant::Domain::Notify,
-            "Receipt",
-            &format!("escpos/{}/print", printer_id),
-            "escpos/available",
-            &format!("escpos/{}/available", printer_id),
-            printer_id,
-            printer_id,
-            printer_name,
-            &format!("{} - {}", model_name, printer_description),
-        );
+        let entities = [
+            (
+                homeassistant::Domain::Notify,
+                "Receipt",
+                format!("escpos/{}/print", printer_id),
+                printer_id.to_string(),
+            ),
+            (
+                homeassistant::Domain::Image,
+                "Last receipt",
+                format!("escpos/{}/last_receipt", printer_id),
+                format!("{}_last_receipt", printer_id),
+            ),
+            (
+                homeassistant::Domain::Image,
+                "Receipt preview",
+                format!("escpos/{}/preview/image", printer_id),
+                format!("{}_preview", printer_id),
+            ),
+        ];
 
-        self.client
-            .home_assistant_discovery_topic()
-            .get_publisher(
-                &homeassistant::Domain::Notify.to_string(),
+        for (domain, name, topic, unique_id) in entities {
+            let message = homeassistant::Configuration::new(
+                domain,
+                name,
+                &topic,
+                "escpos/available",
+                &format!("escpos/{}/available", printer_id),
+                &unique_id,
                 printer_id,
-            )?
-            .with_qos(QoS::AtLeastOnce)
-            .publish_retain(
-                &Some(message),
-            )
-            .await?;
+                printer_name,
+                &format!("{} - {}", model_name, printer_description),
+            );
+
+            self.client
+                .home_assistant_discovery_topic()
+                .get_publisher(&domain.to_string(), &unique_id)?
+                .with_qos(QoS::AtLeastOnce)
+                .publish_retain(
+                    &Some(message),
+                )
+                .await?;
+        }
 
         Ok(())
     }
@@ -198,3 +402,18 @@ impl MqttService {
     }
 
 }
+
+/// Draw the preview of a rendered job on the blocking thread pool, so that
+/// large receipts don't hold up the jobs of other printers
+async fn draw_preview(
+    rendered: printer::Program,
+    profile: &'static escpos_db::Profile<'static>,
+) -> Option<Png> {
+    match tokio::task::spawn_blocking(move || renderer::preview(&rendered, profile)).await {
+        Ok(preview) => Some(preview),
+        Err(err) => {
+            log::error!("Failed to draw preview: {}", err);
+            None
+        }
+    }
+}
@@ -0,0 +1,549 @@
+//! Validation and ESC/POS encoding of 1D barcodes
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("{symbology} does not accept '{character}'")]
+    InvalidCharacter {
+        symbology: &'static str,
+        character: char,
+    },
+    #[error("{symbology} needs {expected}, got {length} characters")]
+    InvalidLength {
+        symbology: &'static str,
+        expected: &'static str,
+        length: usize,
+    },
+    #[error("wrong check digit {found}, expected {expected}")]
+    CheckDigit { expected: char, found: char },
+    #[error("UPC-E number system must be 0 or 1")]
+    NumberSystem,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Symbology {
+    UpcA,
+    UpcE,
+    Ean13,
+    Ean8,
+    Code39,
+    Itf,
+    Codabar,
+    Code128,
+}
+
+impl Symbology {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Symbology::UpcA => "UPC-A",
+            Symbology::UpcE => "UPC-E",
+            Symbology::Ean13 => "EAN-13",
+            Symbology::Ean8 => "EAN-8",
+            Symbology::Code39 => "Code 39",
+            Symbology::Itf => "ITF",
+            Symbology::Codabar => "Codabar",
+            Symbology::Code128 => "Code 128",
+        }
+    }
+
+    /// Barcode system `m` of `GS k` (function B)
+    pub(crate) fn system(&self) -> u8 {
+        match self {
+            Symbology::UpcA => 65,
+            Symbology::UpcE => 66,
+            Symbology::Ean13 => 67,
+            Symbology::Ean8 => 68,
+            Symbology::Code39 => 69,
+            Symbology::Itf => 70,
+            Symbology::Codabar => 71,
+            Symbology::Code128 => 73,
+        }
+    }
+}
+
+/// Where the human readable interpretation (HRI) of the data is printed
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HriPosition {
+    None,
+    Above,
+    #[default]
+    Below,
+    Both,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HriFont {
+    #[default]
+    A,
+    B,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct BarcodeOptions {
+    /// Width of a module (the narrowest bar) in dots, 1-6
+    #[serde(deserialize_with = "super::in_range::<_, _, 1, 6>")]
+    #[schemars(range(min = 1, max = 6))]
+    pub width: u8,
+    /// Height in dots
+    #[serde(deserialize_with = "super::in_range::<_, _, 1, 255>")]
+    #[schemars(range(min = 1, max = 255))]
+    pub height: u8,
+    pub hri: HriPosition,
+    pub hri_font: HriFont,
+}
+
+impl Default for BarcodeOptions {
+    fn default() -> Self {
+        BarcodeOptions {
+            width: 3,
+            height: 102,
+            hri: HriPosition::default(),
+            hri_font: HriFont::default(),
+        }
+    }
+}
+
+/// A barcode with validated data
+// Serialized with the data as written in programs, and validated again when
+// deserialized
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(try_from = "BarcodeData", into = "BarcodeData")]
+#[schemars(with = "BarcodeData")]
+pub struct Barcode {
+    pub symbology: Symbology,
+    /// Validated data, including the check digit for EAN and UPC codes
+    pub data: String,
+    pub options: BarcodeOptions,
+}
+
+/// Serialized form of a [`Barcode`]
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[schemars(rename = "Barcode")]
+struct BarcodeData {
+    symbology: Symbology,
+    /// Data as in the DSL, EAN and UPC check digits are optional
+    data: String,
+    #[serde(default)]
+    options: BarcodeOptions,
+}
+
+impl TryFrom<BarcodeData> for Barcode {
+    type Error = Error;
+
+    fn try_from(barcode: BarcodeData) -> Result<Self, Self::Error> {
+        Barcode::new(barcode.symbology, &barcode.data, barcode.options)
+    }
+}
+
+impl From<Barcode> for BarcodeData {
+    fn from(barcode: Barcode) -> Self {
+        BarcodeData {
+            data: barcode.program_data(),
+            symbology: barcode.symbology,
+            options: barcode.options,
+        }
+    }
+}
+
+impl Barcode {
+    /// Validates `data` for the symbology
+    ///
+    /// EAN and UPC check digits are appended if omitted and verified otherwise.
+    /// UPC-E codes are accepted with 6 digits (number system 0), 7 digits or 8
+    /// digits including the check digit. Codabar data without start and stop
+    /// characters is enclosed in `A`.
+    pub fn new(
+        symbology: Symbology,
+        data: &str,
+        options: BarcodeOptions,
+    ) -> Result<Barcode, Error> {
+        let data = match symbology {
+            Symbology::Ean13 => gtin(symbology, data, 12)?,
+            Symbology::Ean8 => gtin(symbology, data, 7)?,
+            Symbology::UpcA => gtin(symbology, data, 11)?,
+            Symbology::UpcE => upc_e(data)?,
+            Symbology::Itf => {
+                digits(symbology, data)?;
+                if data.len() < 2 || !data.len().is_multiple_of(2) {
+                    return Err(Error::InvalidLength {
+                        symbology: symbology.name(),
+                        expected: "an even number of digits",
+                        length: data.len(),
+                    });
+                }
+                data.to_string()
+            }
+            Symbology::Code39 => {
+                not_empty(symbology, data)?;
+                allowed(symbology, data, |c| {
+                    c.is_ascii_digit() || c.is_ascii_uppercase() || " $%+-./".contains(c)
+                })?;
+                data.to_string()
+            }
+            Symbology::Codabar => codabar(data)?,
+            Symbology::Code128 => {
+                not_empty(symbology, data)?;
+                allowed(symbology, data, |c| c.is_ascii() && !c.is_ascii_control())?;
+                data.to_string()
+            }
+        };
+
+        let barcode = Barcode {
+            symbology,
+            data,
+            options,
+        };
+        // The length is sent as a single byte
+        if barcode.encoded_data().len() > 255 {
+            return Err(Error::InvalidLength {
+                symbology: symbology.name(),
+                expected: "at most 255 characters",
+                length: barcode.data.len(),
+            });
+        }
+        Ok(barcode)
+    }
+
+    /// ESC/POS commands setting the barcode options and printing the barcode
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let data = self.encoded_data();
+        let mut bytes = vec![
+            0x1D,
+            b'w',
+            self.options.width,
+            0x1D,
+            b'h',
+            self.options.height,
+            0x1D,
+            b'f',
+            self.options.hri_font as u8,
+            0x1D,
+            b'H',
+            self.options.hri as u8,
+            0x1D,
+            b'k',
+            self.symbology.system(),
+            data.len() as u8,
+        ];
+        bytes.extend(data);
+        bytes
+    }
+
+    /// Data as written in a program: the 8 digit form of UPC-E codes, which
+    /// are kept expanded to UPC-A, and the data of all other codes
+    pub fn program_data(&self) -> String {
+        if self.symbology != Symbology::UpcE || self.data.len() != 12 {
+            return self.data.clone();
+        }
+        let d: Vec<char> = self.data.chars().collect();
+        let (system, manufacturer, product, check) = (d[0], &d[1..6], &d[6..11], d[11]);
+        let zeros = |digits: &[char]| digits.iter().all(|c| *c == '0');
+        let code: String = if zeros(&manufacturer[3..])
+            && ('0'..='2').contains(&manufacturer[2])
+            && zeros(&product[..2])
+        {
+            [
+                manufacturer[0],
+                manufacturer[1],
+                product[2],
+                product[3],
+                product[4],
+                manufacturer[2],
+            ]
+            .iter()
+            .collect()
+        } else if zeros(&manufacturer[3..]) && zeros(&product[..3]) {
+            [
+                manufacturer[0],
+                manufacturer[1],
+                manufacturer[2],
+                product[3],
+                product[4],
+                '3',
+            ]
+            .iter()
+            .collect()
+        } else if manufacturer[4] == '0' && zeros(&product[..4]) {
+            [
+                manufacturer[0],
+                manufacturer[1],
+                manufacturer[2],
+                manufacturer[3],
+                product[4],
+                '4',
+            ]
+            .iter()
+            .collect()
+        } else if zeros(&product[..4]) && product[4] >= '5' {
+            manufacturer.iter().chain([&product[4]]).collect()
+        } else {
+            return self.data.clone();
+        };
+        format!("{}{}{}", system, code, check)
+    }
+
+    /// Data as sent to the printer. Code 128 data is prefixed with its code set:
+    /// C (digit pairs) for even numbers of digits, B otherwise.
+    fn encoded_data(&self) -> Vec<u8> {
+        if self.symbology != Symbology::Code128 {
+            return self.data.as_bytes().to_vec();
+        }
+
+        let digits = self.data.bytes().all(|b| b.is_ascii_digit());
+        if digits && self.data.len() >= 4 && self.data.len().is_multiple_of(2) {
+            let mut bytes = b"{C".to_vec();
+            bytes.extend(
+                self.data
+                    .as_bytes()
+                    .chunks(2)
+                    .map(|pair| (pair[0] - b'0') * 10 + pair[1] - b'0'),
+            );
+            bytes
+        } else {
+            let mut bytes = b"{B".to_vec();
+            for byte in self.data.bytes() {
+                // `{` starts a code set switch and has to be escaped
+                if byte == b'{' {
+                    bytes.push(b'{');
+                }
+                bytes.push(byte);
+            }
+            bytes
+        }
+    }
+}
+
+fn not_empty(symbology: Symbology, data: &str) -> Result<(), Error> {
+    if data.is_empty() {
+        return Err(Error::InvalidLength {
+            symbology: symbology.name(),
+            expected: "at least one character",
+            length: 0,
+        });
+    }
+    Ok(())
+}
+
+fn allowed(symbology: Symbology, data: &str, allowed: impl Fn(char) -> bool) -> Result<(), Error> {
+    match data.chars().find(|c| !allowed(*c)) {
+        Some(character) => Err(Error::InvalidCharacter {
+            symbology: symbology.name(),
+            character,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn digits(symbology: Symbology, data: &str) -> Result<(), Error> {
+    allowed(symbology, data, |c| c.is_ascii_digit())
+}
+
+/// Modulo 10 check digit of EAN and UPC codes
+fn check_digit(data: &str) -> char {
+    let sum: u32 = data
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, b)| u32::from(b - b'0') * if i % 2 == 0 { 3 } else { 1 })
+        .sum();
+    char::from(b'0' + ((10 - sum % 10) % 10) as u8)
+}
+
+/// Appends or verifies the check digit of a code with `length` data digits
+fn gtin(symbology: Symbology, data: &str, length: usize) -> Result<String, Error> {
+    digits(symbology, data)?;
+    if data.len() == length {
+        return Ok(format!("{}{}", data, check_digit(data)));
+    }
+    if data.len() != length + 1 {
+        return Err(Error::InvalidLength {
+            symbology: symbology.name(),
+            expected: match symbology {
+                Symbology::Ean13 => "12 or 13 digits",
+                Symbology::Ean8 => "7 or 8 digits",
+                _ => "11 or 12 digits",
+            },
+            length: data.len(),
+        });
+    }
+
+    let (code, found) = data.split_at(length);
+    let expected = check_digit(code);
+    let found = found.chars().next().unwrap_or_default();
+    if found != expected {
+        return Err(Error::CheckDigit { expected, found });
+    }
+    Ok(data.to_string())
+}
+
+/// Expands UPC-E data to the 12 digit UPC-A form printers accept most widely
+fn upc_e(data: &str) -> Result<String, Error> {
+    digits(Symbology::UpcE, data)?;
+    let (system, code, check) = match data.len() {
+        6 => ("0", data, None),
+        7 => (&data[..1], &data[1..], None),
+        8 => (&data[..1], &data[1..7], Some(&data[7..])),
+        length => {
+            return Err(Error::InvalidLength {
+                symbology: Symbology::UpcE.name(),
+                expected: "6 to 8 digits",
+                length,
+            })
+        }
+    };
+    if system != "0" && system != "1" {
+        return Err(Error::NumberSystem);
+    }
+
+    let d: Vec<char> = code.chars().collect();
+    let (manufacturer, product) = match d[5] {
+        '0'..='2' => (
+            format!("{}{}{}00", d[0], d[1], d[5]),
+            format!("00{}{}{}", d[2], d[3], d[4]),
+        ),
+        '3' => (
+            format!("{}{}{}00", d[0], d[1], d[2]),
+            format!("000{}{}", d[3], d[4]),
+        ),
+        '4' => (
+            format!("{}{}{}{}0", d[0], d[1], d[2], d[3]),
+            format!("0000{}", d[4]),
+        ),
+        _ => (code[..5].to_string(), format!("0000{}", d[5])),
+    };
+
+    let upc_a = format!(
+        "{}{}{}{}",
+        system,
+        manufacturer,
+        product,
+        check.unwrap_or("")
+    );
+    gtin(Symbology::UpcE, &upc_a, 11)
+}
+
+/// Checks Codabar data, adding start and stop characters if both are missing
+fn codabar(data: &str) -> Result<String, Error> {
+    not_empty(Symbology::Codabar, data)?;
+    let is_start_stop = |c: char| matches!(c.to_ascii_uppercase(), 'A'..='D');
+    let data = match (data.chars().next(), data.chars().last()) {
+        (Some(first), Some(last))
+            if is_start_stop(first) && is_start_stop(last) && data.len() > 1 =>
+        {
+            data.to_ascii_uppercase()
+        }
+        _ => format!("A{}A", data),
+    };
+    // Start and stop characters alone don't encode anything
+    let content = &data[1..data.len() - 1];
+    not_empty(Symbology::Codabar, content)?;
+    allowed(Symbology::Codabar, content, |c| {
+        c.is_ascii_digit() || "-$:/.+".contains(c)
+    })?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(symbology: Symbology, data: &str) -> Result<String, Error> {
+        Barcode::new(symbology, data, BarcodeOptions::default()).map(|barcode| barcode.data)
+    }
+
+    #[test]
+    fn test_check_digits() {
+        assert_eq!(
+            data(Symbology::Ean13, "400638133393"),
+            Ok(String::from("4006381333931"))
+        );
+        assert_eq!(
+            data(Symbology::Ean8, "9638507"),
+            Ok(String::from("96385074"))
+        );
+        assert_eq!(
+            data(Symbology::UpcA, "03600029145"),
+            Ok(String::from("036000291452"))
+        );
+        assert_eq!(
+            data(Symbology::Ean13, "4006381333932"),
+            Err(Error::CheckDigit {
+                expected: '1',
+                found: '2'
+            })
+        );
+        // UPC-E 0 425261 4 expands to UPC-A 0 42100 00526 4
+        assert_eq!(
+            data(Symbology::UpcE, "425261"),
+            Ok(String::from("042100005264"))
+        );
+        assert_eq!(
+            data(Symbology::UpcE, "04252614"),
+            Ok(String::from("042100005264"))
+        );
+        assert_eq!(data(Symbology::UpcE, "2425261"), Err(Error::NumberSystem));
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(data(Symbology::Itf, "12345").is_err());
+        assert!(data(Symbology::Code39, "box-12").is_err());
+        assert!(data(Symbology::Code128, &"x".repeat(254)).is_err());
+        assert_eq!(
+            data(Symbology::Codabar, "40156"),
+            Ok(String::from("A40156A"))
+        );
+        assert_eq!(
+            data(Symbology::Codabar, "b40156d"),
+            Ok(String::from("B40156D"))
+        );
+        for empty in ["", "AB"] {
+            assert_eq!(
+                data(Symbology::Codabar, empty),
+                Err(Error::InvalidLength {
+                    symbology: "Codabar",
+                    expected: "at least one character",
+                    length: 0
+                })
+            );
+        }
+        assert!(data(Symbology::Code39, "").is_err());
+        assert!(data(Symbology::Code128, "").is_err());
+        assert_eq!(
+            data(Symbology::Code128, "Box\t1"),
+            Err(Error::InvalidCharacter {
+                symbology: "Code 128",
+                character: '\t'
+            })
+        );
+    }
+
+    #[test]
+    fn test_code128_bytes() {
+        let barcode = Barcode::new(Symbology::Code128, "{A}", BarcodeOptions::default()).unwrap();
+        assert_eq!(
+            barcode.to_bytes(),
+            b"\x1dw\x03\x1dh\x66\x1df\x00\x1dH\x02\x1dk\x49\x06{B{{A}".to_vec()
+        );
+
+        let barcode =
+            Barcode::new(Symbology::Code128, "123456", BarcodeOptions::default()).unwrap();
+        assert_eq!(&barcode.to_bytes()[14..], &[73, 5, b'{', b'C', 12, 34, 56]);
+    }
+
+    #[test]
+    fn test_upc_e_program_data() {
+        // One code for each way of compressing UPC-A
+        for data in ["0425261", "0123450", "0123453", "1123454", "0123458"] {
+            let barcode = Barcode::new(Symbology::UpcE, data, BarcodeOptions::default()).unwrap();
+            let check = &barcode.data[11..];
+            assert_eq!(barcode.program_data(), format!("{}{}", data, check));
+        }
+    }
+}
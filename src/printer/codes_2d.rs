@@ -0,0 +1,304 @@
+//! Options of the 2D codes printed with `GS ( k`
+use escpos::driver::Driver;
+use escpos::errors::PrinterError;
+use escpos::utils::{
+    AztecMode, AztecOption, DataMatrixOption, DataMatrixType, Pdf417CorrectionLevel, Pdf417Option,
+    Pdf417Type, QRCodeCorrectionLevel, QRCodeModel, QRCodeOption,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+pub enum QrModel {
+    #[default]
+    #[serde(rename = "1")]
+    Model1,
+    #[serde(rename = "2")]
+    Model2,
+    #[serde(rename = "micro")]
+    Micro,
+}
+
+/// Share of the code words used for error correction: about 7%, 15%, 25% or 30%
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum QrCorrection {
+    L,
+    M,
+    Q,
+    #[default]
+    H,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct QrOptions {
+    pub model: QrModel,
+    /// Size of a module in dots, 1-16
+    #[serde(deserialize_with = "super::in_range::<_, _, 1, 16>")]
+    #[schemars(range(min = 1, max = 16))]
+    pub size: u8,
+    pub correction: QrCorrection,
+}
+
+impl Default for QrOptions {
+    fn default() -> Self {
+        QrOptions {
+            model: QrModel::default(),
+            size: 4,
+            correction: QrCorrection::default(),
+        }
+    }
+}
+
+impl QrOptions {
+    pub fn to_escpos(&self) -> QRCodeOption {
+        let model = match self.model {
+            QrModel::Model1 => QRCodeModel::Model1,
+            QrModel::Model2 => QRCodeModel::Model2,
+            QrModel::Micro => QRCodeModel::Micro,
+        };
+        let correction = match self.correction {
+            QrCorrection::L => QRCodeCorrectionLevel::L,
+            QrCorrection::M => QRCodeCorrectionLevel::M,
+            QrCorrection::Q => QRCodeCorrectionLevel::Q,
+            QrCorrection::H => QRCodeCorrectionLevel::H,
+        };
+        QRCodeOption::new(model, self.size, correction)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Pdf417Options {
+    /// Data columns, 1-30, or 0 to let the printer choose
+    #[serde(deserialize_with = "super::in_range::<_, _, 0, 30>")]
+    #[schemars(range(min = 0, max = 30))]
+    pub columns: u8,
+    /// Rows, 3-90, or 0 to let the printer choose
+    #[serde(deserialize_with = "super::in_range::<_, _, 0, 90>")]
+    #[schemars(range(min = 0, max = 90))]
+    pub rows: u8,
+    /// Width of a module in dots, 2-8
+    #[serde(deserialize_with = "super::in_range::<_, _, 2, 8>")]
+    #[schemars(range(min = 2, max = 8))]
+    pub width: u8,
+    /// Height of a row in modules, 2-8
+    #[serde(deserialize_with = "super::in_range::<_, _, 2, 8>")]
+    #[schemars(range(min = 2, max = 8))]
+    pub row_height: u8,
+    /// Error correction level, 0-8
+    #[serde(deserialize_with = "super::in_range::<_, _, 0, 8>")]
+    #[schemars(range(min = 0, max = 8))]
+    pub correction: u8,
+    /// Omits the right row indicators to save space
+    pub truncated: bool,
+}
+
+impl Default for Pdf417Options {
+    fn default() -> Self {
+        Pdf417Options {
+            columns: 0,
+            rows: 0,
+            width: 3,
+            row_height: 3,
+            correction: 1,
+            truncated: false,
+        }
+    }
+}
+
+impl Pdf417Options {
+    pub fn to_escpos(&self) -> Result<Pdf417Option, PrinterError> {
+        let correction = match self.correction {
+            0 => Pdf417CorrectionLevel::Level0,
+            1 => Pdf417CorrectionLevel::Level1,
+            2 => Pdf417CorrectionLevel::Level2,
+            3 => Pdf417CorrectionLevel::Level3,
+            4 => Pdf417CorrectionLevel::Level4,
+            5 => Pdf417CorrectionLevel::Level5,
+            6 => Pdf417CorrectionLevel::Level6,
+            7 => Pdf417CorrectionLevel::Level7,
+            _ => Pdf417CorrectionLevel::Level8,
+        };
+        let code_type = if self.truncated {
+            Pdf417Type::Truncated
+        } else {
+            Pdf417Type::Standard
+        };
+        Pdf417Option::new(
+            self.columns,
+            self.rows,
+            self.width,
+            self.row_height,
+            code_type,
+            correction,
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct DataMatrixOptions {
+    /// Size of a module in dots, 2-16
+    #[serde(deserialize_with = "super::in_range::<_, _, 2, 16>")]
+    #[schemars(range(min = 2, max = 16))]
+    pub size: u8,
+    /// Prints a rectangular instead of a square symbol
+    pub rectangle: bool,
+}
+
+impl Default for DataMatrixOptions {
+    fn default() -> Self {
+        DataMatrixOptions {
+            size: 3,
+            rectangle: false,
+        }
+    }
+}
+
+impl DataMatrixOptions {
+    pub fn to_escpos(&self) -> Result<DataMatrixOption, PrinterError> {
+        // The number of rows and columns is left to the printer
+        let code_type = if self.rectangle {
+            DataMatrixType::Rectangle(8, 0)
+        } else {
+            DataMatrixType::Square(0)
+        };
+        DataMatrixOption::new(code_type, self.size)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct AztecOptions {
+    /// Size of a module in dots, 2-16
+    #[serde(deserialize_with = "super::in_range::<_, _, 2, 16>")]
+    #[schemars(range(min = 2, max = 16))]
+    pub size: u8,
+    /// Percentage of the symbol used for error correction, 5-95
+    #[serde(deserialize_with = "super::in_range::<_, _, 5, 95>")]
+    #[schemars(range(min = 5, max = 95))]
+    pub correction: u8,
+    /// Prints the compact form with up to 4 data layers
+    pub compact: bool,
+}
+
+impl Default for AztecOptions {
+    fn default() -> Self {
+        AztecOptions {
+            size: 3,
+            correction: 23,
+            compact: false,
+        }
+    }
+}
+
+impl AztecOptions {
+    pub fn to_escpos(&self) -> Result<AztecOption, PrinterError> {
+        let mode = if self.compact {
+            AztecMode::Compact(0)
+        } else {
+            AztecMode::FullRange(0)
+        };
+        AztecOption::new(mode, self.size, self.correction)
+    }
+}
+
+/// A 2D code and its data
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Code2d {
+    QrCode(String, QrOptions),
+    Pdf417(String, Pdf417Options),
+    #[serde(rename = "datamatrix")]
+    DataMatrix(String, DataMatrixOptions),
+    Aztec(String, AztecOptions),
+}
+
+impl Code2d {
+    /// Name of the kind of code in plural, as in "does not support QR codes"
+    pub fn name(&self) -> &'static str {
+        match self {
+            Code2d::QrCode(..) => "QR codes",
+            Code2d::Pdf417(..) => "PDF417 codes",
+            Code2d::DataMatrix(..) => "DataMatrix codes",
+            Code2d::Aztec(..) => "Aztec codes",
+        }
+    }
+
+    /// Name of the kind of code in singular
+    pub fn singular_name(&self) -> &'static str {
+        match self {
+            Code2d::QrCode(..) => "QR code",
+            Code2d::Pdf417(..) => "PDF417 code",
+            Code2d::DataMatrix(..) => "DataMatrix code",
+            Code2d::Aztec(..) => "Aztec code",
+        }
+    }
+
+    pub fn print<D: Driver>(
+        &self,
+        printer: &mut escpos::printer::Printer<D>,
+    ) -> Result<(), PrinterError> {
+        match self {
+            Code2d::QrCode(data, options) => printer.qrcode_option(data, options.to_escpos())?,
+            Code2d::Pdf417(data, options) => printer.pdf417_option(data, options.to_escpos()?)?,
+            Code2d::DataMatrix(data, options) => {
+                printer.data_matrix_option(data, options.to_escpos()?)?
+            }
+            Code2d::Aztec(data, options) => printer.aztec_option(data, options.to_escpos()?)?,
+        };
+        Ok(())
+    }
+
+    /// Whether a printer with the given features can print the code
+    ///
+    /// The printer database only lists QR and PDF417 support. DataMatrix and
+    /// Aztec use the same `GS ( k` command as QR codes and are assumed to be
+    /// available on the same printers.
+    pub fn supported(&self, features: &escpos_db::Features) -> bool {
+        match self {
+            Code2d::Pdf417(..) => features.pdf417_code(),
+            _ => features.qr_code(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escpos_options() {
+        let options = QrOptions {
+            model: QrModel::Model2,
+            size: 6,
+            correction: QrCorrection::M,
+        }
+        .to_escpos();
+        assert_eq!(options.size(), 6);
+        assert_eq!(u8::from(options.model()), 50);
+        assert_eq!(u8::from(options.correction_level()), 49);
+
+        assert!(Pdf417Options::default().to_escpos().is_ok());
+        assert!(Pdf417Options {
+            rows: 2,
+            ..Pdf417Options::default()
+        }
+        .to_escpos()
+        .is_err());
+        assert!(DataMatrixOptions {
+            rectangle: true,
+            ..DataMatrixOptions::default()
+        }
+        .to_escpos()
+        .is_ok());
+        assert!(AztecOptions {
+            correction: 99,
+            ..AztecOptions::default()
+        }
+        .to_escpos()
+        .is_err());
+    }
+}
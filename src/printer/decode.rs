@@ -0,0 +1,496 @@
+//! Decoding of ESC/POS byte streams back into printer [`Command`]s
+//!
+//! The decoder knows the sequences [`Printer`](super::Printer) sends for each
+//! command, so that captured jobs can be inspected and printed again. Text is
+//! decoded line by line, as [`Command::Write`] if it is ASCII and as
+//! [`Command::EncodedText`] otherwise, as its code page isn't known. All other
+//! sequences are kept as [`Command::Bytes`]: known ones with their parameters,
+//! unknown ones up to the next command.
+//!
+//! Commands are only decoded if [`encode`] turns them into the same bytes
+//! again, so a decoded job prints exactly like the original.
+use std::slice;
+
+use escpos::utils::{Font, JustifyMode, UnderlineMode};
+use image::{GrayImage, Luma};
+
+use super::barcode::{Barcode, BarcodeOptions, HriFont, HriPosition, Symbology};
+use super::codes_2d::{
+    AztecOptions, Code2d, DataMatrixOptions, Pdf417Options, QrCorrection, QrModel, QrOptions,
+};
+use super::{encode, Command};
+use crate::dither;
+
+const DLE: u8 = 0x10;
+const CAN: u8 = 0x18;
+const ESC: u8 = 0x1B;
+const FS: u8 = 0x1C;
+const GS: u8 = 0x1D;
+
+const SYMBOLOGIES: [Symbology; 8] = [
+    Symbology::UpcA,
+    Symbology::UpcE,
+    Symbology::Ean13,
+    Symbology::Ean8,
+    Symbology::Code39,
+    Symbology::Itf,
+    Symbology::Codabar,
+    Symbology::Code128,
+];
+
+/// A decoded command and the number of bytes it was decoded from
+type Decoded = Option<(Command, usize)>;
+
+/// Decodes the bytes sent to a printer into commands
+pub fn decode(bytes: &[u8]) -> Vec<Command> {
+    let mut commands = Vec::new();
+    let mut input = bytes;
+    while !input.is_empty() {
+        let (command, length) = text(input)
+            .or_else(|| command(input))
+            .or_else(|| barcode(input))
+            .or_else(|| code_2d(input))
+            .or_else(|| image(input))
+            .unwrap_or_else(|| {
+                let length = sequence_length(input);
+                (Command::Bytes(input[..length].to_vec()), length)
+            });
+        commands.push(command);
+        input = &input[length..];
+    }
+    commands
+}
+
+fn is_text(byte: u8) -> bool {
+    byte >= 0x20 || matches!(byte, b'\t' | b'\n' | b'\r')
+}
+
+/// Text up to the end of the line
+fn text(input: &[u8]) -> Decoded {
+    let end = input
+        .iter()
+        .position(|byte| !is_text(*byte))
+        .unwrap_or(input.len());
+    let length = input[..end]
+        .iter()
+        .position(|byte| *byte == b'\n')
+        .map_or(end, |newline| newline + 1);
+    let text = &input[..length];
+    let command = match text {
+        [] => return None,
+        text if text.is_ascii() => {
+            Command::Write(text.iter().map(|byte| char::from(*byte)).collect())
+        }
+        text => Command::EncodedText(text.to_vec()),
+    };
+    Some((command, length))
+}
+
+/// Commands of a fixed length
+fn command(input: &[u8]) -> Decoded {
+    let command = match *input {
+        [ESC, b't', number, ..] => Command::CodePage(number),
+        [ESC, b'E', bold @ (0 | 1), ..] => Command::Bold(bold == 1),
+        [ESC, b'-', mode @ 0..=2, ..] => Command::Underline(match mode {
+            0 => UnderlineMode::None,
+            1 => UnderlineMode::Single,
+            _ => UnderlineMode::Double,
+        }),
+        [ESC, b'G', double_strike @ (0 | 1), ..] => Command::DoubleStrike(double_strike == 1),
+        [ESC, b'M', font @ 0..=2, ..] => Command::Font(match font {
+            0 => Font::A,
+            1 => Font::B,
+            _ => Font::C,
+        }),
+        [ESC, b'V', flip @ (0 | 1), ..] => Command::Flip(flip == 1),
+        [ESC, b'a', mode @ 0..=2, ..] => Command::Justify(match mode {
+            0 => JustifyMode::LEFT,
+            1 => JustifyMode::CENTER,
+            _ => JustifyMode::RIGHT,
+        }),
+        [GS, b'B', reverse @ (0 | 1), ..] => Command::Reverse(reverse == 1),
+        [ESC, b'd', lines, ..] => Command::Feed(lines),
+        [GS, b'!', 0, ..] => Command::ResetSize,
+        // Width and height are 1-8, stored minus one in the high and low nibble
+        [GS, b'!', size, ..] if size & 0x88 == 0 => {
+            Command::Size((size >> 4) + 1, (size & 0x0F) + 1)
+        }
+        [GS, b'V', b'A', 0, ..] => return Some((Command::Cut, 4)),
+        _ => return None,
+    };
+    Some((command, 3))
+}
+
+/// Barcodes with their options, as sent by [`Barcode::to_bytes`]
+fn barcode(input: &[u8]) -> Decoded {
+    let (width, rest) = parameter(input, [GS, b'w'])?;
+    let (height, rest) = parameter(rest, [GS, b'h'])?;
+    let (hri_font, rest) = parameter(rest, [GS, b'f'])?;
+    let (hri, rest) = parameter(rest, [GS, b'H'])?;
+    let [GS, b'k', system, length, ref rest @ ..] = *rest else {
+        return None;
+    };
+    let data = rest.get(..usize::from(length))?;
+    let symbology = SYMBOLOGIES
+        .into_iter()
+        .find(|symbology| symbology.system() == system)?;
+    let options = BarcodeOptions {
+        width,
+        height,
+        hri: match hri {
+            0 => HriPosition::None,
+            1 => HriPosition::Above,
+            2 => HriPosition::Below,
+            3 => HriPosition::Both,
+            _ => return None,
+        },
+        hri_font: match hri_font {
+            0 => HriFont::A,
+            1 => HriFont::B,
+            _ => return None,
+        },
+    };
+    let data = match (symbology, data) {
+        (Symbology::Code128, [b'{', b'C', pairs @ ..]) => {
+            pairs.iter().map(|pair| format!("{:02}", pair)).collect()
+        }
+        (Symbology::Code128, [b'{', b'B', text @ ..]) => {
+            String::from_utf8(text.to_vec()).ok()?.replace("{{", "{")
+        }
+        (Symbology::Code128, _) => return None,
+        (_, data) => String::from_utf8(data.to_vec()).ok()?,
+    };
+
+    // Validated again from the data as written in programs, which turns the
+    // expanded data of UPC-E codes back into its short form
+    let data = Barcode {
+        symbology,
+        data,
+        options: options.clone(),
+    }
+    .program_data();
+    let barcode = Barcode::new(symbology, &data, options).ok()?;
+    let length = 16 + usize::from(length);
+    (barcode.to_bytes() == input[..length]).then_some((Command::Barcode(barcode), length))
+}
+
+/// The parameter of a command taking a single one, and the input after it
+fn parameter(input: &[u8], command: [u8; 2]) -> Option<(u8, &[u8])> {
+    match *input.strip_prefix(&command)? {
+        [parameter, ref rest @ ..] => Some((parameter, rest)),
+        [] => None,
+    }
+}
+
+/// 2D codes, sent as `GS ( k` functions setting their options, storing their
+/// data and printing them
+fn code_2d(input: &[u8]) -> Decoded {
+    let symbol = *input.get(5)?;
+    let (mut code, rest) = match symbol {
+        49 => {
+            let ([model, _], rest) = function(input, symbol, 65)?;
+            let ([size], rest) = function(rest, symbol, 67)?;
+            let ([correction], rest) = function(rest, symbol, 69)?;
+            let model = match model {
+                49 => QrModel::Model1,
+                50 => QrModel::Model2,
+                51 => QrModel::Micro,
+                _ => return None,
+            };
+            let correction = match correction {
+                48 => QrCorrection::L,
+                49 => QrCorrection::M,
+                50 => QrCorrection::Q,
+                51 => QrCorrection::H,
+                _ => return None,
+            };
+            let options = QrOptions {
+                model,
+                size,
+                correction,
+            };
+            (Code2d::QrCode(String::new(), options), rest)
+        }
+        48 => {
+            let ([columns], rest) = function(input, symbol, 65)?;
+            let ([rows], rest) = function(rest, symbol, 66)?;
+            let ([width], rest) = function(rest, symbol, 67)?;
+            let ([row_height], rest) = function(rest, symbol, 68)?;
+            let ([_, correction], rest) = function(rest, symbol, 69)?;
+            let ([code_type], rest) = function(rest, symbol, 70)?;
+            let options = Pdf417Options {
+                columns,
+                rows,
+                width,
+                row_height,
+                correction: correction.checked_sub(48)?,
+                truncated: code_type == 1,
+            };
+            (Code2d::Pdf417(String::new(), options), rest)
+        }
+        54 => {
+            let ([code_type, _, _], rest) = function(input, symbol, 66)?;
+            let ([size], rest) = function(rest, symbol, 67)?;
+            let options = DataMatrixOptions {
+                size,
+                rectangle: code_type == 1,
+            };
+            (Code2d::DataMatrix(String::new(), options), rest)
+        }
+        53 => {
+            let ([mode, _], rest) = function(input, symbol, 66)?;
+            let ([size], rest) = function(rest, symbol, 67)?;
+            let ([correction], rest) = function(rest, symbol, 69)?;
+            let options = AztecOptions {
+                size,
+                correction,
+                compact: mode == 1,
+            };
+            (Code2d::Aztec(String::new(), options), rest)
+        }
+        _ => return None,
+    };
+
+    let [GS, b'(', b'k', low, high, data_symbol, 80, 48, ref rest @ ..] = *rest else {
+        return None;
+    };
+    let data_length = usize::from(u16::from_le_bytes([low, high])).checked_sub(3)?;
+    let data = rest.get(..data_length)?;
+    let ([48], rest) = function(&rest[data_length..], symbol, 81)? else {
+        return None;
+    };
+    if data_symbol != symbol {
+        return None;
+    }
+    let (Code2d::QrCode(text, _)
+    | Code2d::Pdf417(text, _)
+    | Code2d::DataMatrix(text, _)
+    | Code2d::Aztec(text, _)) = &mut code;
+    *text = String::from_utf8(data.to_vec()).ok()?;
+
+    let length = input.len() - rest.len();
+    reencodes(Command::Code2d(code), &input[..length])
+}
+
+/// The parameters of a `GS ( k` function of a 2D code, and the input after it
+///
+/// The parameter count is passed in, rather than taken from the function,
+/// as `escpos` sends wrong counts for the error correction of PDF417 and
+/// Aztec codes.
+fn function<const N: usize>(input: &[u8], symbol: u8, function: u8) -> Option<([u8; N], &[u8])> {
+    let [GS, b'(', b'k', _, _, input_symbol, input_function, ref rest @ ..] = *input else {
+        return None;
+    };
+    if (input_symbol, input_function) != (symbol, function) {
+        return None;
+    }
+    let parameters = rest.get(..N)?.try_into().ok()?;
+    Some((parameters, &rest[N..]))
+}
+
+/// Raster images, preceded by the `CAN` clearing the print buffer
+fn image(input: &[u8]) -> Decoded {
+    let [CAN, GS, b'v', b'0', 0, low_width, high_width, low_height, high_height, ref rest @ ..] =
+        *input
+    else {
+        return None;
+    };
+    // The width is given in bytes of 8 dots
+    let width = u32::from(u16::from_le_bytes([low_width, high_width]));
+    let height = u32::from(u16::from_le_bytes([low_height, high_height]));
+    let data = rest.get(..(width * height) as usize)?;
+    if data.is_empty() {
+        return None;
+    }
+    let image = GrayImage::from_fn(width * 8, height, |x, y| {
+        let byte = data[(y * width + x / 8) as usize];
+        let black = byte & (0x80 >> (x % 8)) != 0;
+        Luma([if black { 0 } else { 255 }])
+    });
+    let png = dither::encode_png(image).ok()?;
+
+    let length = 9 + data.len();
+    reencodes(
+        Command::BitImageFromBytesWithWidth(png, width * 8),
+        &input[..length],
+    )
+}
+
+/// The command, if it is encoded as `bytes`
+fn reencodes(command: Command, bytes: &[u8]) -> Decoded {
+    let encoded = encode(slice::from_ref(&command)).ok()?;
+    (encoded == bytes).then_some((command, bytes.len()))
+}
+
+/// Length of a sequence that isn't decoded: of known ESC/POS commands with
+/// their parameters, of unknown ones up to the next command
+fn sequence_length(input: &[u8]) -> usize {
+    let two_bytes = |low, high| usize::from(u16::from_le_bytes([low, high]));
+    let length = match *input {
+        [ESC, b'@' | b'2' | b'<', ..] => 2,
+        [ESC, b' ' | b'!' | b'-' | b'3' | b'E' | b'G' | b'J' | b'M' | b'R' | b'V' | b'a' | b'd'
+        | b'e' | b't' | b'{', ..] => 3,
+        [ESC, b'$', ..] => 4,
+        [ESC, b'p', ..] => 5,
+        [GS, b'!' | b'B' | b'H' | b'b' | b'f' | b'h' | b'w', ..] => 3,
+        [GS, b'L' | b'W', ..] => 4,
+        // Function B cuts take the feed before the cut as second parameter
+        [GS, b'V', 65..=u8::MAX, ..] => 4,
+        [GS, b'V', ..] => 3,
+        [GS, b'(', _, low, high, ..] => 5 + two_bytes(low, high),
+        // Function A barcodes end with NUL, function B ones are preceded by
+        // their length
+        [GS, b'k', 0..=6, ref data @ ..] => {
+            3 + data
+                .iter()
+                .position(|byte| *byte == 0)
+                .map_or(data.len(), |end| end + 1)
+        }
+        [GS, b'k', _, length, ..] => 4 + usize::from(length),
+        [GS, b'v', b'0', _, low_width, high_width, low_height, high_height, ..] => {
+            8 + two_bytes(low_width, high_width) * two_bytes(low_height, high_height)
+        }
+        [DLE | ESC | FS | GS, ref rest @ ..] => {
+            1 + rest
+                .iter()
+                .position(|byte| matches!(*byte, DLE | CAN | ESC | FS | GS))
+                .unwrap_or(rest.len())
+        }
+        _ => 1,
+    };
+    length.min(input.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn test_round_trip() {
+        let image =
+            GrayImage::from_fn(16, 3, |x, y| Luma([if (x + y) % 3 == 0 { 0 } else { 255 }]));
+        let barcode = |symbology, data: &str| {
+            let options = BarcodeOptions {
+                width: 2,
+                hri: HriPosition::Above,
+                hri_font: HriFont::B,
+                ..BarcodeOptions::default()
+            };
+            Command::Barcode(Barcode::new(symbology, data, options).unwrap())
+        };
+        let commands = vec![
+            Command::Bytes(vec![ESC, b'@']),
+            Command::Justify(JustifyMode::CENTER),
+            Command::Size(2, 3),
+            Command::Write(String::from("Order #42\n")),
+            Command::ResetSize,
+            Command::Bold(true),
+            Command::Underline(UnderlineMode::Double),
+            Command::DoubleStrike(true),
+            Command::Font(Font::B),
+            Command::Flip(true),
+            Command::Reverse(true),
+            Command::CodePage(2),
+            Command::EncodedText(b"Gr\x81\xe1e\n".to_vec()),
+            Command::Write(String::from("Total: $3.50")),
+            Command::Feed(3),
+            barcode(Symbology::Ean13, "400638133393"),
+            barcode(Symbology::UpcE, "04252614"),
+            barcode(Symbology::Code128, "123456"),
+            barcode(Symbology::Code128, "A{1}"),
+            Command::Code2d(Code2d::QrCode(
+                String::from("https://example.com"),
+                QrOptions {
+                    model: QrModel::Model2,
+                    size: 6,
+                    correction: QrCorrection::M,
+                },
+            )),
+            Command::Code2d(Code2d::Pdf417(
+                String::from("PDF417"),
+                Pdf417Options {
+                    correction: 4,
+                    truncated: true,
+                    ..Pdf417Options::default()
+                },
+            )),
+            Command::Code2d(Code2d::DataMatrix(
+                String::from("DataMatrix"),
+                DataMatrixOptions {
+                    size: 4,
+                    rectangle: true,
+                },
+            )),
+            Command::Code2d(Code2d::Aztec(
+                String::from("Aztec"),
+                AztecOptions {
+                    compact: true,
+                    ..AztecOptions::default()
+                },
+            )),
+            Command::BitImageFromBytesWithWidth(dither::encode_png(image).unwrap(), 16),
+            Command::Cut,
+        ];
+
+        assert_eq!(decode(&encode(&commands).unwrap()), commands);
+    }
+
+    #[test]
+    fn test_unknown_sequences() {
+        let mut bytes = vec![ESC, b'@', ESC, b't', 0, GS, b'b', 0];
+        bytes.extend(b"Hello\nWorld");
+        // Unknown, a partial cut, an invalid EAN-13 check digit and a
+        // truncated image
+        bytes.extend([ESC, b'~', 1, b'x', GS, b'V', b'A', 1]);
+        bytes.extend(
+            Barcode::new(Symbology::Ean13, "4006381333931", BarcodeOptions::default())
+                .unwrap()
+                .to_bytes()
+                .iter()
+                .map(|byte| if *byte == b'1' { b'2' } else { *byte }),
+        );
+        bytes.extend([CAN, GS, b'v', b'0', 0, 2, 0, 8, 0, 0xFF]);
+
+        let commands = decode(&bytes);
+        assert_eq!(
+            commands[..6],
+            [
+                Command::Bytes(vec![ESC, b'@']),
+                Command::CodePage(0),
+                Command::Bytes(vec![GS, b'b', 0]),
+                Command::Write(String::from("Hello\n")),
+                Command::Write(String::from("World")),
+                Command::Bytes(vec![ESC, b'~', 1, b'x']),
+            ]
+        );
+        assert_eq!(commands[6], Command::Bytes(vec![GS, b'V', b'A', 1]));
+        assert_eq!(
+            commands[11],
+            Command::Bytes(b"\x1Dk\x43\x0D4006382333932".to_vec())
+        );
+        assert_eq!(commands[12], Command::Bytes(vec![CAN]));
+        assert_eq!(
+            commands.last(),
+            Some(&Command::Bytes(vec![GS, b'v', b'0', 0, 2, 0, 8, 0, 0xFF]))
+        );
+        assert_eq!(encode(&commands).unwrap(), bytes);
+    }
+
+    /// Bytes biased towards the start of commands
+    fn byte() -> impl Strategy<Value = u8> {
+        prop_oneof![
+            any::<u8>(),
+            proptest::sample::select(vec![
+                0, 1, b'\n', CAN, ESC, GS, b'!', b'(', b'V', b'k', b'v'
+            ]),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_reencode(bytes in proptest::collection::vec(byte(), 0..64)) {
+            prop_assert_eq!(encode(&decode(&bytes)).unwrap(), bytes);
+        }
+    }
+}
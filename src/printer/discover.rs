@@ -10,6 +10,7 @@ const SNMP_RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
 const DEFAULT_PORT: u16 = 9100;
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     #[error("i/o error")]
     IoError(#[from] std::io::Error),
@@ -37,7 +38,7 @@ async fn get_snmp_details(addr: &IpAddr) -> Result<Info, Error> {
     let sys_name_oid = Oid::from(&[1, 3, 6, 1, 2, 1, 1, 5, 0]).unwrap();
     let sys_descr_oid = Oid::from(&[1, 3, 6, 1, 2, 1, 1, 1, 0]).unwrap();
     let community = b"public";
-    let mut sess = AsyncSession::new_v1(SocketAddr::new(addr.clone(), 161), community, 0)
+    let mut sess = AsyncSession::new_v1(SocketAddr::new(*addr, 161), community, 0)
         .await
         .map_err(Error::IoError)?;
     let mut response = sess.get(&sys_descr_oid).await.unwrap();
@@ -56,7 +57,7 @@ async fn get_snmp_details(addr: &IpAddr) -> Result<Info, Error> {
         Err(Error::NoName)
     }?;
 
-    let address = SocketAddr::new(addr.clone(), DEFAULT_PORT); // todo: discover port?
+    let address = SocketAddr::new(*addr, DEFAULT_PORT); // todo: discover port?
 
     Ok(Info {
         name,
@@ -98,7 +99,7 @@ pub async fn discover_network_printers() -> Result<Vec<Info>, Error> {
             break;
         }
 
-        let mut buf = [0 as u8; 1024];
+        let mut buf = [0_u8; 1024];
         if let Ok(Ok((_len, addr))) =
             tokio::time::timeout(DISCOVERY_RESPONSE_TIMEOUT, sock.recv_from(&mut buf)).await
         {
@@ -1,3 +1,6 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
 use escpos::driver::Driver;
 use escpos::driver::NetworkDriver;
 use escpos::errors::PrinterError;
@@ -8,15 +11,21 @@ use escpos::utils::Font;
 use escpos::utils::JustifyMode;
 use escpos::utils::Protocol;
 use escpos::utils::UnderlineMode;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
 use thiserror::Error;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot::Sender;
 
+pub mod barcode;
+pub mod codes_2d;
+pub mod decode;
 mod discover;
+mod remote;
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("failed to print")]
+    #[error("{0}")]
     Printer(#[from] PrinterError),
     #[error("discovery error")]
     Discovery(#[from] discover::Error),
@@ -29,26 +38,69 @@ pub struct Printer {
     pub(crate) program_sender: UnboundedSender<Job>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+#[schemars(rename = "PrinterCommand")]
 pub enum Command {
     Write(String),
+    /// Text encoded for the selected code page, only produced by the renderer
+    #[serde(skip)]
+    EncodedText(Vec<u8>),
+    /// Selects a code page by its `ESC t` number, only produced by the renderer
+    #[serde(skip)]
+    CodePage(u8),
     Bold(bool),
-    Underline(UnderlineMode),
+    Underline(#[serde(with = "remote::UnderlineModeDef")] UnderlineMode),
     DoubleStrike(bool),
-    Font(Font),
+    Font(#[serde(with = "remote::FontDef")] Font),
     Flip(bool),
-    Justify(JustifyMode),
+    Justify(#[serde(with = "remote::JustifyModeDef")] JustifyMode),
     Reverse(bool),
     Feed(u8),
-    Ean13(String),
-    Ean8(String),
-    QrCode(String),
-    Size(u8, u8),
+    Barcode(barcode::Barcode),
+    Size(
+        #[serde(deserialize_with = "in_range::<_, _, 1, 8>")]
+        #[schemars(range(min = 1, max = 8))]
+        u8,
+        #[serde(deserialize_with = "in_range::<_, _, 1, 8>")]
+        #[schemars(range(min = 1, max = 8))]
+        u8,
+    ),
     ResetSize,
     Cut,
+    /// A dithered PNG image and its width, only produced by the renderer
+    #[serde(skip)]
     BitImageFromBytesWithWidth(Vec<u8>, u32),
+    /// Bytes sent to the printer as they are, like ESC/POS sequences that
+    /// [`decode::decode`] doesn't know. Not accepted in JSON programs, as
+    /// they would bypass validation.
+    #[serde(skip)]
+    Bytes(Vec<u8>),
+    #[serde(untagged)]
+    Code2d(codes_2d::Code2d),
+}
+
+/// Deserializes an integer argument, rejecting values outside of `MIN..=MAX`
+/// like the parser of the DSL does
+pub(crate) fn in_range<'de, D, T, const MIN: i64, const MAX: i64>(
+    deserializer: D,
+) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Copy + Into<i64>,
+{
+    let value = T::deserialize(deserializer)?;
+    if (MIN..=MAX).contains(&value.into()) {
+        Ok(value)
+    } else {
+        Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Signed(value.into()),
+            &format!("{}-{}", MIN, MAX).as_str(),
+        ))
+    }
 }
 
+#[derive(Debug, Clone)]
 pub struct Program(pub Vec<Command>);
 
 pub(crate) enum Job {
@@ -84,32 +136,7 @@ impl Printer {
                                 .page_code(escpos::utils::PageCode::PC437)?
                                 .smoothing(false)?;
                             for command in &program.0 {
-                                use Command::*;
-                                match command {
-                                    Write(text) => printer.write(&text)?,
-                                    Bold(bold) => printer.bold(*bold)?,
-                                    Underline(mode) => printer.underline(*mode)?,
-                                    DoubleStrike(mode) => printer.double_strike(*mode)?,
-                                    Font(font) => printer.font(*font)?,
-                                    Flip(flip) => printer.flip(*flip)?,
-                                    Justify(mode) => printer.justify(*mode)?,
-                                    Reverse(reverse) => printer.reverse(*reverse)?,
-                                    Feed(lines) => printer.feeds(*lines)?,
-                                    Ean13(string) => printer.ean13(&string)?,
-                                    Ean8(string) => printer.ean8(&string)?,
-                                    QrCode(string) => printer.qrcode(&string)?,
-                                    Size(x, y) => printer.size(*x, *y)?,
-                                    ResetSize => printer.reset_size()?,
-                                    Cut => printer.cut()?,
-                                    BitImageFromBytesWithWidth(bytes, width) => printer
-                                        .bit_image_from_bytes_option(&bytes, {
-                                            BitImageOption::new(
-                                                Some(*width),
-                                                None,
-                                                escpos::utils::BitImageSize::Normal,
-                                            )?
-                                        })?, //_ => &mut self.printer,
-                                };
+                                print_command(&mut printer, command)?;
                             }
 
                             printer.print()?;
@@ -124,7 +151,7 @@ impl Printer {
                             let driver = (driver_builder)()?;
                             driver.write(&[0x1D, 0x49, 67])?;
                             driver.flush()?;
-                            let mut response = [0 as u8; 82];
+                            let mut response = [0_u8; 82];
                             driver.read(&mut response)?;
                             let string_end = &response[1..].iter().position(|x| *x == 0_u8);
                             Ok(
@@ -165,6 +192,79 @@ impl Printer {
     }
 }
 
+fn print_command<D: Driver>(
+    printer: &mut escpos::printer::Printer<D>,
+    command: &Command,
+) -> Result<(), PrinterError> {
+    use Command::*;
+    match command {
+        Write(text) => printer.write(text)?,
+        EncodedText(bytes) => printer.custom(bytes)?,
+        CodePage(number) => printer.custom(&[0x1B, b't', *number])?,
+        Bold(bold) => printer.bold(*bold)?,
+        Underline(mode) => printer.underline(*mode)?,
+        DoubleStrike(mode) => printer.double_strike(*mode)?,
+        Font(font) => printer.font(*font)?,
+        Flip(flip) => printer.flip(*flip)?,
+        Justify(mode) => printer.justify(*mode)?,
+        Reverse(reverse) => printer.reverse(*reverse)?,
+        Feed(lines) => printer.feeds(*lines)?,
+        Barcode(barcode) => printer.custom(&barcode.to_bytes())?,
+        Code2d(code) => {
+            code.print(printer)?;
+            printer
+        }
+        Size(x, y) => printer.size(*x, *y)?,
+        ResetSize => printer.reset_size()?,
+        Cut => printer.cut()?,
+        BitImageFromBytesWithWidth(bytes, width) => printer.bit_image_from_bytes_option(
+            bytes,
+            BitImageOption::new(Some(*width), None, escpos::utils::BitImageSize::Normal)?,
+        )?,
+        Bytes(bytes) => printer.custom(bytes)?,
+    };
+    Ok(())
+}
+
+/// Driver collecting the bytes written to it
+#[derive(Clone, Default)]
+struct MemoryDriver(Rc<RefCell<Vec<u8>>>);
+
+impl Driver for MemoryDriver {
+    fn name(&self) -> String {
+        String::from("memory")
+    }
+
+    fn write(&self, data: &[u8]) -> Result<(), PrinterError> {
+        self.0.borrow_mut().extend_from_slice(data);
+        Ok(())
+    }
+
+    fn read(&self, _buf: &mut [u8]) -> Result<usize, PrinterError> {
+        Ok(0)
+    }
+
+    fn flush(&self) -> Result<(), PrinterError> {
+        Ok(())
+    }
+}
+
+/// The ESC/POS bytes sent for the commands, without the initialization of the
+/// printer that precedes every job
+pub fn encode(commands: &[Command]) -> Result<Vec<u8>, PrinterError> {
+    let driver = MemoryDriver::default();
+    let mut options = PrinterOptions::default();
+    // As selected when initializing the printer
+    options.page_code(Some(escpos::utils::PageCode::PC437));
+    let mut printer =
+        escpos::printer::Printer::new(driver.clone(), Protocol::default(), Some(options));
+    for command in commands {
+        print_command(&mut printer, command)?;
+    }
+    printer.print()?;
+    Ok(driver.0.take())
+}
+
 pub async fn discover_network() -> Result<Vec<Printer>, Error> {
     let printers = discover::discover_network_printers()
         .await
@@ -182,3 +282,20 @@ pub async fn discover_network() -> Result<Vec<Printer>, Error> {
         })
         .collect())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_internal_commands_not_deserialized() {
+        // Renderer output isn't validated, so it can't be sent
+        assert!(serde_json::from_str::<Command>(r#"{"encoded_text": [65]}"#).is_err());
+        assert!(serde_json::from_str::<Command>(r#"{"code_page": 2}"#).is_err());
+        assert!(serde_json::from_str::<Command>(r#"{"bytes": [27, 64]}"#).is_err());
+        assert!(serde_json::from_str::<Command>(
+            r#"{"bit_image_from_bytes_with_width": [[], 600000]}"#
+        )
+        .is_err());
+    }
+}
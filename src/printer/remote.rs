@@ -0,0 +1,33 @@
+//! Serde and JSON Schema definitions of the escpos enums used by
+//! [`Command`](super::Command), named like their values in the DSL
+#![allow(clippy::upper_case_acronyms)]
+use escpos::utils::{Font, JustifyMode, UnderlineMode};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(remote = "Font", rename_all = "lowercase")]
+#[schemars(rename = "Font")]
+pub(super) enum FontDef {
+    A,
+    B,
+    C,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(remote = "JustifyMode", rename_all = "lowercase")]
+#[schemars(rename = "JustifyMode")]
+pub(super) enum JustifyModeDef {
+    LEFT,
+    CENTER,
+    RIGHT,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(remote = "UnderlineMode", rename_all = "lowercase")]
+#[schemars(rename = "UnderlineMode")]
+pub(super) enum UnderlineModeDef {
+    None,
+    Single,
+    Double,
+}
@@ -1,7 +1,7 @@
-/// Macros for documenting DSL commands directly in the parser
-/// This allows documentation to be co-located with the parser implementation
+//! Macros for documenting DSL commands directly in the parser
+//! This allows documentation to be co-located with the parser implementation
 
-/// Storage for command documentation extracted from parser
+// Storage for command documentation extracted from parser
 use once_cell::sync::Lazy;
 use std::sync::Mutex;
 
@@ -33,6 +33,7 @@ pub fn get_registered_commands() -> Vec<CommandDoc> {
 ///         "write \"Hello World\"",
 ///         "write \"Price: $19.99\""
 ///     ],
+///     arguments: vec![Argument::string("text")],
 ///     parser: |input| {
 ///         map(
 ///             preceded(pair(tag("write"), space1), escaped_string),
@@ -50,6 +51,7 @@ macro_rules! doc_command {
         description: $description:expr,
         category: $category:ident,
         examples: [ $($example:expr),* $(,)? ],
+        $(arguments: $arguments:expr,)?
         parser: $parser:expr
     ) => {{
         // Register the documentation
@@ -66,6 +68,7 @@ macro_rules! doc_command {
                 description: $description,
                 category: CommandCategory::$category,
                 examples: vec![$($example),*],
+                arguments: $crate::documented_arguments!($($arguments)?),
             });
         });
 
@@ -75,6 +78,10 @@ macro_rules! doc_command {
 }
 
 /// Helper macro to create a documented command parser function
+///
+/// `arguments` lists the typed [`Argument`](super::documentation::Argument)s
+/// of a command for editor support and may be left out for commands without
+/// arguments.
 #[macro_export]
 macro_rules! documented_parser {
     (
@@ -85,6 +92,7 @@ macro_rules! documented_parser {
                 description: $description:expr,
                 category: $category:ident,
                 examples: [ $($example:expr),* $(,)? ],
+                $(arguments: $arguments:expr,)?
                 parser: $parser:expr
             }
         ),* $(,)?
@@ -104,6 +112,7 @@ macro_rules! documented_parser {
                     description: $description,
                     category: CommandCategory::$category,
                     examples: vec![$($example),*],
+                    arguments: $crate::documented_arguments!($($arguments)?),
                 });
             });
         })*
@@ -115,10 +124,22 @@ macro_rules! documented_parser {
     }};
 }
 
+/// Arguments of a command in [`documented_parser!`], none if not given
+#[doc(hidden)]
+#[macro_export]
+macro_rules! documented_arguments {
+    () => {
+        Vec::new()
+    };
+    ($arguments:expr) => {
+        $arguments
+    };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::program::documentation::CommandCategory;
+    use crate::program::documentation::{Argument, CommandCategory};
 
     #[test]
     fn test_command_doc_structure() {
@@ -129,6 +150,7 @@ mod tests {
             description: "An example command",
             category: CommandCategory::Text,
             examples: vec!["example test", "example foo"],
+            arguments: vec![Argument::string("arg")],
         };
 
         assert_eq!(doc.name, "example");
@@ -137,6 +159,7 @@ mod tests {
         assert_eq!(doc.category, CommandCategory::Text);
         assert_eq!(doc.examples.len(), 2);
         assert_eq!(doc.examples[0], "example test");
+        assert_eq!(doc.arguments[0].name, "arg");
     }
 
     #[test]
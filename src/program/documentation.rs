@@ -1,23 +1,132 @@
 /// Command documentation and metadata for DSL reference generation
 use std::fmt::Write;
 
+use serde::Serialize;
+
 /// Metadata for a DSL command
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CommandDoc {
     pub name: &'static str,
     pub syntax: &'static str,
     pub description: &'static str,
     pub examples: Vec<&'static str>,
     pub category: CommandCategory,
+    /// Arguments in the order they are written
+    pub arguments: Vec<Argument>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// An argument of a DSL command
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Argument {
+    pub name: &'static str,
+    #[serde(flatten)]
+    pub kind: ArgumentKind,
+    /// Whether the argument may be left out
+    pub optional: bool,
+    /// Whether the argument is written as `<name>=<value>`, or as just its
+    /// name for flags
+    pub named: bool,
+    /// Whether the argument may be repeated
+    pub repeated: bool,
+}
+
+/// Type of the value of an argument
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ArgumentKind {
+    /// A quoted string
+    String,
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    Number {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+    },
+    Boolean,
+    /// One of a list of keywords
+    Choice {
+        values: &'static [&'static str],
+    },
+    /// Unquoted digits
+    Digits,
+    /// A comma separated list of column specs like `*., 8>`
+    Columns,
+    /// An unquoted name
+    Name,
+    /// A `{{<variable>}}` placeholder
+    Placeholder,
+    /// A keyword that is present or not
+    Flag,
+}
+
+impl Argument {
+    pub const fn new(name: &'static str, kind: ArgumentKind) -> Self {
+        Argument {
+            name,
+            kind,
+            optional: false,
+            named: false,
+            repeated: false,
+        }
+    }
+
+    pub const fn string(name: &'static str) -> Self {
+        Argument::new(name, ArgumentKind::String)
+    }
+
+    pub const fn integer(name: &'static str, min: i64, max: i64) -> Self {
+        Argument::new(name, ArgumentKind::Integer { min, max })
+    }
+
+    pub const fn number(name: &'static str, min: Option<f64>, max: Option<f64>) -> Self {
+        Argument::new(name, ArgumentKind::Number { min, max })
+    }
+
+    pub const fn boolean(name: &'static str) -> Self {
+        Argument::new(name, ArgumentKind::Boolean)
+    }
+
+    pub const fn choice(name: &'static str, values: &'static [&'static str]) -> Self {
+        Argument::new(name, ArgumentKind::Choice { values })
+    }
+
+    /// An optional flag like `truncated`
+    pub const fn flag(name: &'static str) -> Self {
+        Argument::new(name, ArgumentKind::Flag).option()
+    }
+
+    /// Makes the argument an optional `<name>=<value>` option
+    pub const fn option(mut self) -> Self {
+        self.optional = true;
+        self.named = true;
+        self
+    }
+
+    pub const fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    pub const fn repeated(mut self) -> Self {
+        self.repeated = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum CommandCategory {
     Text,
     Formatting,
     Barcodes,
+    Graphics,
     Layout,
     Special,
+    ControlFlow,
 }
 
 impl CommandCategory {
@@ -26,8 +135,10 @@ impl CommandCategory {
             CommandCategory::Text => "Text Output",
             CommandCategory::Formatting => "Text Formatting",
             CommandCategory::Barcodes => "Barcodes & QR Codes",
+            CommandCategory::Graphics => "Images & Graphics",
             CommandCategory::Layout => "Layout & Spacing",
             CommandCategory::Special => "Special Commands",
+            CommandCategory::ControlFlow => "Control Flow",
         }
     }
 }
@@ -36,6 +147,9 @@ impl CommandCategory {
 /// This is populated when the parser is loaded
 pub fn all_commands() -> Vec<CommandDoc> {
     use super::doc_macros::get_registered_commands;
+    // Run the parsers once so their documentation is registered
+    let _ = super::Command::parse("");
+    let _ = super::Program::parse_blocks("");
     get_registered_commands()
 }
 
@@ -54,7 +168,9 @@ fn generate_markdown_with_commands(commands: Vec<CommandDoc>) -> String {
         CommandCategory::Formatting,
         CommandCategory::Layout,
         CommandCategory::Barcodes,
+        CommandCategory::Graphics,
         CommandCategory::Special,
+        CommandCategory::ControlFlow,
     ];
 
     for category in categories.iter() {
@@ -107,8 +223,8 @@ fn generate_text_with_commands(commands: Vec<CommandDoc>) -> String {
         writeln!(output, "  {}", cmd.description).unwrap();
         if !cmd.examples.is_empty() {
             writeln!(output, "  Examples:").unwrap();
-            for example in &cmd.examples {
-                writeln!(output, "    {}", example).unwrap();
+            for line in cmd.examples.iter().flat_map(|example| example.lines()) {
+                writeln!(output, "    {}", line).unwrap();
             }
         }
         writeln!(output).unwrap();
@@ -117,10 +233,16 @@ fn generate_text_with_commands(commands: Vec<CommandDoc>) -> String {
     output
 }
 
+/// Generate a machine-readable catalogue of all commands as JSON
+pub fn generate_json() -> String {
+    serde_json::to_string_pretty(&all_commands()).unwrap() + "\n"
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::program::Command;
+    use serde_json::Value;
 
     // Helper to ensure parser is loaded before tests
     fn ensure_parser_loaded() {
@@ -164,7 +286,25 @@ mod tests {
         assert!(categories.contains(&CommandCategory::Text));
         assert!(categories.contains(&CommandCategory::Formatting));
         assert!(categories.contains(&CommandCategory::Barcodes));
+        assert!(categories.contains(&CommandCategory::Graphics));
         assert!(categories.contains(&CommandCategory::Layout));
         assert!(categories.contains(&CommandCategory::Special));
+        assert!(categories.contains(&CommandCategory::ControlFlow));
+    }
+
+    #[test]
+    fn test_generate_json() {
+        ensure_parser_loaded();
+        let catalogue: Value = serde_json::from_str(&generate_json()).unwrap();
+        let qr_code = catalogue
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|cmd| cmd["name"] == "qr_code")
+            .unwrap();
+        assert_eq!(qr_code["category"], "barcodes");
+        assert_eq!(qr_code["arguments"][0]["type"], "string");
+        assert_eq!(qr_code["arguments"][1]["name"], "size");
+        assert_eq!(qr_code["arguments"][1]["named"], true);
     }
 }
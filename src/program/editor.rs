@@ -0,0 +1,286 @@
+//! Editor support for programs: a TextMate grammar for syntax highlighting,
+//! as used by VS Code and many other editors, and snippets for completion
+//!
+//! Both are derived from the documented commands, so they follow the parser.
+use serde_json::{json, Map, Value};
+
+use super::documentation::{all_commands, ArgumentKind, CommandCategory, CommandDoc};
+
+/// Scope name of the grammar, and language id of the snippets
+pub const SCOPE: &str = "source.escpos";
+
+/// File name extension of programs
+pub const FILE_TYPE: &str = "escpos";
+
+/// Generate a TextMate grammar for programs as JSON
+pub fn generate_textmate_grammar() -> String {
+    let commands = all_commands();
+
+    let names = |control_flow: bool| {
+        let mut names: Vec<&str> = commands
+            .iter()
+            .filter(|cmd| (cmd.category == CommandCategory::ControlFlow) == control_flow)
+            .map(|cmd| cmd.name)
+            .collect();
+        names.sort();
+        names.dedup();
+        // Longer names first, so `write` doesn't end the match of `writeln`
+        names.sort_by_key(|name| std::cmp::Reverse(name.len()));
+        names.join("|")
+    };
+
+    let arguments = || commands.iter().flat_map(|cmd| &cmd.arguments);
+    let mut options: Vec<&str> = arguments()
+        .filter(|argument| argument.named && argument.kind != ArgumentKind::Flag)
+        .map(|argument| argument.name)
+        .collect();
+    let mut flags: Vec<&str> = arguments()
+        .filter(|argument| argument.kind == ArgumentKind::Flag)
+        .map(|argument| argument.name)
+        .collect();
+    let mut constants: Vec<&str> = arguments()
+        .flat_map(|argument| match argument.kind {
+            ArgumentKind::Choice { values } => values.to_vec(),
+            ArgumentKind::Boolean => vec!["true", "false"],
+            _ => vec![],
+        })
+        .chain(["auto"])
+        .filter(|value| !value.chars().all(|c| c.is_ascii_digit()))
+        .collect();
+    for list in [&mut options, &mut flags, &mut constants] {
+        list.sort();
+        list.dedup();
+    }
+
+    let escapes = json!({
+        "name": "constant.character.escape.escpos",
+        "match": r#"\\(?:u\{[0-9a-fA-F]{1,6}\}|["\\nrt])"#
+    });
+    let grammar = json!({
+        "$schema": "https://raw.githubusercontent.com/martinring/tmlanguage/master/tmlanguage.json",
+        "name": "escpos2mqtt",
+        "scopeName": SCOPE,
+        "fileTypes": [FILE_TYPE],
+        "patterns": [
+            {"include": "#control"},
+            {"include": "#command"},
+            {"include": "#triple-string"},
+            {"include": "#string"},
+            {"include": "#placeholder"},
+            {"include": "#option"},
+            {"include": "#flag"},
+            {"include": "#constant"},
+            {"include": "#number"},
+            {"include": "#column"}
+        ],
+        "repository": {
+            "control": {
+                "name": "keyword.control.escpos",
+                "match": format!(r"^\s*(?:{})\b|\b(?:else|in)\b", names(true))
+            },
+            "command": {
+                "match": format!(r"^\s*({})\b", names(false)),
+                "captures": {"1": {"name": "support.function.escpos"}}
+            },
+            "triple-string": {
+                "name": "string.quoted.triple.escpos",
+                "begin": "\"\"\"",
+                "end": "\"\"\"",
+                "patterns": [escapes, {"include": "#placeholder"}]
+            },
+            "string": {
+                "name": "string.quoted.double.escpos",
+                "begin": "\"",
+                "end": "\"",
+                "patterns": [escapes, {"include": "#placeholder"}]
+            },
+            "placeholder": {
+                "name": "variable.other.placeholder.escpos",
+                "begin": r"\{\{",
+                "end": r"\}\}",
+                "patterns": [{"include": "#string"}, {"include": "#number"}]
+            },
+            "option": {
+                "match": format!(r"\b({})(=)", options.join("|")),
+                "captures": {
+                    "1": {"name": "variable.parameter.escpos"},
+                    "2": {"name": "keyword.operator.assignment.escpos"}
+                }
+            },
+            "flag": {
+                "name": "variable.parameter.escpos",
+                "match": format!(r"\b(?:{})\b", flags.join("|"))
+            },
+            "constant": {
+                "name": "constant.language.escpos",
+                "match": format!(r"\b(?:{})\b", constants.join("|"))
+            },
+            "number": {
+                "name": "constant.numeric.escpos",
+                "match": r"-?\b\d+(?:\.\d+)?\b"
+            },
+            "column": {
+                "name": "keyword.operator.column.escpos",
+                "match": r"(?<=[\d*])[<>.]+|\*"
+            }
+        }
+    });
+    serde_json::to_string_pretty(&grammar).unwrap() + "\n"
+}
+
+/// Generate VS Code snippets for all commands as JSON
+///
+/// Snippets are keyed by the syntax of their command, as some commands have
+/// several forms.
+pub fn generate_snippets() -> String {
+    let snippets: Map<String, Value> = all_commands()
+        .iter()
+        .map(|cmd| {
+            let snippet = json!({
+                "prefix": cmd.name,
+                "body": snippet_body(cmd),
+                "description": cmd.description,
+                "scope": FILE_TYPE
+            });
+            (cmd.syntax.to_string(), snippet)
+        })
+        .collect();
+    serde_json::to_string_pretty(&snippets).unwrap() + "\n"
+}
+
+/// The syntax of a command with its required arguments as tab stops, choices
+/// as drop downs and its optional arguments left out
+fn snippet_body(cmd: &CommandDoc) -> String {
+    let syntax = cmd.syntax;
+    let choices: Vec<&str> = cmd
+        .arguments
+        .iter()
+        .flat_map(|argument| match argument.kind {
+            ArgumentKind::Choice { values } => values.to_vec(),
+            ArgumentKind::Boolean => vec!["true", "false"],
+            _ => vec![],
+        })
+        .collect();
+    let mut body = String::new();
+    let mut tab_stop = 0;
+    let mut block = false;
+    let mut chars = syntax.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '[' => {
+                // Optional parts, along with the space before them
+                let mut depth = 1;
+                for (_, c) in chars.by_ref() {
+                    match c {
+                        '[' => depth += 1,
+                        ']' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                body.truncate(body.trim_end().len());
+            }
+            '<' => {
+                let end = syntax[i..].find('>').map_or(syntax.len(), |end| i + end);
+                let name = &syntax[i + 1..end];
+                while chars.peek().is_some_and(|(j, _)| *j <= end) {
+                    chars.next();
+                }
+                tab_stop += 1;
+                let alternatives: Vec<&str> = name.split('|').collect();
+                if alternatives.len() > 1
+                    && alternatives.iter().all(|value| choices.contains(value))
+                {
+                    body.push_str(&format!("${{{}|{}|}}", tab_stop, alternatives.join(",")));
+                } else {
+                    // Like `<auto|name>`, where only some alternatives are keywords
+                    body.push_str(&format!("${{{}:{}}}", tab_stop, alternatives[0]));
+                }
+            }
+            '.' if syntax[i..].starts_with("...") => {
+                chars.nth(1);
+                // Repetitions like `<column>,...` are left out
+                if body.ends_with(',') {
+                    body.pop();
+                }
+                if body.ends_with("{ ") {
+                    // The body of a block, the cursor ends in the first one
+                    body.truncate(body.len() - 1);
+                    body.push_str(if block { "\n\t\n" } else { "\n\t$0\n" });
+                    block = true;
+                    if chars.peek().is_some_and(|(_, c)| *c == ' ') {
+                        chars.next();
+                    }
+                }
+            }
+            '$' | '\\' => {
+                body.push('\\');
+                body.push(c);
+            }
+            _ => body.push(c),
+        }
+    }
+    body.trim_end().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snippet(syntax: &str) -> String {
+        let cmd = all_commands()
+            .into_iter()
+            .find(|cmd| cmd.syntax == syntax)
+            .unwrap();
+        snippet_body(&cmd)
+    }
+
+    #[test]
+    fn test_snippet_bodies() {
+        assert_eq!(snippet("write \"<text>\""), "write \"${1:text}\"");
+        assert_eq!(
+            snippet("justify <left|center|right>"),
+            "justify ${1|left,center,right|}"
+        );
+        assert_eq!(
+            snippet("size <width>,<height>"),
+            "size ${1:width},${2:height}"
+        );
+        assert_eq!(
+            snippet("qr_code \"<data>\" [size=<1-16>] [correction=<l|m|q|h>] [model=<1|2|micro>]"),
+            "qr_code \"${1:data}\""
+        );
+        assert_eq!(snippet("codepage <auto|name>"), "codepage ${1:auto}");
+        assert_eq!(
+            snippet("row <column>,... \"<cell>\" ..."),
+            "row ${1:column} \"${2:cell}\""
+        );
+        assert_eq!(
+            snippet("repeat <count> { ... }"),
+            "repeat ${1:count} {\n\t$0\n}"
+        );
+        assert_eq!(
+            snippet("if {{<variable>}} { ... } else { ... }"),
+            "if {{${1:variable}}} {\n\t$0\n} else {\n\t\n}"
+        );
+    }
+
+    #[test]
+    fn test_textmate_grammar() {
+        let grammar: Value = serde_json::from_str(&generate_textmate_grammar()).unwrap();
+        let repository = &grammar["repository"];
+        let commands = repository["command"]["match"].as_str().unwrap();
+        assert!(commands.contains("|writeln|"));
+        assert!(commands.find("writeln") < commands.find("|write|"));
+        let options = repository["option"]["match"].as_str().unwrap();
+        assert!(options.contains("|hri_font|"));
+        let constants = repository["constant"]["match"].as_str().unwrap();
+        assert!(constants.contains("|floyd_steinberg|"));
+        assert!(repository["control"]["match"]
+            .as_str()
+            .unwrap()
+            .contains("repeat"));
+    }
+}
@@ -0,0 +1,302 @@
+//! Evaluation of control flow blocks and template variables into a straight-line [`Program`]
+use serde_json::Value;
+use std::cell::Cell;
+
+use super::parser::position;
+use super::template::{self, Variables};
+use super::{Block, Count, Diagnostic, Program};
+
+impl Program {
+    /// Parses a program source and evaluates its `repeat`, `if` and `for` blocks
+    ///
+    /// If `variables` are given, placeholders in every command line are expanded
+    /// before the line is parsed. Without variables, command lines are parsed as-is
+    /// and block conditions see no variables.
+    pub fn compile(input: &str, variables: Option<&Variables>) -> Result<Program, Diagnostic> {
+        let blocks = match Program::parse_blocks(input) {
+            Ok((remains, blocks)) if remains.trim().is_empty() => blocks,
+            Ok((remains, _)) => return Err(Diagnostic::at(input, remains)),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                return Err(Diagnostic::at(input, e.input))
+            }
+            Err(nom::Err::Incomplete(_)) => return Err(Diagnostic::at(input, "")),
+        };
+
+        let evaluator = Evaluator {
+            input,
+            expand: variables.is_some(),
+            steps: Cell::new(0),
+        };
+        let scope = variables.cloned().unwrap_or_default();
+        let mut program = Program { commands: vec![] };
+        evaluator.run(&blocks, &scope, &mut program)?;
+
+        Ok(program)
+    }
+}
+
+/// Lines and block iterations a program may evaluate to, so that nested
+/// blocks can't expand into an endless job
+const MAX_STEPS: usize = 100_000;
+
+struct Evaluator<'a> {
+    input: &'a str,
+    expand: bool,
+    /// Lines and iterations evaluated so far
+    steps: Cell<usize>,
+}
+
+impl Evaluator<'_> {
+    fn error(&self, at: &str, message: String) -> Diagnostic {
+        let (line, column) = position(self.input, at);
+        Diagnostic::new(line, column, message)
+    }
+
+    /// Counts a line or iteration at `at` against [`MAX_STEPS`]
+    fn step(&self, at: &str) -> Result<(), Diagnostic> {
+        self.steps.set(self.steps.get() + 1);
+        if self.steps.get() > MAX_STEPS {
+            return Err(self.error(
+                at,
+                format!(
+                    "blocks expand to more than {} lines and iterations",
+                    MAX_STEPS
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn run(
+        &self,
+        blocks: &[Block],
+        scope: &Variables,
+        out: &mut Program,
+    ) -> Result<(), Diagnostic> {
+        for block in blocks {
+            match block {
+                Block::Line(source) => self.line(source, scope, out)?,
+                Block::Repeat { at, count, body } => {
+                    let count = match count {
+                        Count::Fixed(count) => *count,
+                        Count::Variable(expression) => match expression.value(scope) {
+                            None | Some(Value::Null) => Some(0),
+                            Some(Value::Number(number)) => number.as_u64(),
+                            Some(Value::String(string)) => string.trim().parse().ok(),
+                            Some(_) => None,
+                        }
+                        .ok_or_else(|| {
+                            self.error(
+                                at,
+                                format!(
+                                    "repeat count {} is not a non-negative number",
+                                    expression.path.join(".")
+                                ),
+                            )
+                        })
+                        .and_then(|count| {
+                            // Same range as fixed counts
+                            u16::try_from(count).map_err(|_| {
+                                self.error(
+                                    at,
+                                    format!(
+                                        "repeat count {} is larger than {}",
+                                        expression.path.join("."),
+                                        u16::MAX
+                                    ),
+                                )
+                            })
+                        })?,
+                    };
+                    for _ in 0..count {
+                        self.step(at)?;
+                        self.run(body, scope, out)?;
+                    }
+                }
+                Block::If {
+                    condition,
+                    then,
+                    otherwise,
+                    ..
+                } => {
+                    let branch = if condition.value(scope).is_some_and(|v| truthy(&v)) {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    self.run(branch, scope, out)?;
+                }
+                Block::ForEach {
+                    at,
+                    variable,
+                    items,
+                    body,
+                } => {
+                    let items = match items.value(scope) {
+                        Some(Value::Array(items)) => items,
+                        None | Some(Value::Null) => vec![],
+                        Some(_) => {
+                            return Err(
+                                self.error(at, format!("{} is not a list", items.path.join(".")))
+                            )
+                        }
+                    };
+                    let mut inner = scope.clone();
+                    for item in items {
+                        self.step(at)?;
+                        inner.insert(variable.clone(), item);
+                        self.run(body, &inner, out)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn line(&self, source: &str, scope: &Variables, out: &mut Program) -> Result<(), Diagnostic> {
+        self.step(source)?;
+        let (line, column) = position(self.input, source);
+
+        let text = if self.expand {
+            template::expand(source, scope).map_err(|err| self.error(source, err.to_string()))?
+        } else {
+            source.to_string()
+        };
+
+        let program = Program::parse_complete(&text).map_err(|mut diagnostic| {
+            diagnostic.line = line;
+            diagnostic.column += column - 1;
+            diagnostic
+        })?;
+        out.commands.extend(program.commands);
+
+        Ok(())
+    }
+}
+
+/// Whether a value counts as set for `if` blocks
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(number) => number.as_f64() != Some(0.0),
+        Value::String(string) => !string.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer;
+    use crate::program::Command;
+    use serde_json::json;
+
+    fn write(text: &str) -> Command {
+        Command::Raw(printer::Command::Write(String::from(text)))
+    }
+
+    fn compile(input: &str, variables: Value) -> Result<Program, Diagnostic> {
+        let Value::Object(variables) = variables else {
+            panic!("variables must be an object");
+        };
+        Program::compile(input, Some(&variables))
+    }
+
+    #[test]
+    fn test_repeat_and_if() {
+        let source = "repeat 2 {\n  write \"-\"\n}\n\nif {{member}} {\n  writeln \"Member\"\n} else {\n  writeln \"Guest\"\n}\nrepeat {{n}} {\n  cut\n}";
+
+        assert_eq!(
+            compile(source, json!({ "member": false, "n": "1" })),
+            Ok(Program {
+                commands: vec![
+                    write("-"),
+                    write("-"),
+                    write("Guest\n"),
+                    Command::Raw(printer::Command::Cut),
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_for_each_nested() {
+        let source = "for item in {{items}} {\n  writeln \"{{item.name}}\"\n  if {{item.done}} {\n    writeln \"done\"\n  }\n}";
+        let variables = json!({ "items": [{ "name": "Tea", "done": true }, { "name": "Cake" }] });
+
+        assert_eq!(
+            compile(source, variables),
+            Ok(Program {
+                commands: vec![write("Tea\n"), write("done\n"), write("Cake\n")]
+            })
+        );
+    }
+
+    #[test]
+    fn test_block_diagnostics() {
+        let diagnostic = compile(
+            "feed\nfor x in {{items}} {\n  writln \"{{x}}\"\n}",
+            json!({ "items": [1] }),
+        )
+        .unwrap_err();
+        assert_eq!((diagnostic.line, diagnostic.column), (3, 3));
+        assert_eq!(diagnostic.suggestion.as_deref(), Some("writeln"));
+
+        // Unclosed block
+        let diagnostic = compile("feed\nfor x in {{items}} {\n  feed\n", json!({})).unwrap_err();
+        assert_eq!(diagnostic.line, 2);
+        assert_eq!(
+            diagnostic.expected,
+            vec!["for <item> in {{<list>}} { ... }"]
+        );
+
+        let diagnostic = compile("for x in {{items}} {\n}", json!({ "items": 3 })).unwrap_err();
+        assert_eq!(diagnostic.message, "items is not a list");
+    }
+
+    #[test]
+    fn test_expansion_limits() {
+        let diagnostic =
+            compile("feed\nrepeat {{n}} {\n  feed\n}", json!({ "n": 70000 })).unwrap_err();
+        assert_eq!(diagnostic.line, 2);
+        assert_eq!(diagnostic.message, "repeat count n is larger than 65535");
+
+        let diagnostic = compile(
+            "repeat 1000 {\n  repeat 1000 {\n    repeat 1000 {\n    }\n  }\n}",
+            json!({}),
+        )
+        .unwrap_err();
+        assert_eq!(
+            diagnostic.message,
+            "blocks expand to more than 100000 lines and iterations"
+        );
+    }
+
+    #[test]
+    fn test_multi_line_strings() {
+        let source = "if {{note}} {\n  markdown \"# {{note}}\n\n- }\"\n}\ncut";
+
+        assert_eq!(
+            compile(source, json!({ "note": "Hi" })),
+            Ok(Program {
+                commands: vec![
+                    Command::Markdown(String::from("# Hi\n\n- }")),
+                    Command::Raw(printer::Command::Cut),
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_plain_programs_are_not_expanded() {
+        assert_eq!(
+            Program::compile("writeln \"{{literal}}\"", None),
+            Ok(Program {
+                commands: vec![write("{{literal}}\n")]
+            })
+        );
+    }
+}
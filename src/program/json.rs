@@ -0,0 +1,9 @@
+//! Programs as JSON arrays of commands, an alternative to the DSL for jobs
+//! built by code
+//!
+//! The format follows the serde representation of
+//! [`Command`](super::Command): commands without arguments are strings like
+//! `"cut"`, all others objects with a single key, e.g. `{"justify": "center"}`.
+//! Options may be left out for their defaults. The format is described by the
+//! JSON Schema of print jobs, which is published as `job.schema.json` with the
+//! documentation.
@@ -0,0 +1,152 @@
+//! Static checks of program sources, as done by the `escpos2mqtt-lint` binary
+//!
+//! Unlike [`Program::compile`], every command line is checked, including the
+//! bodies of blocks whose conditions depend on template variables.
+use std::fmt;
+
+use serde::Serialize;
+
+use super::parser::position;
+use super::{Block, Diagnostic, Program};
+use crate::renderer::{validate, Severity};
+
+/// A problem found in a program source
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    #[serde(flatten)]
+    pub diagnostic: Diagnostic,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let diagnostic = &self.diagnostic;
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            diagnostic.line, diagnostic.column, self.severity, diagnostic.message
+        )?;
+        if let Some(suggestion) = &diagnostic.suggestion {
+            write!(f, " (did you mean {}?)", suggestion)?;
+        }
+        if !diagnostic.expected.is_empty() {
+            write!(f, ", expected {}", diagnostic.expected.join(" or "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks a program source, and its commands against a printer profile if one
+/// is given
+///
+/// Lines of known commands whose arguments contain placeholders are only
+/// checked if they parse, as their values are known once the job's variables
+/// are expanded.
+pub fn lint(source: &str, profile: Option<&escpos_db::Profile>) -> Vec<Finding> {
+    let error = |diagnostic| Finding {
+        severity: Severity::Error,
+        diagnostic,
+    };
+
+    let blocks = match Program::parse_blocks(source) {
+        Ok((remains, blocks)) if remains.trim().is_empty() => blocks,
+        Ok((remains, _)) => return vec![error(Diagnostic::at(source, remains))],
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            return vec![error(Diagnostic::at(source, e.input))]
+        }
+        Err(nom::Err::Incomplete(_)) => return vec![error(Diagnostic::at(source, ""))],
+    };
+
+    let mut lines = Vec::new();
+    collect_lines(&blocks, &mut lines);
+
+    let mut findings = Vec::new();
+    // Position of every parsed command, to locate the issues of the profile
+    let mut positions = Vec::new();
+    let mut program = Program { commands: vec![] };
+    for line in lines {
+        let (line_number, column) = position(source, line);
+        match Program::parse_complete(line) {
+            Ok(parsed) => {
+                positions.extend(parsed.commands.iter().map(|_| (line_number, column)));
+                program.commands.extend(parsed.commands);
+            }
+            Err(diagnostic) if line.contains("{{") && !diagnostic.expected.is_empty() => {}
+            Err(mut diagnostic) => {
+                diagnostic.line = line_number;
+                diagnostic.column += column - 1;
+                findings.push(error(diagnostic));
+            }
+        }
+    }
+
+    if let Some(profile) = profile {
+        findings.extend(validate(&program, profile).into_iter().map(|issue| {
+            let (line, column) = positions[issue.command];
+            Finding {
+                severity: issue.severity,
+                diagnostic: Diagnostic::new(line, column, issue.message),
+            }
+        }));
+    }
+
+    findings.sort_by_key(|finding| (finding.diagnostic.line, finding.diagnostic.column));
+    findings
+}
+
+/// The command lines of all branches of the blocks, in source order
+fn collect_lines<'a>(blocks: &[Block<'a>], lines: &mut Vec<&'a str>) {
+    for block in blocks {
+        match block {
+            Block::Line(line) => lines.push(line),
+            Block::Repeat { body, .. } | Block::ForEach { body, .. } => collect_lines(body, lines),
+            Block::If {
+                then, otherwise, ..
+            } => {
+                collect_lines(then, lines);
+                collect_lines(otherwise, lines);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn findings(source: &str, profile: Option<&escpos_db::Profile>) -> Vec<String> {
+        lint(source, profile)
+            .iter()
+            .map(Finding::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let source = "writeln \"Hello\"\nif {{paid}} {\n  writln \"PAID\"\n} else {\n  feed {{lines}}\n  bold maybe\n}\ncut\n";
+
+        assert_eq!(
+            findings(source, None),
+            vec![
+                "3:3: error: unknown command `writln` (did you mean writeln?)",
+                "6:8: error: invalid arguments for `bold`, expected bold <true|false>",
+            ]
+        );
+        assert_eq!(
+            findings(source, Some(&escpos_db::ZJ_5870)).last().unwrap(),
+            "8:1: warning: ZJ-5870 Thermal Receipt Printer has no cutter"
+        );
+        assert_eq!(findings(source, Some(&escpos_db::DEFAULT)).len(), 2);
+    }
+
+    #[test]
+    fn test_lint_unclosed_block() {
+        let findings = lint("feed\nrepeat 2 {\n  feed\n", None);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].diagnostic.line, 2);
+        assert_eq!(
+            serde_json::to_value(&findings[0]).unwrap()["severity"],
+            "error"
+        );
+    }
+}
@@ -1,18 +1,393 @@
+use crate::dither;
 use crate::printer;
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use template::Expression;
 
+mod eval;
 mod parser;
+mod json;
+mod writer;
+pub use parser::Diagnostic;
 pub mod documentation;
 pub mod doc_macros;
+pub mod editor;
+pub mod lint;
+pub mod template;
 
-#[derive(Debug, PartialEq, Clone)]
+/// A command of a program
+///
+/// In JSON, commands are written like `{"write": "Hello\n"}`, `"cut"` or
+/// `{"qr_code": ["https://example.com", {"size": 6}]}`. Printer commands are
+/// written without a wrapper.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
 pub enum Command {
-    Raw(printer::Command),
     Sudoku,
+    #[serde(rename = "minicrossword")]
     MiniCrossword,
+    #[serde(rename = "todo")]
     ToDo(String),
+    Markdown(String),
+    /// Rows of cells laid out in columns over the width of the paper
+    Table {
+        columns: Vec<Column>,
+        #[serde(default)]
+        rows: Vec<Vec<String>>,
+    },
+    Image {
+        source: ImageSource,
+        #[serde(default)]
+        options: ImageOptions,
+    },
+    /// An SVG document, rasterized like an image
+    Svg {
+        source: String,
+        #[serde(default)]
+        options: ImageOptions,
+    },
+    /// Text rendered with a TrueType font into an image
+    TextImage {
+        text: String,
+        #[serde(default)]
+        options: TextImageOptions,
+    },
+    Include(String),
+    /// Forces the named code page for the following text, `None` selects code
+    /// pages automatically
+    #[serde(rename = "codepage")]
+    CodePage(Option<String>),
+    #[serde(untagged)]
+    Raw(printer::Command),
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// A program of commands, written in JSON as an array of commands
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
 pub struct Program {
     pub commands: Vec<Command>,
 }
+
+/// A statement of a program source before control flow and template variables
+/// are evaluated. Every block keeps the source slice it starts at for diagnostics.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Block<'a> {
+    /// A single command line, kept as source until its placeholders are expanded
+    Line(&'a str),
+    Repeat {
+        at: &'a str,
+        count: Count,
+        body: Vec<Block<'a>>,
+    },
+    If {
+        at: &'a str,
+        condition: Expression,
+        then: Vec<Block<'a>>,
+        otherwise: Vec<Block<'a>>,
+    },
+    ForEach {
+        at: &'a str,
+        variable: String,
+        items: Expression,
+        body: Vec<Block<'a>>,
+    },
+}
+
+/// Number of iterations of a `repeat` block
+#[derive(Debug, PartialEq, Clone)]
+pub enum Count {
+    Fixed(u16),
+    Variable(Expression),
+}
+
+/// Layout of a column of a `row` or `table` command
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Column {
+    pub width: ColumnWidth,
+    #[serde(default)]
+    pub align: Align,
+    /// Whether the space next to the cell text is filled with dot leaders
+    #[serde(default)]
+    pub dots: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnWidth {
+    /// Number of characters
+    Fixed(
+        #[serde(deserialize_with = "printer::in_range::<_, _, 1, 255>")]
+        #[schemars(range(min = 1))]
+        u8,
+    ),
+    /// Shares the characters left by the fixed columns with other fill columns
+    Fill,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Align {
+    #[default]
+    Left,
+    Right,
+}
+
+/// Where the data of an `image` command comes from
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageSource {
+    /// Base64 encoded image data, optionally as a `data:` URL
+    Base64(String),
+    Url(String),
+    /// Path of a file in the image directory of the server
+    File(String),
+}
+
+/// Widest `width` accepted for images, well above the paper width of any
+/// printer. Images are narrowed to the paper width when rendered anyway.
+pub const MAX_IMAGE_WIDTH: u32 = 2048;
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ImageOptions {
+    /// Width in pixels, narrowed to the paper width, the full paper width if not set
+    #[serde(deserialize_with = "deserialize_width")]
+    #[schemars(range(min = 1, max = MAX_IMAGE_WIDTH))]
+    pub width: Option<u32>,
+    pub dither: dither::Options,
+}
+
+/// Rejects image widths outside of 1-[`MAX_IMAGE_WIDTH`], like the parser of
+/// the DSL
+fn deserialize_width<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    match Option::<u32>::deserialize(deserializer)? {
+        Some(width) if !(1..=MAX_IMAGE_WIDTH).contains(&width) => {
+            Err(serde::de::Error::invalid_value(
+                serde::de::Unexpected::Unsigned(u64::from(width)),
+                &format!("1-{}", MAX_IMAGE_WIDTH).as_str(),
+            ))
+        }
+        width => Ok(width),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct TextImageOptions {
+    /// Path of a `.ttf`, `.otf` or `.ttc` file in the font directory of the
+    /// server, or the family name of an installed font. A sans-serif font if
+    /// not set.
+    pub font: Option<String>,
+    /// Font size in points, 4-72
+    #[serde(deserialize_with = "printer::in_range::<_, _, 4, 72>")]
+    #[schemars(range(min = 4, max = 72))]
+    pub size: u8,
+}
+
+impl Default for TextImageOptions {
+    fn default() -> Self {
+        TextImageOptions {
+            font: None,
+            size: 10,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::codes_2d::{Code2d, QrOptions};
+    use escpos::utils::JustifyMode;
+
+    #[test]
+    fn test_json_program() {
+        let program: Program = serde_json::from_str(
+            r#"[
+                {"justify": "center"},
+                {"write": "Hello\n"},
+                {"barcode": {"symbology": "upce", "data": "0425261"}},
+                {"qr_code": ["https://example.com", {"size": 6}]},
+                {"table": {"columns": [{"width": "fill", "dots": true}, {"width": {"fixed": 8}, "align": "right"}], "rows": [["Coffee", "$3.50"]]}},
+                {"image": {"source": {"url": "http://example.com/logo.png"}, "options": {"width": 256, "dither": {"algorithm": "atkinson"}}}},
+                {"codepage": null},
+                "cut"
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.commands[..2],
+            [
+                Command::Raw(printer::Command::Justify(JustifyMode::CENTER)),
+                Command::Raw(printer::Command::Write(String::from("Hello\n"))),
+            ]
+        );
+        assert_eq!(
+            program.commands[3],
+            Command::Raw(printer::Command::Code2d(Code2d::QrCode(
+                String::from("https://example.com"),
+                QrOptions {
+                    size: 6,
+                    ..QrOptions::default()
+                }
+            )))
+        );
+        assert_eq!(
+            program.commands[4],
+            Command::Table {
+                columns: vec![
+                    Column {
+                        width: ColumnWidth::Fill,
+                        align: Align::Left,
+                        dots: true
+                    },
+                    Column {
+                        width: ColumnWidth::Fixed(8),
+                        align: Align::Right,
+                        dots: false
+                    },
+                ],
+                rows: vec![vec![String::from("Coffee"), String::from("$3.50")]],
+            }
+        );
+        assert!(matches!(
+            &program.commands[5],
+            Command::Image {
+                source: ImageSource::Url(_),
+                options: ImageOptions {
+                    width: Some(256),
+                    ..
+                }
+            }
+        ));
+        assert_eq!(program.commands[6], Command::CodePage(None));
+        assert_eq!(program.commands[7], Command::Raw(printer::Command::Cut));
+
+        // The same program as the DSL, with the barcode validated
+        let dsl = "justify center\nwriteln \"Hello\"\nupce 04252614\n\
+                   qr_code \"https://example.com\" size=6\nrow *., 8> \"Coffee\" \"$3.50\"\n\
+                   image url \"http://example.com/logo.png\" width=256 dither=atkinson\n\
+                   codepage auto\ncut\n";
+        assert_eq!(program.to_dsl(), dsl);
+
+        let json = serde_json::to_string(&program).unwrap();
+        assert_eq!(serde_json::from_str::<Program>(&json).unwrap(), program);
+    }
+
+    #[test]
+    fn test_invalid_json_program() {
+        assert!(serde_json::from_str::<Program>(r#"["cutt"]"#).is_err());
+        assert!(serde_json::from_str::<Program>(
+            r#"[{"barcode": {"symbology": "ean13", "data": "123"}}]"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_json_argument_ranges() {
+        // Each command once at the bounds the DSL accepts and once outside of them
+        let cases = [
+            (r#"{"size": [1, 8]}"#, r#"{"size": [0, 8]}"#),
+            (r#"{"size": [8, 1]}"#, r#"{"size": [8, 9]}"#),
+            (
+                r#"{"barcode": {"symbology": "code39", "data": "A", "options": {"width": 6, "height": 1}}}"#,
+                r#"{"barcode": {"symbology": "code39", "data": "A", "options": {"width": 7}}}"#,
+            ),
+            (
+                r#"{"barcode": {"symbology": "code39", "data": "A", "options": {"width": 1}}}"#,
+                r#"{"barcode": {"symbology": "code39", "data": "A", "options": {"height": 0}}}"#,
+            ),
+            (
+                r#"{"qr_code": ["a", {"size": 16}]}"#,
+                r#"{"qr_code": ["a", {"size": 17}]}"#,
+            ),
+            (
+                r#"{"qr_code": ["a", {"size": 1}]}"#,
+                r#"{"qr_code": ["a", {"size": 0}]}"#,
+            ),
+            (
+                r#"{"pdf417": ["a", {"columns": 30}]}"#,
+                r#"{"pdf417": ["a", {"columns": 31}]}"#,
+            ),
+            (
+                r#"{"pdf417": ["a", {"rows": 90}]}"#,
+                r#"{"pdf417": ["a", {"rows": 91}]}"#,
+            ),
+            (
+                r#"{"pdf417": ["a", {"width": 2}]}"#,
+                r#"{"pdf417": ["a", {"width": 1}]}"#,
+            ),
+            (
+                r#"{"pdf417": ["a", {"row_height": 8}]}"#,
+                r#"{"pdf417": ["a", {"row_height": 9}]}"#,
+            ),
+            (
+                r#"{"pdf417": ["a", {"correction": 8}]}"#,
+                r#"{"pdf417": ["a", {"correction": 9}]}"#,
+            ),
+            (
+                r#"{"datamatrix": ["a", {"size": 2}]}"#,
+                r#"{"datamatrix": ["a", {"size": 1}]}"#,
+            ),
+            (
+                r#"{"aztec": ["a", {"size": 16}]}"#,
+                r#"{"aztec": ["a", {"size": 17}]}"#,
+            ),
+            (
+                r#"{"aztec": ["a", {"correction": 5}]}"#,
+                r#"{"aztec": ["a", {"correction": 4}]}"#,
+            ),
+            (
+                r#"{"table": {"columns": [{"width": {"fixed": 1}}]}}"#,
+                r#"{"table": {"columns": [{"width": {"fixed": 0}}]}}"#,
+            ),
+            (
+                r#"{"text_image": {"text": "a", "options": {"size": 72}}}"#,
+                r#"{"text_image": {"text": "a", "options": {"size": 73}}}"#,
+            ),
+            (
+                r#"{"text_image": {"text": "a", "options": {"size": 4}}}"#,
+                r#"{"text_image": {"text": "a", "options": {"size": 3}}}"#,
+            ),
+            (
+                r#"{"image": {"source": {"file": "a.png"}, "options": {"width": 1}}}"#,
+                r#"{"image": {"source": {"file": "a.png"}, "options": {"width": 0}}}"#,
+            ),
+            (
+                r#"{"image": {"source": {"file": "a.png"}, "options": {"width": 2048}}}"#,
+                r#"{"image": {"source": {"file": "a.png"}, "options": {"width": 2049}}}"#,
+            ),
+            (
+                r#"{"svg": {"source": "<svg/>", "options": {"dither": {"brightness": -1.0, "contrast": 0.0}}}}"#,
+                r#"{"svg": {"source": "<svg/>", "options": {"dither": {"brightness": 1.5}}}}"#,
+            ),
+            (
+                r#"{"svg": {"source": "<svg/>", "options": {"dither": {"gamma": 0.1}}}}"#,
+                r#"{"svg": {"source": "<svg/>", "options": {"dither": {"contrast": -1.0}}}}"#,
+            ),
+            (
+                r#"{"svg": {"source": "<svg/>", "options": {"dither": {"brightness": 1.0}}}}"#,
+                r#"{"svg": {"source": "<svg/>", "options": {"dither": {"gamma": 0.0}}}}"#,
+            ),
+        ];
+        for (valid, invalid) in cases {
+            assert!(
+                serde_json::from_str::<Command>(valid).is_ok(),
+                "{} was rejected",
+                valid
+            );
+            assert!(
+                serde_json::from_str::<Command>(invalid).is_err(),
+                "{} was accepted",
+                invalid
+            );
+        }
+
+        assert_eq!(
+            serde_json::from_str::<QrOptions>(r#"{"size": 17}"#)
+                .unwrap_err()
+                .to_string(),
+            "invalid value: integer `17`, expected 1-16 at line 1 column 12"
+        );
+    }
+}
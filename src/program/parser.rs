@@ -1,21 +1,42 @@
+use crate::dither;
 use crate::printer;
+use crate::printer::barcode::{Barcode, BarcodeOptions, HriFont, HriPosition, Symbology};
+use crate::printer::codes_2d::{
+    AztecOptions, Code2d, DataMatrixOptions, Pdf417Options, QrCorrection, QrModel, QrOptions,
+};
 use escpos::utils::Font;
 use escpos::utils::JustifyMode;
 use escpos::utils::UnderlineMode;
 use nom::branch::alt;
-use nom::bytes::complete::escaped_transform;
+use nom::bytes::complete::is_not;
 use nom::bytes::complete::tag;
 use nom::bytes::complete::tag_no_case;
+use nom::bytes::complete::take_till1;
+use nom::bytes::complete::take_while1;
 use nom::bytes::complete::take_while_m_n;
-use nom::bytes::is_not;
+use nom::character::complete::anychar;
 use nom::character::complete::line_ending;
+use nom::character::complete::none_of;
 use nom::character::complete::space0;
 use nom::character::complete::space1;
+use nom::character::complete::u16;
+use nom::character::complete::u32;
 use nom::character::complete::u8;
+use nom::combinator::all_consuming;
 use nom::combinator::eof;
 use nom::combinator::map;
+use nom::combinator::map_opt;
+use nom::combinator::map_res;
+use nom::combinator::not;
+use nom::combinator::opt;
+use nom::combinator::recognize;
 use nom::combinator::value;
+use nom::combinator::verify;
+use nom::multi::fold_many0;
 use nom::multi::many0;
+use nom::multi::many1;
+use nom::multi::separated_list1;
+use nom::number::complete::float;
 use nom::sequence::delimited;
 use nom::sequence::pair;
 use nom::sequence::preceded;
@@ -23,9 +44,134 @@ use nom::sequence::separated_pair;
 use nom::sequence::terminated;
 use nom::AsChar;
 use nom::IResult;
+use nom::Offset;
 use nom::Parser;
+use serde::{Deserialize, Serialize};
 
-use crate::program::{Command, Program};
+use crate::program::documentation::{all_commands, Argument, ArgumentKind};
+use crate::program::template::Expression;
+use crate::program::{
+    Align, Block, Column, ColumnWidth, Command, Count, ImageOptions, ImageSource, Program,
+    TextImageOptions, MAX_IMAGE_WIDTH,
+};
+
+/// A problem found while parsing a program, pointing at the offending source location
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// 1-based line number
+    pub line: usize,
+    /// 1-based column number
+    pub column: usize,
+    pub message: String,
+    /// Syntax of the command(s) expected at this location
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub expected: Vec<String>,
+    /// Closest known command name if the command was misspelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )?;
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, " (did you mean {}?)", suggestion)?;
+        }
+        if !self.expected.is_empty() {
+            write!(f, ", expected {}", self.expected.join(" or "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Diagnostic {
+    pub(super) fn new(line: usize, column: usize, message: String) -> Diagnostic {
+        Diagnostic {
+            line,
+            column,
+            message,
+            expected: vec![],
+            suggestion: None,
+        }
+    }
+
+    /// Builds a diagnostic for the line of `input` where parsing stopped at `remains`
+    pub(super) fn at(input: &str, remains: &str) -> Diagnostic {
+        let consumed = &input[..input.len() - remains.len()];
+        let line = consumed.matches('\n').count() + 1;
+        let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+        let text = input[line_start..].lines().next().unwrap_or("");
+        let column = |byte: usize| text[..byte].chars().count() + 1;
+
+        let indent = text.len() - text.trim_start().len();
+        let statement = &text[indent..];
+        let keyword = statement
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or("");
+
+        let commands = all_commands();
+        let expected: Vec<String> = commands
+            .iter()
+            .filter(|cmd| cmd.name == keyword)
+            .map(|cmd| cmd.syntax.to_string())
+            .collect();
+
+        if !expected.is_empty() {
+            let arguments = statement[keyword.len()..].trim_start();
+            Diagnostic {
+                line,
+                column: column(text.len() - arguments.len()),
+                message: format!("invalid arguments for `{}`", keyword),
+                expected,
+                suggestion: None,
+            }
+        } else {
+            let suggestion = commands
+                .iter()
+                .map(|cmd| (edit_distance(keyword, cmd.name), cmd.name))
+                .filter(|(distance, _)| *distance <= 2 && *distance < keyword.len())
+                .min()
+                .map(|(_, name)| name.to_string());
+            Diagnostic {
+                line,
+                column: column(indent),
+                message: format!("unknown command `{}`", keyword),
+                expected: vec![],
+                suggestion,
+            }
+        }
+    }
+}
+
+/// 1-based line and column of `at`, a slice of `input`
+pub(super) fn position(input: &str, at: &str) -> (usize, usize) {
+    let consumed = &input[..input.offset(at)];
+    let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+    (
+        consumed.matches('\n').count() + 1,
+        consumed[line_start..].chars().count() + 1,
+    )
+}
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
 
 impl Program {
     pub fn parse(input: &str) -> IResult<&str, Program> {
@@ -50,21 +196,269 @@ impl Program {
             },
         ))
     }
+
+    /// Parses a complete program, reporting the first line that could not be
+    /// parsed as a [`Diagnostic`]
+    pub fn parse_complete(input: &str) -> Result<Program, Diagnostic> {
+        match Program::parse(input) {
+            Ok((remains, program)) if remains.trim().is_empty() => Ok(program),
+            Ok((remains, _)) => Err(Diagnostic::at(input, remains)),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(Diagnostic::at(input, e.input)),
+            Err(nom::Err::Incomplete(_)) => Err(Diagnostic::at(input, "")),
+        }
+    }
+
+    /// Parses the statements of a program, including `repeat`, `if` and `for` blocks
+    pub fn parse_blocks(input: &str) -> IResult<&str, Vec<Block<'_>>> {
+        blocks(input)
+    }
+}
+
+fn line_end(input: &str) -> IResult<&str, &str> {
+    preceded(space0, alt((eof, line_ending))).parse(input)
+}
+
+fn placeholder(input: &str) -> IResult<&str, Expression> {
+    delimited(tag("{{"), Expression::parse, tag("}}")).parse(input)
+}
+
+fn count(input: &str) -> IResult<&str, Count> {
+    alt((map(u16, Count::Fixed), map(placeholder, Count::Variable))).parse(input)
+}
+
+fn identifier(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || c == '_').parse(input)
 }
 
+/// A `{ ... }` block body, the closing brace on its own line
+fn body(input: &str) -> IResult<&str, Vec<Block<'_>>> {
+    delimited((space0, tag("{"), line_end), blocks, (space0, tag("}"))).parse(input)
+}
+
+/// Statements up to the end of input or the closing brace of the enclosing block
+fn blocks(input: &str) -> IResult<&str, Vec<Block<'_>>> {
+    map(
+        many0(alt((
+            // Parse a statement
+            map(delimited(space0, block, line_end), Some),
+            // Skip empty lines
+            map(terminated(space0, line_ending), |_| None),
+        ))),
+        |blocks| blocks.into_iter().flatten().collect(),
+    )
+    .parse(input)
+}
+
+fn block(input: &str) -> IResult<&str, Block<'_>> {
+    alt((control_flow, command_line)).parse(input)
+}
+
+fn control_flow(input: &str) -> IResult<&str, Block<'_>> {
+    crate::documented_parser! {
+        {
+            name: "repeat",
+            syntax: "repeat <count> { ... }",
+            description: "Repeats the enclosed commands. The count may be a number or a {{variable}}",
+            category: ControlFlow,
+            examples: [
+                "repeat 3 {\n  writeln \"-\"\n}",
+                "repeat {{copies}} {\n  todo \"Sign here\"\n}"
+            ],
+            arguments: vec![Argument::integer("count", 0, 65535)],
+            parser: map(
+                pair(preceded(pair(tag("repeat"), space1), count), body),
+                |(count, body)| Block::Repeat { at: input, count, body }
+            )
+        },
+        {
+            name: "if",
+            syntax: "if {{<variable>}} { ... } else { ... }",
+            description: "Runs the enclosed commands if the variable is set and not false, empty or zero. The else branch is optional",
+            category: ControlFlow,
+            examples: [
+                "if {{member}} {\n  writeln \"Member discount\"\n}",
+                "if {{paid}} {\n  writeln \"PAID\"\n} else {\n  writeln \"DUE\"\n}"
+            ],
+            arguments: vec![Argument::new("variable", ArgumentKind::Placeholder)],
+            parser: map(
+                (
+                    preceded(pair(tag("if"), space1), placeholder),
+                    body,
+                    opt(preceded((space0, tag("else")), body)),
+                ),
+                |(condition, then, otherwise)| Block::If {
+                    at: input,
+                    condition,
+                    then,
+                    otherwise: otherwise.unwrap_or_default(),
+                }
+            )
+        },
+        {
+            name: "for",
+            syntax: "for <item> in {{<list>}} { ... }",
+            description: "Runs the enclosed commands once for every element of a list variable, available as {{<item>}}",
+            category: ControlFlow,
+            examples: [
+                "for item in {{items}} {\n  todo \"{{item}}\"\n}",
+                "for line in {{order.lines}} {\n  writeln \"{{line.name}}\"\n}"
+            ],
+            arguments: vec![
+                Argument::new("item", ArgumentKind::Name),
+                Argument::new("list", ArgumentKind::Placeholder)
+            ],
+            parser: map(
+                (
+                    preceded(pair(tag("for"), space1), identifier),
+                    preceded((space1, tag("in"), space1), placeholder),
+                    body,
+                ),
+                |(variable, items, body)| Block::ForEach {
+                    at: input,
+                    variable: variable.to_string(),
+                    items,
+                    body,
+                }
+            )
+        }
+    }
+    .parse(input)
+}
+
+/// A command line that is not a block, parsed once its placeholders are expanded.
+/// Quoted strings may continue over several lines.
+fn command_line(input: &str) -> IResult<&str, Block<'_>> {
+    map(
+        preceded(
+            not(alt((
+                tag("}"),
+                terminated(alt((tag("repeat"), tag("if"), tag("for"))), space1),
+            ))),
+            recognize(many1(alt((
+                quoted,
+                take_till1(|c| c == '"' || c == '\r' || c == '\n'),
+            )))),
+        ),
+        |line: &str| Block::Line(line.trim_end()),
+    )
+    .parse(input)
+}
+
+/// A quoted or triple-quoted string including its quotes, without resolving escapes
+fn quoted(input: &str) -> IResult<&str, &str> {
+    alt((
+        recognize((tag(TRIPLE_QUOTE), triple_quoted_content, tag(TRIPLE_QUOTE))),
+        recognize((
+            tag("\""),
+            many0(alt((preceded(tag("\\"), anychar), none_of("\\\"")))),
+            tag("\""),
+        )),
+    ))
+    .parse(input)
+}
+
+const TRIPLE_QUOTE: &str = "\"\"\"";
+
+/// A string literal, either `"..."` or a triple-quoted `"""..."""` heredoc which
+/// may contain unescaped quotes. Both support the escapes `\\`, `\"`, `\n`, `\r`,
+/// `\t` and `\u{<hex>}`.
 fn escaped_string(input: &str) -> IResult<&str, String> {
-    delimited(
-        tag("\""),
-        escaped_transform(
-            is_not("\\\""),
-            '\\',
-            alt((value("\\", tag("\\")), value("\"", tag("\"")))),
+    alt((
+        map_res(
+            delimited(tag(TRIPLE_QUOTE), triple_quoted_content, tag(TRIPLE_QUOTE)),
+            |content| {
+                let content = dedent(content);
+                let result = all_consuming(string_fragments(is_not("\\")))
+                    .parse(content.as_str())
+                    .map(|(_, string)| string)
+                    .map_err(|err| err.to_string());
+                result
+            },
         ),
-        tag("\""),
+        delimited(tag("\""), string_fragments(is_not("\\\"")), tag("\"")),
+    ))
+    .parse(input)
+}
+
+/// Raw content of a triple-quoted string, up to the first unescaped `"""`
+fn triple_quoted_content(input: &str) -> IResult<&str, &str> {
+    recognize(many0(alt((
+        preceded(tag("\\"), anychar),
+        preceded(not(tag(TRIPLE_QUOTE)), anychar),
+    ))))
+    .parse(input)
+}
+
+/// Literal text accepted by `literal`, interleaved with escape sequences
+fn string_fragments<'a>(
+    literal: impl Parser<&'a str, Output = &'a str, Error = nom::error::Error<&'a str>>,
+) -> impl Parser<&'a str, Output = String, Error = nom::error::Error<&'a str>> {
+    fold_many0(
+        alt((
+            map(literal, StringFragment::Literal),
+            map(preceded(tag("\\"), escape), StringFragment::Escaped),
+        )),
+        String::new,
+        |mut string, fragment| {
+            match fragment {
+                StringFragment::Literal(literal) => string.push_str(literal),
+                StringFragment::Escaped(c) => string.push(c),
+            }
+            string
+        },
     )
+}
+
+enum StringFragment<'a> {
+    Literal(&'a str),
+    Escaped(char),
+}
+
+fn escape(input: &str) -> IResult<&str, char> {
+    alt((
+        value('\\', tag("\\")),
+        value('"', tag("\"")),
+        value('\n', tag("n")),
+        value('\r', tag("r")),
+        value('\t', tag("t")),
+        map_opt(
+            delimited(
+                tag("u{"),
+                take_while_m_n(1, 6, |c: char| c.is_ascii_hexdigit()),
+                tag("}"),
+            ),
+            |hex| u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+        ),
+    ))
     .parse(input)
 }
 
+/// Removes the line break after the opening quotes of a triple-quoted string.
+/// If the closing quotes are on their own line, their indentation is removed
+/// from every line, as is the line break before them.
+fn dedent(content: &str) -> String {
+    let content = content
+        .strip_prefix("\r\n")
+        .or_else(|| content.strip_prefix('\n'))
+        .unwrap_or(content);
+
+    let Some((text, indent)) = content.rsplit_once('\n') else {
+        return content.to_string();
+    };
+    if !indent.chars().all(|c| c == ' ' || c == '\t') {
+        return content.to_string();
+    }
+    let text = text.strip_suffix('\r').unwrap_or(text);
+
+    text.split('\n')
+        .map(|line| {
+            line.strip_prefix(indent)
+                .unwrap_or_else(|| line.trim_start_matches([' ', '\t']))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn bool(input: &str) -> IResult<&str, bool> {
     alt((
         map(tag_no_case("true"), |_| true),
@@ -100,275 +494,1106 @@ fn justify_mode(input: &str) -> IResult<&str, JustifyMode> {
     .parse(input)
 }
 
-impl Command {
-    pub fn parse(input: &str) -> IResult<&str, Command> {
-        crate::documented_parser! {
-            {
-                name: "write",
-                syntax: "write \"<text>\"",
-                description: "Outputs text to the printer without a line break at the end",
-                category: Text,
-                examples: [
-                    "write \"Hello World\"",
-                    "write \"Price: $19.99\""
-                ],
-                parser: map(
-                    preceded(pair(tag("write"), space1), escaped_string),
-                    |string| Command::Raw(printer::Command::Write(string))
-                )
-            },
-            {
-                name: "writeln",
-                syntax: "writeln \"<text>\"",
-                description: "Outputs text to the printer followed by a line break",
-                category: Text,
-                examples: [
-                    "writeln \"Hello World\"",
-                    "writeln \"Order #12345\""
-                ],
-                parser: map(
-                    preceded(pair(tag("writeln"), space1), escaped_string),
-                    |string| Command::Raw(printer::Command::Write(string + "\n"))
-                )
-            },
-            {
-                name: "bold",
-                syntax: "bold <true|false>",
-                description: "Enables or disables bold text",
-                category: Formatting,
-                examples: [
-                    "bold true",
-                    "bold false"
-                ],
-                parser: map(
-                    preceded(pair(tag("bold"), space1), bool),
-                    |mode| Command::Raw(printer::Command::Bold(mode))
-                )
-            },
-            {
-                name: "underline",
-                syntax: "underline <none|single|double>",
-                description: "Sets the underline mode for text",
-                category: Formatting,
-                examples: [
-                    "underline none",
-                    "underline single",
-                    "underline double"
-                ],
-                parser: map(
-                    preceded(pair(tag("underline"), space1), underline_mode),
-                    |mode| Command::Raw(printer::Command::Underline(mode))
-                )
-            },
-            {
-                name: "double_strike",
-                syntax: "double_strike <true|false>",
-                description: "Enables or disables double-strike for text",
-                category: Formatting,
-                examples: [
-                    "double_strike true",
-                    "double_strike false"
-                ],
-                parser: map(
-                    preceded(pair(tag("double_strike"), space1), bool),
-                    |mode| Command::Raw(printer::Command::DoubleStrike(mode))
-                )
-            },
-            {
-                name: "font",
-                syntax: "font <a|b|c>",
-                description: "Sets the font type. Available fonts depend on printer model and might fallback to another font if unavailable.",
-                category: Formatting,
-                examples: [
-                    "font a",
-                    "font b",
-                    "font c"
-                ],
-                parser: map(
-                    preceded(pair(tag("font"), space1), font),
-                    |f| Command::Raw(printer::Command::Font(f))
-                )
-            },
-            {
-                name: "flip",
-                syntax: "flip <true|false>",
-                description: "Flips text 180 degrees",
-                category: Formatting,
-                examples: [
-                    "flip true",
-                    "flip false"
-                ],
-                parser: map(
-                    preceded(pair(tag("flip"), space1), bool),
-                    |flip| Command::Raw(printer::Command::Flip(flip))
-                )
-            },
-            {
-                name: "justify",
-                syntax: "justify <left|center|right>",
-                description: "Sets text justification/alignment",
-                category: Layout,
-                examples: [
-                    "justify left",
-                    "justify center",
-                    "justify right"
-                ],
-                parser: map(
-                    preceded(pair(tag("justify"), space1), justify_mode),
-                    |mode| Command::Raw(printer::Command::Justify(mode))
-                )
-            },
-            {
-                name: "reverse",
-                syntax: "reverse <true|false>",
-                description: "Enables or disables inverted text colors (white text on black background)",
-                category: Formatting,
-                examples: [
-                    "reverse true",
-                    "reverse false"
-                ],
-                parser: map(
-                    preceded(pair(tag("reverse"), space1), bool),
-                    |reverse| Command::Raw(printer::Command::Reverse(reverse))
-                )
-            },
-            {
-                name: "feed",
-                syntax: "feed <lines>",
-                description: "Feeds paper forward by the specified number of lines",
-                category: Layout,
-                examples: [
-                    "feed 1",
-                    "feed 3",
-                    "feed 10"
-                ],
-                parser: map(
-                    preceded(pair(tag("feed"), space1), u8),
-                    |lines| Command::Raw(printer::Command::Feed(lines))
-                )
-            },
-            {
-                name: "feed",
-                syntax: "feed",
-                description: "Feeds paper forward by 1 line (default)",
-                category: Layout,
-                examples: ["feed"],
-                parser: map(tag("feed"), |_| Command::Raw(printer::Command::Feed(1)))
-            },
-            {
-                name: "ean13",
-                syntax: "ean13 <12-13 digits>",
-                description: "Prints an EAN-13 barcode (12 or 13 digits)",
-                category: Barcodes,
-                examples: [
-                    "ean13 1234567890123",
-                    "ean13 123456789012"
-                ],
-                parser: map(
+/// A column spec like `*`, `12>` or `*<.`: a width (`*` to fill the line), an
+/// optional alignment (`<` left, `>` right) and `.` for dot leaders
+fn column(input: &str) -> IResult<&str, Column> {
+    map(
+        (
+            alt((
+                value(ColumnWidth::Fill, tag("*")),
+                map(verify(u8, |width| *width > 0), ColumnWidth::Fixed),
+            )),
+            opt(alt((
+                value(Align::Left, tag("<")),
+                value(Align::Right, tag(">")),
+            ))),
+            opt(tag(".")),
+        ),
+        |(width, align, dots)| Column {
+            width,
+            align: align.unwrap_or(Align::Left),
+            dots: dots.is_some(),
+        },
+    )
+    .parse(input)
+}
+
+fn columns(input: &str) -> IResult<&str, Vec<Column>> {
+    separated_list1((space0, tag(","), space0), column).parse(input)
+}
+
+/// Splits table text into rows of `|`-separated cells, padding missing cells.
+/// Fails if a row has more cells than there are columns.
+fn table_rows(text: &str, columns: usize) -> Option<Vec<Vec<String>>> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut cells = table_cells(line);
+            if cells.len() > columns {
+                return None;
+            }
+            cells.resize(columns, String::new());
+            Some(cells)
+        })
+        .collect()
+}
+
+/// Splits a line of a `table` into its trimmed cells at `|`
+///
+/// `\|` and `\\` are a literal `|` and backslash, `\n` and `\r` line breaks
+/// within a cell, and whitespace after a backslash is kept when trimming.
+fn table_cells(line: &str) -> Vec<String> {
+    // Characters and whether they were escaped
+    let mut cells: Vec<Vec<(char, bool)>> = vec![vec![]];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('n')) => Some('\n'),
+            ('\\', Some('r')) => Some('\r'),
+            ('\\', Some(&next)) if next == '|' || next == '\\' || next.is_whitespace() => {
+                Some(next)
+            }
+            _ => None,
+        };
+        let cell = cells.last_mut().unwrap();
+        match escaped {
+            Some(escaped) => {
+                chars.next();
+                cell.push((escaped, true));
+            }
+            None if c == '|' => cells.push(vec![]),
+            None => cell.push((c, false)),
+        }
+    }
+
+    cells
+        .into_iter()
+        .map(|cell| {
+            let blank = |(c, escaped): &(char, bool)| c.is_whitespace() && !escaped;
+            let start = cell.iter().position(|c| !blank(c)).unwrap_or(cell.len());
+            let end = cell
+                .iter()
+                .rposition(|c| !blank(c))
+                .map_or(start, |end| end + 1);
+            cell[start..end].iter().map(|(c, _)| c).collect()
+        })
+        .collect()
+}
+
+fn image_source(input: &str) -> IResult<&str, ImageSource> {
+    alt((
+        map(
+            preceded(pair(tag("url"), space1), escaped_string),
+            ImageSource::Url,
+        ),
+        map(
+            preceded(pair(tag("file"), space1), escaped_string),
+            ImageSource::File,
+        ),
+        map(escaped_string, ImageSource::Base64),
+    ))
+    .parse(input)
+}
+
+/// A single option of an image, `invert` or `<key>=<value>`
+#[derive(Clone)]
+enum ImageOption {
+    Width(u32),
+    Dither(dither::Algorithm),
+    Threshold(u8),
+    Brightness(f32),
+    Contrast(f32),
+    Gamma(f32),
+    Invert,
+}
+
+fn dither_algorithm(input: &str) -> IResult<&str, dither::Algorithm> {
+    alt((
+        value(dither::Algorithm::FloydSteinberg, tag("floyd_steinberg")),
+        value(dither::Algorithm::Atkinson, tag("atkinson")),
+        value(dither::Algorithm::Bayer, tag("bayer")),
+        value(dither::Algorithm::Threshold, tag("threshold")),
+    ))
+    .parse(input)
+}
+
+fn image_option(input: &str) -> IResult<&str, ImageOption> {
+    alt((
+        map(
+            preceded(
+                tag("width="),
+                verify(u32, |width| (1..=MAX_IMAGE_WIDTH).contains(width)),
+            ),
+            ImageOption::Width,
+        ),
+        map(
+            preceded(tag("dither="), dither_algorithm),
+            ImageOption::Dither,
+        ),
+        map(preceded(tag("threshold="), u8), ImageOption::Threshold),
+        map(
+            preceded(
+                tag("brightness="),
+                verify(float, |b: &f32| (-1.0..=1.0).contains(b)),
+            ),
+            ImageOption::Brightness,
+        ),
+        map(
+            preceded(tag("contrast="), verify(float, |c: &f32| *c >= 0.0)),
+            ImageOption::Contrast,
+        ),
+        map(
+            preceded(tag("gamma="), verify(float, |g: &f32| *g > 0.0)),
+            ImageOption::Gamma,
+        ),
+        value(ImageOption::Invert, tag("invert")),
+    ))
+    .parse(input)
+}
+
+fn image_options(input: &str) -> IResult<&str, ImageOptions> {
+    fold_many0(
+        preceded(space1, image_option),
+        ImageOptions::default,
+        |mut options, option| {
+            match option {
+                ImageOption::Width(width) => options.width = Some(width),
+                ImageOption::Dither(algorithm) => options.dither.algorithm = algorithm,
+                ImageOption::Threshold(threshold) => options.dither.threshold = threshold,
+                ImageOption::Brightness(brightness) => options.dither.brightness = brightness,
+                ImageOption::Contrast(contrast) => options.dither.contrast = contrast,
+                ImageOption::Gamma(gamma) => options.dither.gamma = gamma,
+                ImageOption::Invert => options.dither.invert = true,
+            }
+            options
+        },
+    )
+    .parse(input)
+}
+
+#[derive(Clone)]
+enum TextImageOption {
+    Font(String),
+    Size(u8),
+}
+
+fn text_image_options(input: &str) -> IResult<&str, TextImageOptions> {
+    fold_many0(
+        preceded(
+            space1,
+            alt((
+                map(
+                    preceded(tag("font="), escaped_string),
+                    TextImageOption::Font,
+                ),
+                map(ranged("size", 4..=72), TextImageOption::Size),
+            )),
+        ),
+        TextImageOptions::default,
+        |mut options, option| {
+            match option {
+                TextImageOption::Font(font) => options.font = Some(font),
+                TextImageOption::Size(size) => options.size = size,
+            }
+            options
+        },
+    )
+    .parse(input)
+}
+
+#[derive(Clone)]
+enum BarcodeOption {
+    Hri(HriPosition),
+    HriFont(HriFont),
+    Width(u8),
+    Height(u8),
+}
+
+fn barcode_option(input: &str) -> IResult<&str, BarcodeOption> {
+    alt((
+        map(
+            preceded(
+                tag("hri="),
+                alt((
+                    value(HriPosition::None, tag("none")),
+                    value(HriPosition::Above, tag("above")),
+                    value(HriPosition::Below, tag("below")),
+                    value(HriPosition::Both, tag("both")),
+                )),
+            ),
+            BarcodeOption::Hri,
+        ),
+        map(
+            preceded(
+                tag("hri_font="),
+                alt((
+                    value(HriFont::A, tag_no_case("a")),
+                    value(HriFont::B, tag_no_case("b")),
+                )),
+            ),
+            BarcodeOption::HriFont,
+        ),
+        map(
+            preceded(tag("width="), verify(u8, |width| (1..=6).contains(width))),
+            BarcodeOption::Width,
+        ),
+        map(
+            preceded(tag("height="), verify(u8, |height| *height > 0)),
+            BarcodeOption::Height,
+        ),
+    ))
+    .parse(input)
+}
+
+fn barcode_options(input: &str) -> IResult<&str, BarcodeOptions> {
+    fold_many0(
+        preceded(space1, barcode_option),
+        BarcodeOptions::default,
+        |mut options, option| {
+            match option {
+                BarcodeOption::Hri(hri) => options.hri = hri,
+                BarcodeOption::HriFont(font) => options.hri_font = font,
+                BarcodeOption::Width(width) => options.width = width,
+                BarcodeOption::Height(height) => options.height = height,
+            }
+            options
+        },
+    )
+    .parse(input)
+}
+
+fn barcode_digits(input: &str) -> IResult<&str, String> {
+    map(take_while1(AsChar::is_dec_digit), String::from).parse(input)
+}
+
+/// Arguments of a barcode command with the given data
+fn barcode_arguments(data: Argument) -> Vec<Argument> {
+    vec![
+        data,
+        Argument::choice("hri", &["none", "above", "below", "both"]).option(),
+        Argument::choice("hri_font", &["a", "b"]).option(),
+        Argument::integer("width", 1, 6).option(),
+        Argument::integer("height", 1, 255).option(),
+    ]
+}
+
+/// Arguments of an image command with the given source
+fn image_arguments(mut source: Vec<Argument>) -> Vec<Argument> {
+    source.extend([
+        Argument::integer("width", 1, i64::from(MAX_IMAGE_WIDTH)).option(),
+        Argument::choice(
+            "dither",
+            &["floyd_steinberg", "atkinson", "bayer", "threshold"],
+        )
+        .option(),
+        Argument::integer("threshold", 0, 255).option(),
+        Argument::number("brightness", Some(-1.0), Some(1.0)).option(),
+        Argument::number("contrast", Some(0.0), None).option(),
+        Argument::number("gamma", Some(0.0), None).option(),
+        Argument::flag("invert"),
+    ]);
+    source
+}
+
+/// Barcode data followed by options, validated for the symbology
+fn barcode<'a>(
+    symbology: Symbology,
+    data: impl Parser<&'a str, Output = String, Error = nom::error::Error<&'a str>>,
+) -> impl Parser<&'a str, Output = Command, Error = nom::error::Error<&'a str>> {
+    map_res(pair(data, barcode_options), move |(data, options)| {
+        Barcode::new(symbology, &data, options)
+            .map(|barcode| Command::Raw(printer::Command::Barcode(barcode)))
+    })
+}
+
+#[derive(Clone)]
+enum QrOption {
+    Size(u8),
+    Correction(QrCorrection),
+    Model(QrModel),
+}
+
+#[derive(Clone)]
+enum Pdf417Option {
+    Columns(u8),
+    Rows(u8),
+    Width(u8),
+    RowHeight(u8),
+    Correction(u8),
+    Truncated,
+}
+
+#[derive(Clone)]
+enum DataMatrixOption {
+    Size(u8),
+    Rectangle,
+}
+
+#[derive(Clone)]
+enum AztecOption {
+    Size(u8),
+    Correction(u8),
+    Compact,
+}
+
+/// A character size multiplier of the `size` command
+fn size_multiplier(input: &str) -> IResult<&str, u8> {
+    verify(u8, |size| (1..=8).contains(size)).parse(input)
+}
+
+/// `name=<value>` with a value in `range`
+fn ranged<'a>(
+    name: &'static str,
+    range: std::ops::RangeInclusive<u8>,
+) -> impl Parser<&'a str, Output = u8, Error = nom::error::Error<&'a str>> {
+    preceded(
+        pair(tag(name), tag("=")),
+        verify(u8, move |value| range.contains(value)),
+    )
+}
+
+fn qr_options(input: &str) -> IResult<&str, QrOptions> {
+    fold_many0(
+        preceded(
+            space1,
+            alt((
+                map(ranged("size", 1..=16), QrOption::Size),
+                map(
                     preceded(
-                        pair(tag("ean13"), space1),
-                        take_while_m_n(12, 13, AsChar::is_dec_digit)
+                        tag("correction="),
+                        alt((
+                            value(QrCorrection::L, tag_no_case("l")),
+                            value(QrCorrection::M, tag_no_case("m")),
+                            value(QrCorrection::Q, tag_no_case("q")),
+                            value(QrCorrection::H, tag_no_case("h")),
+                        )),
                     ),
-                    |digits| Command::Raw(printer::Command::Ean13(String::from(digits)))
-                )
-            },
-            {
-                name: "ean8",
-                syntax: "ean8 <7-8 digits>",
-                description: "Prints an EAN-8 barcode (7 or 8 digits)",
-                category: Barcodes,
-                examples: [
-                    "ean8 12345678",
-                    "ean8 1234567"
-                ],
-                parser: map(
+                    QrOption::Correction,
+                ),
+                map(
                     preceded(
-                        pair(tag("ean8"), space1),
-                        take_while_m_n(7, 8, AsChar::is_dec_digit)
+                        tag("model="),
+                        alt((
+                            value(QrModel::Model1, tag("1")),
+                            value(QrModel::Model2, tag("2")),
+                            value(QrModel::Micro, tag("micro")),
+                        )),
                     ),
-                    |digits| Command::Raw(printer::Command::Ean8(String::from(digits)))
-                )
-            },
-            {
-                name: "qr_code",
-                syntax: "qr_code \"<data>\"",
-                description: "Prints a QR code with the specified data",
-                category: Barcodes,
-                examples: [
-                    "qr_code \"https://example.com\"",
-                    "qr_code \"Hello World\""
-                ],
-                parser: map(
-                    preceded(pair(tag("qr_code"), space1), escaped_string),
-                    |data| Command::Raw(printer::Command::QrCode(data))
-                )
-            },
-            {
-                name: "size",
-                syntax: "size <width>,<height>",
-                description: "Sets character size multiplier (1-8 for both width and height)",
-                category: Formatting,
-                examples: [
-                    "size 1,1",
-                    "size 2,2",
-                    "size 3,1"
-                ],
-                parser: map(
-                    preceded(pair(tag("size"), space1), separated_pair(u8, tag(","), u8)),
-                    |(a, b)| Command::Raw(printer::Command::Size(a, b))
-                )
-            },
-            {
-                name: "reset_size",
-                syntax: "reset_size",
-                description: "Resets text size to default (1,1)",
-                category: Formatting,
-                examples: ["reset_size"],
-                parser: map(tag("reset_size"), |_| Command::Raw(printer::Command::ResetSize))
-            },
-            {
-                name: "sudoku",
-                syntax: "sudoku",
-                description: "Generates and prints a random Sudoku puzzle",
-                category: Special,
-                examples: ["sudoku"],
-                parser: map(tag("sudoku"), |_| Command::Sudoku)
-            },
-            {
-                name: "minicrossword",
-                syntax: "minicrossword",
-                description: "Generates and prints a mini crossword puzzle",
-                category: Special,
-                examples: ["minicrossword"],
-                parser: map(tag("minicrossword"), |_| Command::MiniCrossword)
-            },
-            {
-                name: "cut",
-                syntax: "cut",
-                description: "Cuts the paper (if printer has auto-cutter)",
-                category: Special,
-                examples: ["cut"],
-                parser: map(tag("cut"), |_| Command::Raw(printer::Command::Cut))
-            },
-            {
-                name: "todo",
-                syntax: "todo \"<task>\"",
-                description: "Adds a line rendered as a todo item",
-                category: Special,
-                examples: [
-                    "todo \"Buy groceries\"",
-                    "todo \"Call dentist\""
-                ],
-                parser: map(
-                    preceded(pair(tag("todo"), space1), escaped_string),
-                    Command::ToDo
-                )
+                    QrOption::Model,
+                ),
+            )),
+        ),
+        QrOptions::default,
+        |mut options, option| {
+            match option {
+                QrOption::Size(size) => options.size = size,
+                QrOption::Correction(correction) => options.correction = correction,
+                QrOption::Model(model) => options.model = model,
             }
-        }
+            options
+        },
+    )
+    .parse(input)
+}
+
+fn pdf417_options(input: &str) -> IResult<&str, Pdf417Options> {
+    fold_many0(
+        preceded(
+            space1,
+            alt((
+                map(ranged("columns", 0..=30), Pdf417Option::Columns),
+                map(
+                    verify(ranged("rows", 0..=90), |rows| *rows == 0 || *rows >= 3),
+                    Pdf417Option::Rows,
+                ),
+                map(ranged("width", 2..=8), Pdf417Option::Width),
+                map(ranged("row_height", 2..=8), Pdf417Option::RowHeight),
+                map(ranged("correction", 0..=8), Pdf417Option::Correction),
+                value(Pdf417Option::Truncated, tag("truncated")),
+            )),
+        ),
+        Pdf417Options::default,
+        |mut options, option| {
+            match option {
+                Pdf417Option::Columns(columns) => options.columns = columns,
+                Pdf417Option::Rows(rows) => options.rows = rows,
+                Pdf417Option::Width(width) => options.width = width,
+                Pdf417Option::RowHeight(height) => options.row_height = height,
+                Pdf417Option::Correction(correction) => options.correction = correction,
+                Pdf417Option::Truncated => options.truncated = true,
+            }
+            options
+        },
+    )
+    .parse(input)
+}
+
+fn data_matrix_options(input: &str) -> IResult<&str, DataMatrixOptions> {
+    fold_many0(
+        preceded(
+            space1,
+            alt((
+                map(ranged("size", 2..=16), DataMatrixOption::Size),
+                value(DataMatrixOption::Rectangle, tag("rectangle")),
+            )),
+        ),
+        DataMatrixOptions::default,
+        |mut options, option| {
+            match option {
+                DataMatrixOption::Size(size) => options.size = size,
+                DataMatrixOption::Rectangle => options.rectangle = true,
+            }
+            options
+        },
+    )
+    .parse(input)
+}
+
+fn aztec_options(input: &str) -> IResult<&str, AztecOptions> {
+    fold_many0(
+        preceded(
+            space1,
+            alt((
+                map(ranged("size", 2..=16), AztecOption::Size),
+                map(ranged("correction", 5..=95), AztecOption::Correction),
+                value(AztecOption::Compact, tag("compact")),
+            )),
+        ),
+        AztecOptions::default,
+        |mut options, option| {
+            match option {
+                AztecOption::Size(size) => options.size = size,
+                AztecOption::Correction(correction) => options.correction = correction,
+                AztecOption::Compact => options.compact = true,
+            }
+            options
+        },
+    )
+    .parse(input)
+}
+
+impl Command {
+    pub fn parse(input: &str) -> IResult<&str, Command> {
+        alt((
+            text_command,
+            formatting_command,
+            layout_command,
+            barcode_command,
+            graphics_command,
+            special_command,
+        ))
         .parse(input)
     }
 }
 
+/// Commands that output text
+fn text_command(input: &str) -> IResult<&str, Command> {
+    crate::documented_parser! {
+        {
+            name: "write",
+            syntax: "write \"<text>\"",
+            description: "Outputs text to the printer without a line break at the end",
+            category: Text,
+            examples: [
+                "write \"Hello World\"",
+                "write \"Price: $19.99\"",
+                "write \"Name:\\tAda\\n\""
+            ],
+            arguments: vec![Argument::string("text")],
+            parser: map(
+                preceded(pair(tag("write"), space1), escaped_string),
+                |string| Command::Raw(printer::Command::Write(string))
+            )
+        },
+        {
+            name: "writeln",
+            syntax: "writeln \"<text>\"",
+            description: "Outputs text to the printer followed by a line break",
+            category: Text,
+            examples: [
+                "writeln \"Hello World\"",
+                "writeln \"Order #12345\"",
+                "writeln \"\"\"\n    Dear customer,\n    thank you for \"shopping\" with us!\n    \"\"\""
+            ],
+            arguments: vec![Argument::string("text")],
+            parser: map(
+                preceded(pair(tag("writeln"), space1), escaped_string),
+                |string| Command::Raw(printer::Command::Write(string + "\n"))
+            )
+        },
+        {
+            name: "codepage",
+            syntax: "codepage <auto|name>",
+            description: "Prints the following text in the given code page of the printer, e.g. cp858 or iso_8859_15. By default (auto) the code page is chosen for every piece of text, and characters the printer can't print are replaced, e.g. \u{201c} by \"",
+            category: Text,
+            examples: [
+                "codepage cp858",
+                "codepage auto"
+            ],
+            arguments: vec![Argument::new("name", ArgumentKind::Name)],
+            parser: map(
+                preceded(
+                    pair(tag("codepage"), space1),
+                    alt((
+                        value(None, tag("auto")),
+                        map(take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-'), |name: &str| Some(name.to_string()))
+                    ))
+                ),
+                Command::CodePage
+            )
+        },
+        {
+            name: "markdown",
+            syntax: "markdown \"<text>\"",
+            description: "Outputs Markdown text with headings, bold and underlined text, lists, rules and code formatted for the receipt. The text may span several lines",
+            category: Text,
+            examples: [
+                "markdown \"# Shopping list\n- Milk\n- **Fresh** bread\"",
+                "markdown \"Door opened at `07:45`\n\n---\n_Front door_\""
+            ],
+            arguments: vec![Argument::string("text")],
+            parser: map(
+                preceded(pair(tag("markdown"), space1), escaped_string),
+                Command::Markdown
+            )
+        },
+        {
+            name: "text_image",
+            syntax: "text_image \"<text>\" [font=\"<file or family>\"] [size=<4-72>]",
+            description: "Prints text that the printer's fonts can't, like emoji and Chinese, Japanese or Korean characters, as an image rendered with a TrueType font at the printer's resolution. The font is a .ttf, .otf or .ttc file in the font directory of the server or the name of an installed font, characters it lacks are taken from the other installed fonts. The size is given in points (10 by default) and the text is wrapped to the paper width",
+            category: Text,
+            examples: [
+                "text_image \"Laundry is done \u{1F455}\u{2705}\"",
+                "text_image \"\u{4F60}\u{597D}\u{FF0C}\u{4E16}\u{754C}\" size=14",
+                "text_image \"Welcome home!\" font=\"/config/fonts/Pacifico.ttf\" size=24",
+                "text_image \"Good morning\" font=\"DejaVu Serif\""
+            ],
+            arguments: vec![
+                Argument::string("text"),
+                Argument::string("font").option(),
+                Argument::integer("size", 4, 72).option()
+            ],
+            parser: map(
+                preceded(pair(tag("text_image"), space1), pair(escaped_string, text_image_options)),
+                |(text, options)| Command::TextImage { text, options }
+            )
+        }
+    }
+    .parse(input)
+}
+
+/// Commands that change the text style
+fn formatting_command(input: &str) -> IResult<&str, Command> {
+    crate::documented_parser! {
+        {
+            name: "bold",
+            syntax: "bold <true|false>",
+            description: "Enables or disables bold text",
+            category: Formatting,
+            examples: [
+                "bold true",
+                "bold false"
+            ],
+            arguments: vec![Argument::boolean("enabled")],
+            parser: map(
+                preceded(pair(tag("bold"), space1), bool),
+                |mode| Command::Raw(printer::Command::Bold(mode))
+            )
+        },
+        {
+            name: "underline",
+            syntax: "underline <none|single|double>",
+            description: "Sets the underline mode for text",
+            category: Formatting,
+            examples: [
+                "underline none",
+                "underline single",
+                "underline double"
+            ],
+            arguments: vec![Argument::choice("mode", &["none", "single", "double"])],
+            parser: map(
+                preceded(pair(tag("underline"), space1), underline_mode),
+                |mode| Command::Raw(printer::Command::Underline(mode))
+            )
+        },
+        {
+            name: "double_strike",
+            syntax: "double_strike <true|false>",
+            description: "Enables or disables double-strike for text",
+            category: Formatting,
+            examples: [
+                "double_strike true",
+                "double_strike false"
+            ],
+            arguments: vec![Argument::boolean("enabled")],
+            parser: map(
+                preceded(pair(tag("double_strike"), space1), bool),
+                |mode| Command::Raw(printer::Command::DoubleStrike(mode))
+            )
+        },
+        {
+            name: "font",
+            syntax: "font <a|b|c>",
+            description: "Sets the font type. Available fonts depend on printer model and might fallback to another font if unavailable.",
+            category: Formatting,
+            examples: [
+                "font a",
+                "font b",
+                "font c"
+            ],
+            arguments: vec![Argument::choice("font", &["a", "b", "c"])],
+            parser: map(
+                preceded(pair(tag("font"), space1), font),
+                |f| Command::Raw(printer::Command::Font(f))
+            )
+        },
+        {
+            name: "flip",
+            syntax: "flip <true|false>",
+            description: "Flips text 180 degrees",
+            category: Formatting,
+            examples: [
+                "flip true",
+                "flip false"
+            ],
+            arguments: vec![Argument::boolean("enabled")],
+            parser: map(
+                preceded(pair(tag("flip"), space1), bool),
+                |flip| Command::Raw(printer::Command::Flip(flip))
+            )
+        },
+        {
+            name: "reverse",
+            syntax: "reverse <true|false>",
+            description: "Enables or disables inverted text colors (white text on black background)",
+            category: Formatting,
+            examples: [
+                "reverse true",
+                "reverse false"
+            ],
+            arguments: vec![Argument::boolean("enabled")],
+            parser: map(
+                preceded(pair(tag("reverse"), space1), bool),
+                |reverse| Command::Raw(printer::Command::Reverse(reverse))
+            )
+        },
+        {
+            name: "size",
+            syntax: "size <width>,<height>",
+            description: "Sets character size multiplier (1-8 for both width and height)",
+            category: Formatting,
+            examples: [
+                "size 1,1",
+                "size 2,2",
+                "size 3,1"
+            ],
+            arguments: vec![Argument::integer("width", 1, 8), Argument::integer("height", 1, 8)],
+            parser: map(
+                preceded(
+                    pair(tag("size"), space1),
+                    separated_pair(size_multiplier, tag(","), size_multiplier)
+                ),
+                |(a, b)| Command::Raw(printer::Command::Size(a, b))
+            )
+        },
+        {
+            name: "reset_size",
+            syntax: "reset_size",
+            description: "Resets text size to default (1,1)",
+            category: Formatting,
+            examples: ["reset_size"],
+            parser: map(tag("reset_size"), |_| Command::Raw(printer::Command::ResetSize))
+        }
+    }
+    .parse(input)
+}
+
+/// Commands for alignment and spacing
+fn layout_command(input: &str) -> IResult<&str, Command> {
+    crate::documented_parser! {
+        {
+            name: "justify",
+            syntax: "justify <left|center|right>",
+            description: "Sets text justification/alignment",
+            category: Layout,
+            examples: [
+                "justify left",
+                "justify center",
+                "justify right"
+            ],
+            arguments: vec![Argument::choice("mode", &["left", "center", "right"])],
+            parser: map(
+                preceded(pair(tag("justify"), space1), justify_mode),
+                |mode| Command::Raw(printer::Command::Justify(mode))
+            )
+        },
+        {
+            name: "feed",
+            syntax: "feed <lines>",
+            description: "Feeds paper forward by the specified number of lines",
+            category: Layout,
+            examples: [
+                "feed 1",
+                "feed 3",
+                "feed 10"
+            ],
+            arguments: vec![Argument::integer("lines", 0, 255)],
+            parser: map(
+                preceded(pair(tag("feed"), space1), u8),
+                |lines| Command::Raw(printer::Command::Feed(lines))
+            )
+        },
+        {
+            name: "feed",
+            syntax: "feed",
+            description: "Feeds paper forward by 1 line (default)",
+            category: Layout,
+            examples: ["feed"],
+            parser: map(tag("feed"), |_| Command::Raw(printer::Command::Feed(1)))
+        },
+        {
+            name: "row",
+            syntax: "row <column>,... \"<cell>\" ...",
+            description: "Prints one cell per column, aligned to the width of the paper. A column is a width in characters or * to fill the remaining width, followed by < (left, default) or > (right) alignment and . for dot leaders",
+            category: Layout,
+            examples: [
+                "row *., 8> \"Coffee\" \"$3.50\"",
+                "row 4, *, 10> \"2x\" \"Bagel with cream cheese\" \"$7.00\"",
+                "row *, * \"Table 4\" \"Server: Ada\""
+            ],
+            arguments: vec![
+                Argument::new("columns", ArgumentKind::Columns),
+                Argument::string("cell").repeated()
+            ],
+            parser: map(
+                preceded(
+                    pair(tag("row"), space1),
+                    verify(
+                        separated_pair(columns, space1, separated_list1(space1, escaped_string)),
+                        |(columns, cells)| columns.len() == cells.len()
+                    )
+                ),
+                |(columns, cells)| Command::Table { columns, rows: vec![cells] }
+            )
+        },
+        {
+            name: "table",
+            syntax: "table <column>,... \"<rows>\"",
+            description: "Prints several rows with the same columns as row. Rows are separated by line breaks, cells by | and trimmed. Within the rows, \\| is a literal |, \\n a line break within a cell and a backslash before a space keeps it from being trimmed",
+            category: Layout,
+            examples: [
+                "table *, 3>, 8> \"Item|Qty|Price\nCoffee|2|$7.00\nBagel|1|$3.50\"",
+                "table *., 10> \"\"\"\nSubtotal | $10.50\nTax | $0.84\n\"\"\""
+            ],
+            arguments: vec![Argument::new("columns", ArgumentKind::Columns), Argument::string("rows")],
+            parser: map_opt(
+                preceded(
+                    pair(tag("table"), space1),
+                    separated_pair(columns, space1, escaped_string)
+                ),
+                |(columns, text)| {
+                    let rows = table_rows(&text, columns.len())?;
+                    Some(Command::Table { columns, rows })
+                }
+            )
+        }
+    }
+    .parse(input)
+}
+
+/// Barcode and QR code commands
+fn barcode_command(input: &str) -> IResult<&str, Command> {
+    crate::documented_parser! {
+        {
+            name: "ean13",
+            syntax: "ean13 <12-13 digits> [hri=<none|above|below|both>] [hri_font=<a|b>] [width=<1-6>] [height=<1-255>]",
+            description: "Prints an EAN-13 barcode. The check digit is added to 12 digits and verified for 13. The human readable digits are printed below the barcode unless hri moves or hides them, width sets the width of the narrowest bar and height the height in dots",
+            category: Barcodes,
+            examples: [
+                "ean13 4006381333931",
+                "ean13 400638133393 hri=none",
+                "ean13 4006381333931 hri=both hri_font=b width=2 height=60"
+            ],
+            arguments: barcode_arguments(Argument::new("digits", ArgumentKind::Digits)),
+            parser: preceded(pair(tag("ean13"), space1), barcode(Symbology::Ean13, barcode_digits))
+        },
+        {
+            name: "ean8",
+            syntax: "ean8 <7-8 digits> [<barcode options>]",
+            description: "Prints an EAN-8 barcode, adding or verifying the check digit. Accepts the same options as ean13",
+            category: Barcodes,
+            examples: [
+                "ean8 96385074",
+                "ean8 9638507 height=50"
+            ],
+            arguments: barcode_arguments(Argument::new("digits", ArgumentKind::Digits)),
+            parser: preceded(pair(tag("ean8"), space1), barcode(Symbology::Ean8, barcode_digits))
+        },
+        {
+            name: "upca",
+            syntax: "upca <11-12 digits> [<barcode options>]",
+            description: "Prints a UPC-A barcode, adding or verifying the check digit. Accepts the same options as ean13",
+            category: Barcodes,
+            examples: [
+                "upca 036000291452",
+                "upca 03600029145 hri=above"
+            ],
+            arguments: barcode_arguments(Argument::new("digits", ArgumentKind::Digits)),
+            parser: preceded(pair(tag("upca"), space1), barcode(Symbology::UpcA, barcode_digits))
+        },
+        {
+            name: "upce",
+            syntax: "upce <6-8 digits> [<barcode options>]",
+            description: "Prints a UPC-E barcode from 6 digits, or 7 digits starting with the number system 0 or 1, optionally followed by the check digit. Accepts the same options as ean13",
+            category: Barcodes,
+            examples: [
+                "upce 425261",
+                "upce 04252614 width=2"
+            ],
+            arguments: barcode_arguments(Argument::new("digits", ArgumentKind::Digits)),
+            parser: preceded(pair(tag("upce"), space1), barcode(Symbology::UpcE, barcode_digits))
+        },
+        {
+            name: "itf",
+            syntax: "itf <digits> [<barcode options>]",
+            description: "Prints an Interleaved 2 of 5 barcode of an even number of digits. Accepts the same options as ean13",
+            category: Barcodes,
+            examples: [
+                "itf 12345678",
+                "itf 10012345678902 hri=none"
+            ],
+            arguments: barcode_arguments(Argument::new("digits", ArgumentKind::Digits)),
+            parser: preceded(pair(tag("itf"), space1), barcode(Symbology::Itf, barcode_digits))
+        },
+        {
+            name: "code39",
+            syntax: "code39 \"<data>\" [<barcode options>]",
+            description: "Prints a Code 39 barcode of digits, upper case letters, spaces and - . $ / + %. Accepts the same options as ean13",
+            category: Barcodes,
+            examples: [
+                "code39 \"SHELF-12\"",
+                "code39 \"A 12/B\" width=2 height=80"
+            ],
+            arguments: barcode_arguments(Argument::string("data")),
+            parser: preceded(pair(tag("code39"), space1), barcode(Symbology::Code39, escaped_string))
+        },
+        {
+            name: "codabar",
+            syntax: "codabar \"<data>\" [<barcode options>]",
+            description: "Prints a Codabar barcode of digits and - $ : / . +, enclosed in start and stop characters A to D. A is used if they are missing. Accepts the same options as ean13",
+            category: Barcodes,
+            examples: [
+                "codabar \"40156\"",
+                "codabar \"B34-5678D\" hri=none"
+            ],
+            arguments: barcode_arguments(Argument::string("data")),
+            parser: preceded(pair(tag("codabar"), space1), barcode(Symbology::Codabar, escaped_string))
+        },
+        {
+            name: "code128",
+            syntax: "code128 \"<data>\" [<barcode options>]",
+            description: "Prints a Code 128 barcode of printable ASCII characters. Even numbers of digits are encoded compactly as digit pairs. Accepts the same options as ean13",
+            category: Barcodes,
+            examples: [
+                "code128 \"BOX-0042\" hri=above height=80",
+                "code128 \"20251016\"",
+                "code128 \"https://example.com/t/42\" width=2"
+            ],
+            arguments: barcode_arguments(Argument::string("data")),
+            parser: preceded(pair(tag("code128"), space1), barcode(Symbology::Code128, escaped_string))
+        },
+        {
+            name: "qr_code",
+            syntax: "qr_code \"<data>\" [size=<1-16>] [correction=<l|m|q|h>] [model=<1|2|micro>]",
+            description: "Prints a QR code with the specified data. size sets the size of a module in dots, correction the share of the code used for error correction (about 7%, 15%, 25% or 30%)",
+            category: Barcodes,
+            examples: [
+                "qr_code \"https://example.com\"",
+                "qr_code \"Hello World\" size=6 correction=m model=2",
+                "qr_code \"\"\"\nBEGIN:VCARD\nVERSION:3.0\nFN:Ada Lovelace\nEND:VCARD\n\"\"\""
+            ],
+            arguments: vec![
+                Argument::string("data"),
+                Argument::integer("size", 1, 16).option(),
+                Argument::choice("correction", &["l", "m", "q", "h"]).option(),
+                Argument::choice("model", &["1", "2", "micro"]).option()
+            ],
+            parser: map(
+                preceded(pair(tag("qr_code"), space1), pair(escaped_string, qr_options)),
+                |(data, options)| Command::Raw(printer::Command::Code2d(Code2d::QrCode(data, options)))
+            )
+        },
+        {
+            name: "pdf417",
+            syntax: "pdf417 \"<data>\" [columns=<0-30>] [rows=<0,3-90>] [width=<2-8>] [row_height=<2-8>] [correction=<0-8>] [truncated]",
+            description: "Prints a PDF417 code. The printer chooses the number of columns and rows unless they are given. width sets the width of a module in dots, row_height the height of a row in modules",
+            category: Barcodes,
+            examples: [
+                "pdf417 \"SHIP TO: Ada Lovelace, 12 Analytical St\"",
+                "pdf417 \"TICKET 0042\" columns=4 width=2 correction=3 truncated"
+            ],
+            arguments: vec![
+                Argument::string("data"),
+                Argument::integer("columns", 0, 30).option(),
+                Argument::integer("rows", 0, 90).option(),
+                Argument::integer("width", 2, 8).option(),
+                Argument::integer("row_height", 2, 8).option(),
+                Argument::integer("correction", 0, 8).option(),
+                Argument::flag("truncated")
+            ],
+            parser: map(
+                preceded(pair(tag("pdf417"), space1), pair(escaped_string, pdf417_options)),
+                |(data, options)| Command::Raw(printer::Command::Code2d(Code2d::Pdf417(data, options)))
+            )
+        },
+        {
+            name: "datamatrix",
+            syntax: "datamatrix \"<data>\" [size=<2-16>] [rectangle]",
+            description: "Prints a square or rectangular DataMatrix code. size sets the size of a module in dots",
+            category: Barcodes,
+            examples: [
+                "datamatrix \"BOX-0042\"",
+                "datamatrix \"0123456789\" size=4 rectangle"
+            ],
+            arguments: vec![
+                Argument::string("data"),
+                Argument::integer("size", 2, 16).option(),
+                Argument::flag("rectangle")
+            ],
+            parser: map(
+                preceded(pair(tag("datamatrix"), space1), pair(escaped_string, data_matrix_options)),
+                |(data, options)| Command::Raw(printer::Command::Code2d(Code2d::DataMatrix(data, options)))
+            )
+        },
+        {
+            name: "aztec",
+            syntax: "aztec \"<data>\" [size=<2-16>] [correction=<5-95>] [compact]",
+            description: "Prints an Aztec code, as used on tickets. size sets the size of a module in dots, correction the percentage used for error correction",
+            category: Barcodes,
+            examples: [
+                "aztec \"TICKET 0042 SEAT 12A\"",
+                "aztec \"0042\" size=5 correction=33 compact"
+            ],
+            arguments: vec![
+                Argument::string("data"),
+                Argument::integer("size", 2, 16).option(),
+                Argument::integer("correction", 5, 95).option(),
+                Argument::flag("compact")
+            ],
+            parser: map(
+                preceded(pair(tag("aztec"), space1), pair(escaped_string, aztec_options)),
+                |(data, options)| Command::Raw(printer::Command::Code2d(Code2d::Aztec(data, options)))
+            )
+        }
+    }
+    .parse(input)
+}
+
+/// Images and graphics
+fn graphics_command(input: &str) -> IResult<&str, Command> {
+    crate::documented_parser! {
+        {
+            name: "image",
+            syntax: "image [url|file] \"<source>\" [width=<pixels>] [dither=<floyd_steinberg|atkinson|bayer|threshold>] [threshold=<0-255>] [brightness=<-1.0-1.0>] [contrast=<factor>] [gamma=<gamma>] [invert]",
+            description: "Prints a PNG, JPEG or GIF image given as base64 data, an http(s) URL or a file path in the image directory of the server. The image is scaled to the given width, at most the paper width, and dithered to black and white (Floyd-Steinberg by default). Brightness, contrast and gamma are adjusted before dithering",
+            category: Graphics,
+            examples: [
+                "image \"iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAAAAAA6fptVAAAACklEQVR4nGNgAAAAAgABSK+kcQAAAABJRU5ErkJggg==\"",
+                "image url \"http://homeassistant.local:8123/local/logo.png\" width=256",
+                "image file \"/config/www/snapshot.jpg\" dither=atkinson contrast=1.3",
+                "image url \"http://camera.local/snapshot.jpg\" width=384 dither=bayer brightness=0.1 gamma=1.8",
+                "image file \"/config/www/logo.png\" dither=threshold threshold=100 invert"
+            ],
+            arguments: image_arguments(vec![
+                Argument::choice("kind", &["url", "file"]).optional(),
+                Argument::string("source")
+            ]),
+            parser: map(
+                preceded(pair(tag("image"), space1), pair(image_source, image_options)),
+                |(source, options)| Command::Image { source, options }
+            )
+        },
+        {
+            name: "svg",
+            syntax: "svg \"\"\"<document>\"\"\" [<image options>]",
+            description: "Prints an SVG graphic, rasterized at the printer's resolution. It is scaled to the given width or the full paper width and accepts the same options as image",
+            category: Graphics,
+            examples: [
+                "svg \"<svg xmlns='http://www.w3.org/2000/svg' width='40' height='40'><circle cx='20' cy='20' r='18'/></svg>\" width=120",
+                "svg \"\"\"\n<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"60mm\" height=\"20mm\">\n  <rect x=\"1\" y=\"1\" width=\"58\" height=\"18\" fill=\"none\" stroke=\"black\"/>\n</svg>\n\"\"\" dither=threshold"
+            ],
+            arguments: image_arguments(vec![Argument::string("document")]),
+            parser: map(
+                preceded(pair(tag("svg"), space1), pair(escaped_string, image_options)),
+                |(source, options)| Command::Svg { source, options }
+            )
+        }
+    }
+    .parse(input)
+}
+
+/// Generated content, cutting and templates
+fn special_command(input: &str) -> IResult<&str, Command> {
+    crate::documented_parser! {
+        {
+            name: "sudoku",
+            syntax: "sudoku",
+            description: "Generates and prints a random Sudoku puzzle",
+            category: Special,
+            examples: ["sudoku"],
+            parser: map(tag("sudoku"), |_| Command::Sudoku)
+        },
+        {
+            name: "minicrossword",
+            syntax: "minicrossword",
+            description: "Generates and prints a mini crossword puzzle",
+            category: Special,
+            examples: ["minicrossword"],
+            parser: map(tag("minicrossword"), |_| Command::MiniCrossword)
+        },
+        {
+            name: "cut",
+            syntax: "cut",
+            description: "Cuts the paper (if printer has auto-cutter)",
+            category: Special,
+            examples: ["cut"],
+            parser: map(tag("cut"), |_| Command::Raw(printer::Command::Cut))
+        },
+        {
+            name: "todo",
+            syntax: "todo \"<task>\"",
+            description: "Adds a line rendered as a todo item",
+            category: Special,
+            examples: [
+                "todo \"Buy groceries\"",
+                "todo \"Call dentist\"",
+                "todo \"Buy caf\\u{e9} beans\""
+            ],
+            arguments: vec![Argument::string("task")],
+            parser: map(
+                preceded(pair(tag("todo"), space1), escaped_string),
+                Command::ToDo
+            )
+        },
+        {
+            name: "include",
+            syntax: "include \"<template>\"",
+            description: "Inserts a named template published to escpos/templates/<template>",
+            category: Special,
+            examples: [
+                "include \"header\"",
+                "include \"ticket_footer\""
+            ],
+            arguments: vec![Argument::string("template")],
+            parser: map(
+                preceded(pair(tag("include"), space1), escaped_string),
+                Command::Include
+            )
+        }
+    }
+    .parse(input)
+}
+
 #[cfg(test)]
 mod tests {
     // Note this useful idiom: importing names from outer (for mod tests) scope.
@@ -377,7 +1602,7 @@ mod tests {
     #[test]
     fn test_add() {
         let string = "write \"asdf\"\t\n   \n\n\t\n  \twriteln \"rofl\"\ncut";
-        let command = Program::parse(&string);
+        let command = Program::parse(string);
         assert_eq!(
             command,
             Ok((
@@ -393,13 +1618,224 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_strings() {
+        assert_eq!(
+            escaped_string(r#""a\"b\\c\nd\te\u{e9}\u{1F600}" rest"#),
+            Ok((" rest", String::from("a\"b\\c\nd\te\u{e9}\u{1F600}")))
+        );
+        assert!(escaped_string(r#""\q""#).is_err());
+        assert!(escaped_string(r#""\u{110000}""#).is_err());
+
+        let source = "writeln \"\"\"\n    Dear \"customer\",\n      \\tthanks!\n    \"\"\"\ncut";
+        assert_eq!(
+            Program::parse_complete(source),
+            Ok(Program {
+                commands: vec![
+                    Command::Raw(printer::Command::Write(String::from(
+                        "Dear \"customer\",\n  \tthanks!\n"
+                    ))),
+                    Command::Raw(printer::Command::Cut),
+                ]
+            })
+        );
+
+        assert_eq!(
+            Program::compile("repeat 1 {\n  todo \"\"\"a } \"b\" \"\"\"\n}", None),
+            Ok(Program {
+                commands: vec![Command::ToDo(String::from("a } \"b\" "))]
+            })
+        );
+        assert_eq!(
+            escaped_string("\"\"\"one \\\"\"\" two\"\"\""),
+            Ok(("", String::from("one \"\"\" two")))
+        );
+    }
+
+    #[test]
+    fn test_rows_and_tables() {
+        let columns = vec![
+            Column {
+                width: ColumnWidth::Fill,
+                align: Align::Left,
+                dots: true,
+            },
+            Column {
+                width: ColumnWidth::Fixed(8),
+                align: Align::Right,
+                dots: false,
+            },
+        ];
+        assert_eq!(
+            Command::parse("row *.,8> \"Coffee\" \"$3.50\""),
+            Ok((
+                "",
+                Command::Table {
+                    columns: columns.clone(),
+                    rows: vec![vec![String::from("Coffee"), String::from("$3.50")]]
+                }
+            ))
+        );
+        assert_eq!(
+            Command::parse("table *., 8> \"Tea | $2\n\nWater\""),
+            Ok((
+                "",
+                Command::Table {
+                    columns,
+                    rows: vec![
+                        vec![String::from("Tea"), String::from("$2")],
+                        vec![String::from("Water"), String::new()]
+                    ]
+                }
+            ))
+        );
+
+        assert!(Program::parse_complete("row *, 8> \"Coffee\"").is_err());
+        assert!(Program::parse_complete("table * \"a|b\"").is_err());
+        assert!(Program::parse_complete("row 0 \"a\"").is_err());
+    }
+
+    #[test]
+    fn test_image() {
+        assert_eq!(
+            Command::parse("image url \"http://x/a.png\" width=200"),
+            Ok((
+                "",
+                Command::Image {
+                    source: ImageSource::Url(String::from("http://x/a.png")),
+                    options: ImageOptions {
+                        width: Some(200),
+                        ..ImageOptions::default()
+                    }
+                }
+            ))
+        );
+        assert_eq!(
+            Command::parse("image \"abc\" dither=atkinson gamma=2.2 invert"),
+            Ok((
+                "",
+                Command::Image {
+                    source: ImageSource::Base64(String::from("abc")),
+                    options: ImageOptions {
+                        width: None,
+                        dither: dither::Options {
+                            algorithm: dither::Algorithm::Atkinson,
+                            gamma: 2.2,
+                            invert: true,
+                            ..dither::Options::default()
+                        }
+                    }
+                }
+            ))
+        );
+        assert!(Program::parse_complete("image \"abc\" width=0").is_err());
+        assert!(Program::parse_complete("image \"abc\" width=2049").is_err());
+        assert!(Program::parse_complete("image \"abc\" brightness=2").is_err());
+    }
+
+    #[test]
+    fn test_barcodes() {
+        assert_eq!(
+            Command::parse("code128 \"BOX-42\" hri=above height=80 width=2"),
+            Ok((
+                "",
+                Command::Raw(printer::Command::Barcode(Barcode {
+                    symbology: Symbology::Code128,
+                    data: String::from("BOX-42"),
+                    options: BarcodeOptions {
+                        width: 2,
+                        height: 80,
+                        hri: HriPosition::Above,
+                        hri_font: HriFont::A,
+                    }
+                }))
+            ))
+        );
+        let Ok((_, Command::Raw(printer::Command::Barcode(barcode)))) =
+            Command::parse("ean13 400638133393")
+        else {
+            panic!("expected a barcode");
+        };
+        assert_eq!(barcode.data, "4006381333931");
+
+        assert!(Program::parse_complete("ean13 4006381333932").is_err());
+        assert!(Program::parse_complete("code39 \"lower\"").is_err());
+        assert!(Program::parse_complete("itf 12 width=7").is_err());
+    }
+
+    #[test]
+    fn test_2d_codes() {
+        assert_eq!(
+            Command::parse("qr_code \"x\" size=8 correction=q model=micro"),
+            Ok((
+                "",
+                Command::Raw(printer::Command::Code2d(Code2d::QrCode(
+                    String::from("x"),
+                    QrOptions {
+                        model: QrModel::Micro,
+                        size: 8,
+                        correction: QrCorrection::Q,
+                    }
+                )))
+            ))
+        );
+        assert_eq!(
+            Command::parse("pdf417 \"x\" rows=10 truncated"),
+            Ok((
+                "",
+                Command::Raw(printer::Command::Code2d(Code2d::Pdf417(
+                    String::from("x"),
+                    Pdf417Options {
+                        rows: 10,
+                        truncated: true,
+                        ..Pdf417Options::default()
+                    }
+                )))
+            ))
+        );
+        assert!(Program::parse_complete("qr_code \"x\" size=17").is_err());
+        assert!(Program::parse_complete("pdf417 \"x\" rows=2").is_err());
+        assert!(Program::parse_complete("aztec \"x\" correction=4").is_err());
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        assert_eq!(
+            Program::parse_complete("writeln \"ok\"\n\n  writln \"typo\""),
+            Err(Diagnostic {
+                line: 3,
+                column: 3,
+                message: String::from("unknown command `writln`"),
+                expected: vec![],
+                suggestion: Some(String::from("writeln")),
+            })
+        );
+
+        let diagnostic = Program::parse_complete("cut\nbold maybe\ncut").unwrap_err();
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 6));
+        assert_eq!(diagnostic.expected, vec![String::from("bold <true|false>")]);
+        assert_eq!(
+            diagnostic.to_string(),
+            "line 2, column 6: invalid arguments for `bold`, expected bold <true|false>"
+        );
+
+        assert_eq!(
+            Program::parse_complete("cut\n  "),
+            Ok(Program {
+                commands: vec![Command::Raw(printer::Command::Cut)]
+            })
+        );
+    }
+
     #[test]
     fn test_all_documented_examples_parse() {
         // Get all commands from the documentation registry
         use crate::program::doc_macros::get_registered_commands;
+        use crate::program::documentation::CommandCategory;
 
         // Ensure parser is loaded to register commands
         let _ = Command::parse("");
+        let _ = Program::parse_blocks("");
 
         let commands = get_registered_commands();
         assert!(
@@ -415,6 +1851,17 @@ mod tests {
             for example in &cmd.examples {
                 total_examples += 1;
 
+                // Blocks span several lines and are parsed as whole programs
+                if cmd.category == CommandCategory::ControlFlow {
+                    if let Err(e) = Program::compile(example, Some(&Default::default())) {
+                        failed_examples.push(format!(
+                            "Command '{}' example '{}' failed to parse: {}",
+                            cmd.name, example, e
+                        ));
+                    }
+                    continue;
+                }
+
                 // Try to parse the example
                 match Command::parse(example) {
                     Ok((remaining, _parsed_cmd)) => {
@@ -450,4 +1897,180 @@ mod tests {
             total_examples, num_commands
         );
     }
+
+    /// Spans of the arguments of an example, split at spaces and commas
+    /// outside of strings
+    fn argument_spans(arguments: &str) -> Vec<std::ops::Range<usize>> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+        while start < arguments.len() {
+            let rest = &arguments[start..];
+            let length = if let Some(body) = rest.strip_prefix("\"\"\"") {
+                body.find("\"\"\"").map_or(rest.len(), |end| end + 6)
+            } else if rest.starts_with('"') {
+                let mut escaped = false;
+                rest.char_indices()
+                    .skip(1)
+                    .find(|&(_, c)| {
+                        let end = c == '"' && !escaped;
+                        escaped = c == '\\' && !escaped;
+                        end
+                    })
+                    .map_or(rest.len(), |(end, _)| end + 1)
+            } else {
+                rest.find([' ', ',', '\n']).unwrap_or(rest.len())
+            };
+            if length > 0 {
+                spans.push(start..start + length);
+            }
+            start += length.max(1);
+        }
+        spans
+    }
+
+    /// Values at the bounds of the documented range of an argument, and values
+    /// just outside of it
+    fn documented_bounds(argument: &Argument) -> Option<(Vec<f64>, Vec<f64>)> {
+        match argument.kind {
+            ArgumentKind::Integer { min, max } => Some((
+                vec![min as f64, max as f64],
+                vec![min as f64 - 1.0, max as f64 + 1.0],
+            )),
+            ArgumentKind::Number { min, max } => Some((
+                vec![],
+                min.map(|min| min - 0.5)
+                    .into_iter()
+                    .chain(max.map(|max| max + 0.5))
+                    .collect(),
+            )),
+            _ => None,
+        }
+    }
+
+    /// Whether an example value is in the documented range of the argument
+    fn in_documented_range(argument: &Argument, value: &str) -> bool {
+        match argument.kind {
+            ArgumentKind::Integer { min, max } => value
+                .parse::<i64>()
+                .is_ok_and(|value| (min..=max).contains(&value)),
+            ArgumentKind::Number { min, max } => value.parse::<f64>().is_ok_and(|value| {
+                min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+            }),
+            _ => true,
+        }
+    }
+
+    #[test]
+    fn test_documented_argument_ranges() {
+        use crate::program::documentation::CommandCategory;
+
+        let parses = |source: &str| matches!(Command::parse(source), Ok(("", _)));
+        let mut failures = Vec::new();
+        // Values at the bounds are accepted, values outside of them rejected
+        let mut check = |name: &str, source: String, inside: bool| {
+            if parses(&source) != inside {
+                let (range, result) = if inside {
+                    ("in", "rejected")
+                } else {
+                    ("outside of", "accepted")
+                };
+                failures.push(format!(
+                    "{}: `{}` is {} the documented range, but {}",
+                    name, source, range, result
+                ));
+            }
+        };
+
+        for doc in all_commands() {
+            // Blocks are checked by the evaluator
+            if doc.category == CommandCategory::ControlFlow {
+                continue;
+            }
+            let examples: Vec<&str> = doc
+                .examples
+                .iter()
+                .copied()
+                .filter(|example| parses(example))
+                .collect();
+            let Some(first) = examples.first() else {
+                continue;
+            };
+
+            // Named options are appended to the first example
+            for argument in doc.arguments.iter().filter(|argument| argument.named) {
+                let Some((inside, outside)) = documented_bounds(argument) else {
+                    continue;
+                };
+                let with = |value: f64| format!("{} {}={}", first, argument.name, value);
+                for value in inside {
+                    check(doc.name, with(value), true);
+                }
+                for value in outside {
+                    check(doc.name, with(value), false);
+                }
+            }
+
+            // Values in the examples are checked, and positional arguments
+            // replaced if all of them are required
+            let positional: Vec<&Argument> = doc
+                .arguments
+                .iter()
+                .filter(|argument| !argument.named)
+                .collect();
+            let offset = doc.name.len() + 1;
+            for example in examples.iter().filter(|example| example.len() > offset) {
+                let arguments = &example[offset..];
+                let mut values = Vec::new();
+                for span in argument_spans(arguments) {
+                    let token = &arguments[span.clone()];
+                    let named = token
+                        .split_once('=')
+                        .filter(|_| !token.starts_with('"'))
+                        .and_then(|(name, value)| {
+                            let argument = doc.arguments.iter().find(|arg| arg.name == name)?;
+                            Some((argument, value))
+                        });
+                    match named {
+                        Some((argument, value)) => {
+                            if !in_documented_range(argument, value) {
+                                check(doc.name, example.to_string(), false);
+                            }
+                        }
+                        None if doc.arguments.iter().any(|arg| arg.name == token) => {}
+                        None => values.push(span),
+                    }
+                }
+                if positional.iter().any(|argument| argument.optional)
+                    || values.len() != positional.len()
+                {
+                    continue;
+                }
+
+                for (argument, span) in positional.iter().zip(values) {
+                    if !in_documented_range(argument, &arguments[span.clone()]) {
+                        check(doc.name, example.to_string(), false);
+                    }
+                    let Some((inside, outside)) = documented_bounds(argument) else {
+                        continue;
+                    };
+                    let with = |value: f64| {
+                        format!(
+                            "{}{}{}",
+                            &example[..offset + span.start],
+                            value,
+                            &example[offset + span.end..]
+                        )
+                    };
+                    for value in inside {
+                        check(doc.name, with(value), true);
+                    }
+                    for value in outside {
+                        check(doc.name, with(value), false);
+                    }
+                }
+            }
+        }
+
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
 }
@@ -0,0 +1,337 @@
+//! Template variables and interpolation for DSL programs
+//!
+//! Placeholders of the form `{{ name | filter | filter(arg) }}` are expanded on the
+//! program source before it is parsed into a [`Program`](super::Program), so a stored
+//! layout can be filled with values at print time.
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::bytes::complete::take_till;
+use nom::bytes::complete::take_while1;
+use nom::character::complete::space0;
+use nom::character::complete::usize;
+use nom::combinator::all_consuming;
+use nom::combinator::map;
+use nom::combinator::opt;
+use nom::combinator::value;
+use nom::multi::many0;
+use nom::multi::separated_list1;
+use nom::sequence::delimited;
+use nom::sequence::pair;
+use nom::sequence::preceded;
+use nom::IResult;
+use nom::Parser;
+use serde_json::Value;
+use thiserror::Error;
+use unicode_width::UnicodeWidthStr;
+
+/// Values available to placeholders, keyed by variable name
+pub type Variables = serde_json::Map<String, Value>;
+
+const OPEN: &str = "{{";
+const CLOSE: &str = "}}";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("unterminated placeholder at offset {0}")]
+    Unterminated(usize),
+    #[error("invalid placeholder expression: {0}")]
+    InvalidExpression(String),
+    #[error("missing variable: {0}")]
+    MissingVariable(String),
+    #[error("filter {filter} cannot be applied to {value}")]
+    InvalidFilterInput { filter: &'static str, value: String },
+}
+
+/// A filter applied to a placeholder value
+#[derive(Debug, PartialEq, Clone)]
+pub enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    /// Pads the value with spaces on the right to the given width
+    Pad(usize),
+    /// Pads the value with spaces on the left to the given width
+    PadLeft(usize),
+    /// Formats a number with two decimals, prefixed with an optional symbol
+    Currency(String),
+    /// Replaces a missing or null value
+    Default(String),
+}
+
+/// A parsed placeholder, e.g. `{{ order.total | currency('$') }}`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expression {
+    pub path: Vec<String>,
+    pub filters: Vec<Filter>,
+}
+
+fn identifier(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '-').parse(input)
+}
+
+fn quoted(input: &str) -> IResult<&str, String> {
+    map(
+        delimited(tag("'"), take_till(|c| c == '\''), tag("'")),
+        String::from,
+    )
+    .parse(input)
+}
+
+fn argument<'a, O, P>(
+    parser: P,
+) -> impl Parser<&'a str, Output = O, Error = nom::error::Error<&'a str>>
+where
+    P: Parser<&'a str, Output = O, Error = nom::error::Error<&'a str>>,
+{
+    delimited((tag("("), space0), parser, (space0, tag(")")))
+}
+
+fn filter(input: &str) -> IResult<&str, Filter> {
+    alt((
+        value(Filter::Upper, tag("upper")),
+        value(Filter::Lower, tag("lower")),
+        value(Filter::Trim, tag("trim")),
+        map(preceded(tag("lpad"), argument(usize)), Filter::PadLeft),
+        map(preceded(tag("pad"), argument(usize)), Filter::Pad),
+        map(preceded(tag("currency"), opt(argument(quoted))), |symbol| {
+            Filter::Currency(symbol.unwrap_or_default())
+        }),
+        map(preceded(tag("default"), argument(quoted)), Filter::Default),
+    ))
+    .parse(input)
+}
+
+impl Expression {
+    pub fn parse(input: &str) -> IResult<&str, Expression> {
+        map(
+            delimited(
+                space0,
+                pair(
+                    separated_list1(tag("."), identifier),
+                    many0(preceded((space0, tag("|"), space0), filter)),
+                ),
+                space0,
+            ),
+            |(path, filters)| Expression {
+                path: path.into_iter().map(String::from).collect(),
+                filters,
+            },
+        )
+        .parse(input)
+    }
+
+    fn lookup<'a>(&self, variables: &'a Variables) -> Option<&'a Value> {
+        let (first, rest) = self.path.split_first()?;
+        rest.iter()
+            .try_fold(variables.get(first)?, |value, key| match value {
+                Value::Object(map) => map.get(key),
+                Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+                _ => None,
+            })
+    }
+
+    /// Resolves the expression to a value: the variable itself if there are no
+    /// filters, otherwise the filtered text
+    pub fn value(&self, variables: &Variables) -> Option<Value> {
+        if self.filters.is_empty() {
+            self.lookup(variables).cloned()
+        } else {
+            self.evaluate(variables).ok().map(Value::String)
+        }
+    }
+
+    /// Resolves the expression against `variables` and applies all filters
+    pub fn evaluate(&self, variables: &Variables) -> Result<String, Error> {
+        let mut current = self
+            .lookup(variables)
+            .filter(|value| !value.is_null())
+            .map(to_text);
+
+        for filter in &self.filters {
+            current = match (filter, current) {
+                (Filter::Default(fallback), None) => Some(fallback.clone()),
+                (_, None) => None,
+                (filter, Some(text)) => Some(filter.apply(text)?),
+            };
+        }
+
+        current.ok_or_else(|| Error::MissingVariable(self.path.join(".")))
+    }
+}
+
+impl Filter {
+    fn apply(&self, text: String) -> Result<String, Error> {
+        Ok(match self {
+            Filter::Upper => text.to_uppercase(),
+            Filter::Lower => text.to_lowercase(),
+            Filter::Trim => text.trim().to_string(),
+            Filter::Pad(width) => {
+                let padding = width.saturating_sub(text.width());
+                text + &" ".repeat(padding)
+            }
+            Filter::PadLeft(width) => {
+                let padding = width.saturating_sub(text.width());
+                " ".repeat(padding) + &text
+            }
+            Filter::Currency(symbol) => {
+                let amount: f64 = text.trim().parse().map_err(|_| Error::InvalidFilterInput {
+                    filter: "currency",
+                    value: text.clone(),
+                })?;
+                format!("{}{:.2}", symbol, amount)
+            }
+            Filter::Default(_) => text,
+        })
+    }
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+/// Escapes a value so it can be placed inside a quoted DSL string
+///
+/// Line breaks and other control characters are written as escapes too, so a
+/// value outside of quotes can't end the command line and start another one.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", u32::from(c))),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Expands all `{{ ... }}` placeholders in `input` using `variables`
+///
+/// Substituted values are escaped for use inside quoted DSL strings, and never
+/// contain a line break.
+pub fn expand(input: &str, variables: &Variables) -> Result<String, Error> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find(OPEN) {
+        output.push_str(&rest[..start]);
+        let offset = input.len() - rest.len() + start;
+        let after_open = &rest[start + OPEN.len()..];
+        let end = after_open.find(CLOSE).ok_or(Error::Unterminated(offset))?;
+        let source = &after_open[..end];
+
+        let (_, expression) = all_consuming(Expression::parse)
+            .parse(source)
+            .map_err(|_| Error::InvalidExpression(source.trim().to_string()))?;
+        output.push_str(&escape(&expression.evaluate(variables)?));
+
+        rest = &after_open[end + CLOSE.len()..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn variables(value: Value) -> Variables {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("variables must be an object"),
+        }
+    }
+
+    #[test]
+    fn test_expand_variables_and_filters() {
+        let vars = variables(json!({
+            "name": "Ada",
+            "order": { "total": 12.5, "items": ["Tea", "Cake"] }
+        }));
+
+        assert_eq!(
+            expand("writeln \"Hello {{name}}\"", &vars),
+            Ok(String::from("writeln \"Hello Ada\""))
+        );
+        assert_eq!(
+            expand(
+                "{{ name | upper | pad(5) }}|{{order.items.1|lpad(6)}}",
+                &vars
+            ),
+            Ok(String::from("ADA  |  Cake"))
+        );
+        assert_eq!(
+            expand("{{ order.total | currency('$') }}", &vars),
+            Ok(String::from("$12.50"))
+        );
+    }
+
+    #[test]
+    fn test_expand_defaults_and_missing() {
+        let vars = variables(json!({ "empty": null }));
+
+        assert_eq!(
+            expand("{{ guest | default('friend') | upper }}", &vars),
+            Ok(String::from("FRIEND"))
+        );
+        assert_eq!(
+            expand("{{ empty | default('-') }}", &vars),
+            Ok(String::from("-"))
+        );
+        assert_eq!(
+            expand("{{ guest }}", &vars),
+            Err(Error::MissingVariable(String::from("guest")))
+        );
+        assert_eq!(expand("{{ guest ", &vars), Err(Error::Unterminated(0)));
+        assert_eq!(
+            expand("{{ guest | shout }}", &vars),
+            Err(Error::InvalidExpression(String::from("guest | shout")))
+        );
+    }
+
+    #[test]
+    fn test_expanded_values_are_escaped() {
+        let vars = variables(json!({ "quote": "say \"hi\" \\o/" }));
+        let source = expand("writeln \"{{quote}}\"", &vars).unwrap();
+
+        assert_eq!(source, "writeln \"say \\\"hi\\\" \\\\o/\"");
+        assert_eq!(
+            crate::program::Command::parse(&source),
+            Ok((
+                "",
+                crate::program::Command::Raw(crate::printer::Command::Write(String::from(
+                    "say \"hi\" \\o/\n"
+                )))
+            ))
+        );
+    }
+
+    #[test]
+    fn test_unquoted_values_cant_add_commands() {
+        let vars = variables(json!({ "lines": "1\ncut", "note": "a\r\nb\u{7}" }));
+
+        let source = expand("feed {{lines}}", &vars).unwrap();
+        assert_eq!(source, "feed 1\\ncut");
+        assert!(crate::program::Program::parse_complete(&source).is_err());
+
+        // Inside quotes the escapes still print the original characters
+        let source = expand("write \"{{note}}\"", &vars).unwrap();
+        assert_eq!(source, "write \"a\\r\\nb\\u{7}\"");
+        assert_eq!(
+            crate::program::Program::parse_complete(&source).map(|program| program.commands),
+            Ok(vec![crate::program::Command::Raw(
+                crate::printer::Command::Write(String::from("a\r\nb\u{7}"))
+            )])
+        );
+    }
+}
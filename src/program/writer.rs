@@ -0,0 +1,652 @@
+//! Writing programs as canonical source text that parses back to the same
+//! commands
+//!
+//! Every command is written on its own line, with its options in a fixed
+//! order and only where they differ from their defaults. Strings are always
+//! written as `"..."` literals with escapes for quotes, backslashes and control
+//! characters.
+//!
+//! Tables of several rows are written as `table`, with `|`, line breaks,
+//! backslashes and surrounding whitespace of their cells escaped. Tables whose
+//! rows don't have a cell per column, or with empty rows of a single column,
+//! have no source form and are written as `<...>`, which does not parse.
+//!
+//! The printer commands produced while rendering or decoding
+//! ([`printer::Command::EncodedText`], [`printer::Command::CodePage`],
+//! [`printer::Command::BitImageFromBytesWithWidth`] and
+//! [`printer::Command::Bytes`]) have no source form either and are written as
+//! `<...>` too.
+use std::fmt::{self, Display, Formatter};
+
+use escpos::utils::{Font, JustifyMode, UnderlineMode};
+
+use crate::dither;
+use crate::printer;
+use crate::printer::barcode::{Barcode, BarcodeOptions, HriFont, HriPosition, Symbology};
+use crate::printer::codes_2d::{
+    AztecOptions, Code2d, DataMatrixOptions, Pdf417Options, QrCorrection, QrModel, QrOptions,
+};
+use crate::program::{
+    Align, Column, ColumnWidth, Command, ImageOptions, ImageSource, Program, TextImageOptions,
+};
+
+impl Program {
+    /// The program as source text, one command per line
+    pub fn to_dsl(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Command {
+    /// The command as a single line of source text
+    pub fn to_dsl(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for command in &self.commands {
+            writeln!(f, "{}", command)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Raw(command) => write_raw(f, command),
+            Command::Sudoku => f.write_str("sudoku"),
+            Command::MiniCrossword => f.write_str("minicrossword"),
+            Command::ToDo(task) => write!(f, "todo {}", Quoted(task)),
+            Command::Markdown(text) => write!(f, "markdown {}", Quoted(text)),
+            Command::Table { columns, rows } if !is_writable_table(columns, rows) => {
+                write!(f, "<{:?}>", self)
+            }
+            Command::Table { columns, rows } => write_table(f, columns, rows),
+            Command::Image { source, options } => {
+                match source {
+                    ImageSource::Base64(data) => write!(f, "image {}", Quoted(data))?,
+                    ImageSource::Url(url) => write!(f, "image url {}", Quoted(url))?,
+                    ImageSource::File(path) => write!(f, "image file {}", Quoted(path))?,
+                }
+                write_image_options(f, options)
+            }
+            Command::Svg { source, options } => {
+                write!(f, "svg {}", Quoted(source))?;
+                write_image_options(f, options)
+            }
+            Command::TextImage { text, options } => {
+                write!(f, "text_image {}", Quoted(text))?;
+                let defaults = TextImageOptions::default();
+                if let Some(font) = &options.font {
+                    write!(f, " font={}", Quoted(font))?;
+                }
+                if options.size != defaults.size {
+                    write!(f, " size={}", options.size)?;
+                }
+                Ok(())
+            }
+            Command::Include(template) => write!(f, "include {}", Quoted(template)),
+            Command::CodePage(None) => f.write_str("codepage auto"),
+            Command::CodePage(Some(name)) => write!(f, "codepage {}", name),
+        }
+    }
+}
+
+fn write_raw(f: &mut Formatter<'_>, command: &printer::Command) -> fmt::Result {
+    match command {
+        printer::Command::Write(text) => match text.strip_suffix('\n') {
+            Some(line) => write!(f, "writeln {}", Quoted(line)),
+            None => write!(f, "write {}", Quoted(text)),
+        },
+        printer::Command::Bold(bold) => write!(f, "bold {}", bold),
+        printer::Command::Underline(mode) => {
+            let mode = match mode {
+                UnderlineMode::None => "none",
+                UnderlineMode::Single => "single",
+                UnderlineMode::Double => "double",
+            };
+            write!(f, "underline {}", mode)
+        }
+        printer::Command::DoubleStrike(double_strike) => {
+            write!(f, "double_strike {}", double_strike)
+        }
+        printer::Command::Font(font) => {
+            let font = match font {
+                Font::A => "a",
+                Font::B => "b",
+                Font::C => "c",
+            };
+            write!(f, "font {}", font)
+        }
+        printer::Command::Flip(flip) => write!(f, "flip {}", flip),
+        printer::Command::Justify(mode) => {
+            let mode = match mode {
+                JustifyMode::LEFT => "left",
+                JustifyMode::CENTER => "center",
+                JustifyMode::RIGHT => "right",
+            };
+            write!(f, "justify {}", mode)
+        }
+        printer::Command::Reverse(reverse) => write!(f, "reverse {}", reverse),
+        printer::Command::Feed(lines) => write!(f, "feed {}", lines),
+        printer::Command::Barcode(barcode) => write_barcode(f, barcode),
+        printer::Command::Code2d(code) => write_code_2d(f, code),
+        printer::Command::Size(width, height) => write!(f, "size {},{}", width, height),
+        printer::Command::ResetSize => f.write_str("reset_size"),
+        printer::Command::Cut => f.write_str("cut"),
+        printer::Command::EncodedText(_)
+        | printer::Command::CodePage(_)
+        | printer::Command::BitImageFromBytesWithWidth(..)
+        | printer::Command::Bytes(_) => {
+            write!(f, "<{:?}>", command)
+        }
+    }
+}
+
+fn write_table(f: &mut Formatter<'_>, columns: &[Column], rows: &[Vec<String>]) -> fmt::Result {
+    let columns = columns
+        .iter()
+        .map(|column| {
+            let mut spec = match column.width {
+                ColumnWidth::Fixed(width) => width.to_string(),
+                ColumnWidth::Fill => String::from("*"),
+            };
+            if column.align == Align::Right {
+                spec.push('>');
+            }
+            if column.dots {
+                spec.push('.');
+            }
+            spec
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if let [cells] = rows {
+        write!(f, "row {}", columns)?;
+        for cell in cells {
+            write!(f, " {}", Quoted(cell))?;
+        }
+        Ok(())
+    } else {
+        let text = rows
+            .iter()
+            .map(|cells| {
+                let cells: Vec<String> = cells.iter().map(|cell| escape_cell(cell)).collect();
+                cells.join("|")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        write!(f, "table {} {}", columns, Quoted(&text))
+    }
+}
+
+/// Whether a table parses back from `row` or `table`, which need a cell per
+/// column and skip blank lines
+fn is_writable_table(columns: &[Column], rows: &[Vec<String>]) -> bool {
+    let blank_rows = rows.len() > 1 && columns.len() == 1;
+    !columns.is_empty()
+        && rows
+            .iter()
+            .all(|cells| cells.len() == columns.len() && !(blank_rows && cells[0].is_empty()))
+}
+
+/// Escapes a cell of a `table`, so it is split and trimmed back to itself
+fn escape_cell(cell: &str) -> String {
+    // Surrounding whitespace is escaped to keep it
+    let start = cell.len() - cell.trim_start().len();
+    let end = cell.trim_end().len();
+    let mut escaped = String::with_capacity(cell.len());
+    for (i, c) in cell.char_indices() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '|' => escaped.push_str("\\|"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c if c.is_whitespace() && (i < start || i >= end) => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn write_barcode(f: &mut Formatter<'_>, barcode: &Barcode) -> fmt::Result {
+    let data = barcode.program_data();
+    match barcode.symbology {
+        Symbology::Ean13 => write!(f, "ean13 {}", data)?,
+        Symbology::Ean8 => write!(f, "ean8 {}", data)?,
+        Symbology::UpcA => write!(f, "upca {}", data)?,
+        Symbology::UpcE => write!(f, "upce {}", data)?,
+        Symbology::Itf => write!(f, "itf {}", data)?,
+        Symbology::Code39 => write!(f, "code39 {}", Quoted(&data))?,
+        Symbology::Codabar => write!(f, "codabar {}", Quoted(&data))?,
+        Symbology::Code128 => write!(f, "code128 {}", Quoted(&data))?,
+    }
+
+    let options = &barcode.options;
+    let defaults = BarcodeOptions::default();
+    if options.hri != defaults.hri {
+        let hri = match options.hri {
+            HriPosition::None => "none",
+            HriPosition::Above => "above",
+            HriPosition::Below => "below",
+            HriPosition::Both => "both",
+        };
+        write!(f, " hri={}", hri)?;
+    }
+    if options.hri_font != defaults.hri_font {
+        let font = match options.hri_font {
+            HriFont::A => "a",
+            HriFont::B => "b",
+        };
+        write!(f, " hri_font={}", font)?;
+    }
+    if options.width != defaults.width {
+        write!(f, " width={}", options.width)?;
+    }
+    if options.height != defaults.height {
+        write!(f, " height={}", options.height)?;
+    }
+    Ok(())
+}
+
+fn write_code_2d(f: &mut Formatter<'_>, code: &Code2d) -> fmt::Result {
+    match code {
+        Code2d::QrCode(data, options) => {
+            write!(f, "qr_code {}", Quoted(data))?;
+            let defaults = QrOptions::default();
+            if options.size != defaults.size {
+                write!(f, " size={}", options.size)?;
+            }
+            if options.correction != defaults.correction {
+                let correction = match options.correction {
+                    QrCorrection::L => "l",
+                    QrCorrection::M => "m",
+                    QrCorrection::Q => "q",
+                    QrCorrection::H => "h",
+                };
+                write!(f, " correction={}", correction)?;
+            }
+            if options.model != defaults.model {
+                let model = match options.model {
+                    QrModel::Model1 => "1",
+                    QrModel::Model2 => "2",
+                    QrModel::Micro => "micro",
+                };
+                write!(f, " model={}", model)?;
+            }
+        }
+        Code2d::Pdf417(data, options) => {
+            write!(f, "pdf417 {}", Quoted(data))?;
+            let defaults = Pdf417Options::default();
+            if options.columns != defaults.columns {
+                write!(f, " columns={}", options.columns)?;
+            }
+            if options.rows != defaults.rows {
+                write!(f, " rows={}", options.rows)?;
+            }
+            if options.width != defaults.width {
+                write!(f, " width={}", options.width)?;
+            }
+            if options.row_height != defaults.row_height {
+                write!(f, " row_height={}", options.row_height)?;
+            }
+            if options.correction != defaults.correction {
+                write!(f, " correction={}", options.correction)?;
+            }
+            if options.truncated {
+                f.write_str(" truncated")?;
+            }
+        }
+        Code2d::DataMatrix(data, options) => {
+            write!(f, "datamatrix {}", Quoted(data))?;
+            if options.size != DataMatrixOptions::default().size {
+                write!(f, " size={}", options.size)?;
+            }
+            if options.rectangle {
+                f.write_str(" rectangle")?;
+            }
+        }
+        Code2d::Aztec(data, options) => {
+            write!(f, "aztec {}", Quoted(data))?;
+            let defaults = AztecOptions::default();
+            if options.size != defaults.size {
+                write!(f, " size={}", options.size)?;
+            }
+            if options.correction != defaults.correction {
+                write!(f, " correction={}", options.correction)?;
+            }
+            if options.compact {
+                f.write_str(" compact")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_image_options(f: &mut Formatter<'_>, options: &ImageOptions) -> fmt::Result {
+    if let Some(width) = options.width {
+        write!(f, " width={}", width)?;
+    }
+    let dither = &options.dither;
+    let defaults = dither::Options::default();
+    if dither.algorithm != defaults.algorithm {
+        write!(f, " dither={}", dither.algorithm.name())?;
+    }
+    if dither.threshold != defaults.threshold {
+        write!(f, " threshold={}", dither.threshold)?;
+    }
+    // Floats are written in their shortest form that parses to the same value
+    if dither.brightness != defaults.brightness {
+        write!(f, " brightness={}", dither.brightness)?;
+    }
+    if dither.contrast != defaults.contrast {
+        write!(f, " contrast={}", dither.contrast)?;
+    }
+    if dither.gamma != defaults.gamma {
+        write!(f, " gamma={}", dither.gamma)?;
+    }
+    if dither.invert {
+        f.write_str(" invert")?;
+    }
+    Ok(())
+}
+
+/// A string written as a `"..."` literal
+struct Quoted<'a>(&'a str);
+
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.0.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if c.is_control() => write!(f, "\\u{{{:x}}}", u32::from(c))?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn raw(command: printer::Command) -> Command {
+        Command::Raw(command)
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        let program = Program::parse_complete(
+            "writeln   \"Caf\\u{e9} \\\"Ada\\\"\"\nwrite \"a\\tb\u{7}\"\nfeed\n\
+             size 2,3\nupce 425261 hri=none\nqr_code \"x\" size=4 model=2\n\
+             table *., 8> \"\"\"\n  Coffee | $3.50\n  Tea | $2.00\n  \"\"\"\n\
+             image file \"logo.png\" dither=floyd_steinberg contrast=1.30\n",
+        )
+        .unwrap();
+        assert_eq!(
+            program.to_dsl(),
+            "writeln \"Café \\\"Ada\\\"\"\n\
+             write \"a\\tb\\u{7}\"\n\
+             feed 1\n\
+             size 2,3\n\
+             upce 04252614 hri=none\n\
+             qr_code \"x\" model=2\n\
+             table *., 8> \"Coffee|$3.50\\nTea|$2.00\"\n\
+             image file \"logo.png\" contrast=1.3\n"
+        );
+        assert_eq!(
+            raw(printer::Command::CodePage(16)).to_dsl(),
+            "<CodePage(16)>"
+        );
+    }
+
+    #[test]
+    fn test_table_cells() {
+        let column = Column {
+            width: ColumnWidth::Fill,
+            align: Align::Left,
+            dots: false,
+        };
+        let cells = |cells: &[&str]| cells.iter().map(|cell| cell.to_string()).collect();
+        let table = Command::Table {
+            columns: vec![column.clone(), column.clone()],
+            rows: vec![cells(&["a|b", " x "]), cells(&["line\nbreak", "\\"])],
+        };
+        let dsl = table.to_dsl();
+        assert_eq!(dsl, r#"table *, * "a\\|b|\\ x\\ \nline\\nbreak|\\\\""#);
+        assert_eq!(Command::parse(&dsl), Ok(("", table)));
+
+        // Rows that don't fit the columns can't be written
+        let table = Command::Table {
+            columns: vec![column.clone(), column],
+            rows: vec![cells(&["a"]), cells(&["b", "c"])],
+        };
+        assert!(table.to_dsl().starts_with("<Table"));
+    }
+
+    /// Text with quotes, backslashes, control characters and non-ASCII
+    /// characters
+    fn text() -> impl Strategy<Value = String> {
+        "(?s).{0,12}"
+    }
+
+    fn barcode() -> impl Strategy<Value = Barcode> {
+        let data = prop_oneof![
+            "[0-9]{12}".prop_map(|data| (Symbology::Ean13, data)),
+            "[0-9]{7}".prop_map(|data| (Symbology::Ean8, data)),
+            "[0-9]{11}".prop_map(|data| (Symbology::UpcA, data)),
+            "[01][0-9]{6}".prop_map(|data| (Symbology::UpcE, data)),
+            "([0-9]{2}){1,6}".prop_map(|data| (Symbology::Itf, data)),
+            "[0-9A-Z $%+./-]{1,12}".prop_map(|data| (Symbology::Code39, data)),
+            "[0-9$:/.+-]{1,12}".prop_map(|data| (Symbology::Codabar, data)),
+            "[ -~]{1,12}".prop_map(|data| (Symbology::Code128, data)),
+        ];
+        let options = (
+            prop_oneof![
+                Just(HriPosition::None),
+                Just(HriPosition::Above),
+                Just(HriPosition::Below),
+                Just(HriPosition::Both),
+            ],
+            prop_oneof![Just(HriFont::A), Just(HriFont::B)],
+            1..=6u8,
+            1..=255u8,
+        )
+            .prop_map(|(hri, hri_font, width, height)| BarcodeOptions {
+                width,
+                height,
+                hri,
+                hri_font,
+            });
+        (data, options).prop_map(|((symbology, data), options)| {
+            Barcode::new(symbology, &data, options).unwrap()
+        })
+    }
+
+    fn code_2d() -> impl Strategy<Value = Code2d> {
+        let qr = (
+            prop_oneof![
+                Just(QrModel::Model1),
+                Just(QrModel::Model2),
+                Just(QrModel::Micro),
+            ],
+            1..=16u8,
+            prop_oneof![
+                Just(QrCorrection::L),
+                Just(QrCorrection::M),
+                Just(QrCorrection::Q),
+                Just(QrCorrection::H),
+            ],
+        )
+            .prop_map(|(model, size, correction)| QrOptions {
+                model,
+                size,
+                correction,
+            });
+        let pdf417 = (
+            0..=30u8,
+            prop_oneof![Just(0u8), 3..=90u8],
+            2..=8u8,
+            2..=8u8,
+            0..=8u8,
+            any::<bool>(),
+        )
+            .prop_map(
+                |(columns, rows, width, row_height, correction, truncated)| Pdf417Options {
+                    columns,
+                    rows,
+                    width,
+                    row_height,
+                    correction,
+                    truncated,
+                },
+            );
+        let data_matrix = (2..=16u8, any::<bool>())
+            .prop_map(|(size, rectangle)| DataMatrixOptions { size, rectangle });
+        let aztec = (2..=16u8, 5..=95u8, any::<bool>()).prop_map(|(size, correction, compact)| {
+            AztecOptions {
+                size,
+                correction,
+                compact,
+            }
+        });
+        prop_oneof![
+            (text(), qr).prop_map(|(data, options)| Code2d::QrCode(data, options)),
+            (text(), pdf417).prop_map(|(data, options)| Code2d::Pdf417(data, options)),
+            (text(), data_matrix).prop_map(|(data, options)| Code2d::DataMatrix(data, options)),
+            (text(), aztec).prop_map(|(data, options)| Code2d::Aztec(data, options)),
+        ]
+    }
+
+    fn image_options() -> impl Strategy<Value = ImageOptions> {
+        (
+            proptest::option::of(1..=2000u32),
+            prop_oneof![
+                Just(dither::Algorithm::FloydSteinberg),
+                Just(dither::Algorithm::Atkinson),
+                Just(dither::Algorithm::Bayer),
+                Just(dither::Algorithm::Threshold),
+            ],
+            any::<u8>(),
+            -1.0..=1.0f32,
+            0.0..4.0f32,
+            0.1..4.0f32,
+            any::<bool>(),
+        )
+            .prop_map(
+                |(width, algorithm, threshold, brightness, contrast, gamma, invert)| ImageOptions {
+                    width,
+                    dither: dither::Options {
+                        algorithm,
+                        threshold,
+                        brightness,
+                        contrast,
+                        gamma,
+                        invert,
+                    },
+                },
+            )
+    }
+
+    fn table() -> impl Strategy<Value = Command> {
+        let column = (
+            prop_oneof![
+                Just(ColumnWidth::Fill),
+                (1..=48u8).prop_map(ColumnWidth::Fixed)
+            ],
+            prop_oneof![Just(Align::Left), Just(Align::Right)],
+            any::<bool>(),
+        )
+            .prop_map(|(width, align, dots)| Column { width, align, dots });
+        proptest::collection::vec(column, 1..4).prop_flat_map(|columns| {
+            let count = columns.len();
+            let row = proptest::collection::vec(text(), count).prop_map(|cells| vec![cells]);
+            // Blank lines of single column tables are skipped when parsing
+            let rows = proptest::collection::vec(
+                proptest::collection::vec(text(), count).prop_filter("blank line", |cells| {
+                    cells.len() > 1 || !cells[0].is_empty()
+                }),
+                0..4,
+            )
+            .prop_filter("single row", |rows| rows.len() != 1);
+            (Just(columns), prop_oneof![row, rows])
+                .prop_map(|(columns, rows)| Command::Table { columns, rows })
+        })
+    }
+
+    fn command() -> impl Strategy<Value = Command> {
+        let justify = prop_oneof![
+            Just(JustifyMode::LEFT),
+            Just(JustifyMode::CENTER),
+            Just(JustifyMode::RIGHT),
+        ];
+        let font = prop_oneof![Just(Font::A), Just(Font::B), Just(Font::C)];
+        let underline = prop_oneof![
+            Just(UnderlineMode::None),
+            Just(UnderlineMode::Single),
+            Just(UnderlineMode::Double),
+        ];
+        let image_source = prop_oneof![
+            text().prop_map(ImageSource::Base64),
+            text().prop_map(ImageSource::Url),
+            text().prop_map(ImageSource::File),
+        ];
+        prop_oneof![
+            text().prop_map(|text| raw(printer::Command::Write(text))),
+            any::<bool>().prop_map(|bold| raw(printer::Command::Bold(bold))),
+            underline.prop_map(|mode| raw(printer::Command::Underline(mode))),
+            any::<bool>().prop_map(|mode| raw(printer::Command::DoubleStrike(mode))),
+            font.prop_map(|font| raw(printer::Command::Font(font))),
+            any::<bool>().prop_map(|flip| raw(printer::Command::Flip(flip))),
+            justify.prop_map(|mode| raw(printer::Command::Justify(mode))),
+            any::<bool>().prop_map(|reverse| raw(printer::Command::Reverse(reverse))),
+            any::<u8>().prop_map(|lines| raw(printer::Command::Feed(lines))),
+            barcode().prop_map(|barcode| raw(printer::Command::Barcode(barcode))),
+            code_2d().prop_map(|code| raw(printer::Command::Code2d(code))),
+            (1..=8u8, 1..=8u8)
+                .prop_map(|(width, height)| raw(printer::Command::Size(width, height))),
+            Just(raw(printer::Command::ResetSize)),
+            Just(raw(printer::Command::Cut)),
+            Just(Command::Sudoku),
+            Just(Command::MiniCrossword),
+            text().prop_map(Command::ToDo),
+            text().prop_map(Command::Markdown),
+            table(),
+            (image_source, image_options())
+                .prop_map(|(source, options)| Command::Image { source, options }),
+            (text(), image_options())
+                .prop_map(|(source, options)| Command::Svg { source, options }),
+            (text(), proptest::option::of(text()), 4..=72u8).prop_map(|(text, font, size)| {
+                Command::TextImage {
+                    text,
+                    options: TextImageOptions { font, size },
+                }
+            }),
+            text().prop_map(Command::Include),
+            proptest::option::of("[a-z]{2,3}[0-9_]{1,4}").prop_map(Command::CodePage),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(commands in proptest::collection::vec(command(), 0..8)) {
+            let program = Program { commands };
+            let dsl = program.to_dsl();
+            prop_assert_eq!(Program::parse(&dsl), Ok(("", program)), "{}", dsl);
+        }
+    }
+}
@@ -39,6 +39,12 @@ impl PrinterMetadata {
     }
 }
 
+impl Default for PrinterMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Entry in the printer registry containing the printer, profile, and metadata
 #[derive(Debug)]
 pub struct PrinterEntry {
@@ -76,6 +82,12 @@ pub struct PrinterRegistry {
     event_tx: broadcast::Sender<RegistryEvent>,
 }
 
+impl Default for PrinterRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PrinterRegistry {
     pub fn new() -> Self {
         let (event_tx, _) = broadcast::channel(100);
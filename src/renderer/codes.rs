@@ -0,0 +1,271 @@
+//! Software rendering of barcodes and QR codes as images, for printers that
+//! lack native support
+use ::image::{GrayImage, Luma};
+use barcoders::sym::{codabar::Codabar, code128::Code128, code39::Code39, ean13::EAN13};
+use barcoders::sym::{ean8::EAN8, tf::TF};
+use qrcode::{Color, EcLevel, QrCode};
+use thiserror::Error;
+
+use crate::dither;
+use crate::printer;
+use crate::printer::barcode::{Barcode, HriPosition, Symbology};
+use crate::printer::codes_2d::{QrCorrection, QrOptions};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("could not encode barcode: {0}")]
+    Barcode(#[from] barcoders::error::Error),
+    #[error("could not encode QR code: {0}")]
+    QrCode(#[from] qrcode::types::QrError),
+    #[error("could not encode image: {0}")]
+    Image(#[from] ::image::ImageError),
+    #[error("{code} is {needed} dots wide at the smallest, wider than the paper ({width} dots)")]
+    TooWide {
+        code: &'static str,
+        needed: u32,
+        width: u32,
+    },
+}
+
+/// Modules of white space around barcodes
+const BARCODE_QUIET_ZONE: u32 = 10;
+/// Modules of white space around QR codes
+const QR_QUIET_ZONE: u32 = 4;
+
+/// Renders a barcode as an image, with the human readable data printed as
+/// text above or below it
+///
+/// Bars are drawn `options.width` dots wide, narrower if the barcode would not
+/// fit on the paper. Barcodes that don't fit even with bars of a single dot
+/// are rejected.
+pub fn make_barcode(barcode: &Barcode, width_px: u32) -> Result<Vec<printer::Command>, Error> {
+    let data = &barcode.data;
+    let modules = match barcode.symbology {
+        Symbology::Ean13 => EAN13::new(data)?.encode(),
+        Symbology::UpcA => EAN13::new(format!("0{}", data))?.encode(),
+        Symbology::UpcE => upc_e(&barcode.program_data()),
+        Symbology::Ean8 => EAN8::new(data)?.encode(),
+        Symbology::Code39 => Code39::new(data)?.encode(),
+        Symbology::Itf => TF::interleaved(data)?.encode(),
+        Symbology::Codabar => Codabar::new(data)?.encode(),
+        Symbology::Code128 => {
+            // Code set C encodes digit pairs, like the native encoding
+            let digits = data.bytes().all(|b| b.is_ascii_digit());
+            let set = if digits && data.len() >= 4 && data.len().is_multiple_of(2) {
+                'Ć'
+            } else {
+                'Ɓ'
+            };
+            Code128::new(format!("{}{}", set, data))?.encode()
+        }
+    };
+
+    let total = modules.len() as u32 + 2 * BARCODE_QUIET_ZONE;
+    if total > width_px {
+        return Err(Error::TooWide {
+            code: barcode.symbology.name(),
+            needed: total,
+            width: width_px,
+        });
+    }
+    let module_width = u32::from(barcode.options.width).min(width_px / total);
+    let height = u32::from(barcode.options.height);
+    let image = GrayImage::from_fn(total * module_width, height, |x, _| {
+        let module = (x / module_width).checked_sub(BARCODE_QUIET_ZONE);
+        let black = module.and_then(|module| modules.get(module as usize)) == Some(&1);
+        Luma([if black { 0 } else { 255 }])
+    });
+
+    let text = printer::Command::Write(format!("{}\n", barcode.program_data()));
+    let hri = barcode.options.hri;
+    let mut commands = Vec::new();
+    if matches!(hri, HriPosition::Above | HriPosition::Both) {
+        commands.push(text.clone());
+    }
+    commands.push(bit_image(image)?);
+    if matches!(hri, HriPosition::Below | HriPosition::Both) {
+        commands.push(text);
+    }
+    Ok(commands)
+}
+
+/// Digits of the left half of EAN codes with odd parity, one bit per module
+const ODD_PARITY: [u8; 10] = [
+    0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011, 0b0110001, 0b0101111, 0b0111011,
+    0b0110111, 0b0001011,
+];
+/// Digits of the left half of EAN codes with even parity
+const EVEN_PARITY: [u8; 10] = [
+    0b0100111, 0b0110011, 0b0011011, 0b0100001, 0b0011101, 0b0111001, 0b0000101, 0b0010001,
+    0b0001001, 0b0010111,
+];
+/// Digits with even parity in UPC-E codes of number system 0, one bit per
+/// digit from the left, by check digit. Number system 1 uses the opposite.
+const UPC_E_PARITY: [u8; 10] = [
+    0b111000, 0b110100, 0b110010, 0b110001, 0b101100, 0b100110, 0b100011, 0b101010, 0b101001,
+    0b100101,
+];
+
+/// Modules of a UPC-E code given as its number system, six digits and check
+/// digit, which is only encoded in the parity of the digits
+fn upc_e(data: &str) -> Vec<u8> {
+    let digits: Vec<usize> = data
+        .bytes()
+        .map(|digit| usize::from(digit - b'0'))
+        .collect();
+    let mut parity = UPC_E_PARITY[digits[7]];
+    if digits[0] == 1 {
+        parity = !parity & 0b111111;
+    }
+
+    let mut modules = vec![1, 0, 1];
+    for (index, digit) in digits[1..7].iter().enumerate() {
+        let even = parity & (0b100000 >> index) != 0;
+        let pattern = if even {
+            EVEN_PARITY[*digit]
+        } else {
+            ODD_PARITY[*digit]
+        };
+        modules.extend((0..7).rev().map(|bit| (pattern >> bit) & 1));
+    }
+    modules.extend([0, 1, 0, 1, 0, 1]);
+    modules
+}
+
+/// Renders a QR code as an image with modules of `options.size` dots, smaller
+/// if the code would not fit on the paper, and rejects codes that don't fit
+/// even with modules of a single dot
+///
+/// Model 1 and Micro QR codes are drawn as regular (model 2) QR codes, which
+/// all scanners read.
+pub fn make_qr_code(
+    data: &str,
+    options: &QrOptions,
+    width_px: u32,
+) -> Result<Vec<printer::Command>, Error> {
+    let level = match options.correction {
+        QrCorrection::L => EcLevel::L,
+        QrCorrection::M => EcLevel::M,
+        QrCorrection::Q => EcLevel::Q,
+        QrCorrection::H => EcLevel::H,
+    };
+    let code = QrCode::with_error_correction_level(data, level)?;
+    let colors = code.to_colors();
+    let modules = code.width() as u32;
+
+    let total = modules + 2 * QR_QUIET_ZONE;
+    if total > width_px {
+        return Err(Error::TooWide {
+            code: "QR code",
+            needed: total,
+            width: width_px,
+        });
+    }
+    let size = u32::from(options.size).min(width_px / total);
+    let module = |position: u32| (position / size).checked_sub(QR_QUIET_ZONE);
+    let image = GrayImage::from_fn(total * size, total * size, |x, y| {
+        let color = match (module(x), module(y)) {
+            (Some(x), Some(y)) if x < modules && y < modules => colors[(y * modules + x) as usize],
+            _ => Color::Light,
+        };
+        Luma([if color == Color::Dark { 0 } else { 255 }])
+    });
+
+    Ok(vec![bit_image(image)?])
+}
+
+fn bit_image(image: GrayImage) -> Result<printer::Command, Error> {
+    let width = image.width();
+    Ok(printer::Command::BitImageFromBytesWithWidth(
+        dither::encode_png(image)?,
+        width,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::barcode::BarcodeOptions;
+
+    fn bitmap(command: &printer::Command) -> GrayImage {
+        let printer::Command::BitImageFromBytesWithWidth(png, _) = command else {
+            panic!("expected an image, got {:?}", command);
+        };
+        ::image::load_from_memory(png).unwrap().to_luma8()
+    }
+
+    #[test]
+    fn test_barcode() {
+        let options = BarcodeOptions {
+            width: 2,
+            height: 40,
+            hri: HriPosition::Above,
+            ..BarcodeOptions::default()
+        };
+        let barcode = Barcode::new(Symbology::Ean8, "9638507", options).unwrap();
+        let commands = make_barcode(&barcode, 512).unwrap();
+
+        assert_eq!(
+            commands[0],
+            printer::Command::Write(String::from("96385074\n"))
+        );
+        let image = bitmap(&commands[1]);
+        // 67 modules of EAN-8 and the quiet zones, 2 dots each
+        assert_eq!(image.dimensions(), (174, 40));
+        assert_eq!(image.get_pixel(19, 0).0, [255]);
+        assert_eq!(image.get_pixel(20, 0).0, [0]);
+
+        // Bars get narrower on small paper, down to a single dot
+        let image = bitmap(&make_barcode(&barcode, 100).unwrap()[1]);
+        assert_eq!(image.width(), 87);
+        let image = bitmap(&make_barcode(&barcode, 87).unwrap()[1]);
+        assert_eq!(image.width(), 87);
+        assert_eq!(
+            make_barcode(&barcode, 86).unwrap_err().to_string(),
+            "EAN-8 is 87 dots wide at the smallest, wider than the paper (86 dots)"
+        );
+    }
+
+    #[test]
+    fn test_upc_e() {
+        let modules: String = upc_e("04252614")
+            .iter()
+            .map(|module| char::from(b'0' + module))
+            .collect();
+        // Check digit 4 gives the parities even, odd, even, even, odd, odd
+        assert_eq!(
+            modules,
+            ["101", "0011101", "0010011", "0111001", "0011011", "0101111", "0011001", "010101"]
+                .concat()
+        );
+
+        let options = BarcodeOptions {
+            width: 1,
+            ..BarcodeOptions::default()
+        };
+        let barcode = Barcode::new(Symbology::UpcE, "0425261", options).unwrap();
+        let commands = make_barcode(&barcode, 512).unwrap();
+        // 51 modules of UPC-E and the quiet zones, not the 95 of UPC-A
+        assert_eq!(bitmap(&commands[0]).width(), 71);
+        assert_eq!(
+            commands[1],
+            printer::Command::Write(String::from("04252614\n"))
+        );
+    }
+
+    #[test]
+    fn test_qr_code() {
+        let commands = make_qr_code("Hello", &QrOptions::default(), 512).unwrap();
+        let image = bitmap(&commands[0]);
+        // Version 1 with 21 modules and the quiet zones, 4 dots each
+        assert_eq!(image.dimensions(), (116, 116));
+        assert_eq!(image.get_pixel(15, 15).0, [255]);
+        assert_eq!(image.get_pixel(16, 16).0, [0]);
+
+        assert!(make_qr_code("Hello", &QrOptions::default(), 29).is_ok());
+        assert!(matches!(
+            make_qr_code("Hello", &QrOptions::default(), 28),
+            Err(Error::TooWide { needed: 29, .. })
+        ));
+    }
+}
@@ -0,0 +1,398 @@
+//! Loading, scaling and dithering of `image` commands
+use ::image::imageops::{self, FilterType};
+use ::image::DynamicImage;
+use base64::Engine;
+use once_cell::sync::Lazy;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::printer;
+use crate::program::{ImageOptions, ImageSource, TextImageOptions};
+use crate::truetype::{self, FontSource};
+use crate::{dither, svg};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid base64 image data: {0}")]
+    Base64(#[from] base64::DecodeError),
+    #[error("could not download image: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("image is larger than {} bytes", MAX_IMAGE_BYTES)]
+    TooLarge,
+    #[error("could not read image file: {0}")]
+    File(#[from] std::io::Error),
+    #[error("{0} is outside of the image directory")]
+    OutsideDirectory(String),
+    #[error("image files are disabled, no image directory is configured")]
+    NoDirectory,
+    #[error("{0} is outside of the font directory")]
+    OutsideFontDirectory(String),
+    #[error("font files are disabled, no font directory is configured")]
+    NoFontDirectory,
+    #[error("unsupported image: {0}")]
+    Image(#[from] ::image::ImageError),
+    #[error("{0}")]
+    Svg(#[from] svg::Error),
+    #[error("{0}")]
+    Font(#[from] truetype::Error),
+    #[error("image processing failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+/// Downloads larger than this are aborted
+const MAX_IMAGE_BYTES: usize = 16 * 1024 * 1024;
+
+/// Client for `image url` commands, with timeouts so a slow server can't hold
+/// up the jobs of all printers
+static CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(5))
+        .read_timeout(Duration::from_secs(10))
+        .timeout(Duration::from_secs(30))
+        .build()
+        .expect("could not create HTTP client")
+});
+
+/// Loads an image and converts it to a black and white raster image
+///
+/// The image is scaled to `options.width`, or to `width_px` (the paper width) if
+/// no width is given, keeping its aspect ratio. Image files are read from
+/// `image_dir` only.
+pub async fn make_image(
+    source: &ImageSource,
+    options: &ImageOptions,
+    width_px: u32,
+    image_dir: Option<&Path>,
+) -> Result<Vec<printer::Command>, Error> {
+    let bytes = load(source, image_dir).await?;
+    let width = image_width(options, width_px);
+    let dither_options = options.dither.clone();
+
+    blocking(move || {
+        let image = ::image::load_from_memory(&bytes)?;
+        let bitmap = dither::process(&dither::grayscale(&scale(&image, width)), &dither_options);
+        let png = dither::encode_png(bitmap)?;
+
+        Ok(vec![printer::Command::BitImageFromBytesWithWidth(
+            png, width,
+        )])
+    })
+    .await
+}
+
+/// Rasterizes an SVG document at `dpi` and converts it to a black and white
+/// raster image, scaled like [`make_image`]
+pub async fn make_svg(
+    source: &str,
+    options: &ImageOptions,
+    dpi: u16,
+    width_px: u32,
+) -> Result<Vec<printer::Command>, Error> {
+    let width = image_width(options, width_px);
+    let source = source.to_string();
+    let dither_options = options.dither.clone();
+
+    blocking(move || {
+        let pixmap = svg::rasterize(&source, dpi, width)?;
+        let bitmap = dither::process(&dither::grayscale_pixmap(&pixmap), &dither_options);
+        let png = dither::encode_png(bitmap)?;
+
+        Ok(vec![printer::Command::BitImageFromBytesWithWidth(
+            png, width,
+        )])
+    })
+    .await
+}
+
+/// Renders text with a TrueType font at `dpi`, wrapped to `width_px`, and
+/// converts it to a black and white raster image
+///
+/// Font files are read from `font_dir` only.
+pub async fn make_text_image(
+    text: &str,
+    options: &TextImageOptions,
+    dpi: u16,
+    width_px: u32,
+    font_dir: Option<&Path>,
+) -> Result<Vec<printer::Command>, Error> {
+    let font_data = match &options.font {
+        Some(font) if is_font_file(font) => {
+            let dir = font_dir.ok_or(Error::NoFontDirectory)?;
+            let path = resolve(dir, font)
+                .await?
+                .ok_or_else(|| Error::OutsideFontDirectory(font.clone()))?;
+            Some(tokio::fs::read(path).await?)
+        }
+        _ => None,
+    };
+    let family = options.font.clone();
+    let text = text.to_string();
+    // Points are 1/72 inch
+    let size_px = f32::from(options.size) * f32::from(dpi) / 72.0;
+
+    blocking(move || {
+        let font = match (font_data, &family) {
+            (Some(data), _) => FontSource::Data(data),
+            (None, Some(family)) => FontSource::Family(family),
+            (None, None) => FontSource::Default,
+        };
+        let pixmap = truetype::rasterize(&text, font, size_px, width_px)?;
+        let width = pixmap.width();
+        let bitmap = dither::process(
+            &dither::grayscale_pixmap(&pixmap),
+            &dither::Options::threshold(),
+        );
+        let png = dither::encode_png(bitmap)?;
+
+        Ok(vec![printer::Command::BitImageFromBytesWithWidth(
+            png, width,
+        )])
+    })
+    .await
+}
+
+/// Runs the decoding, rasterizing and dithering of an image on the blocking
+/// thread pool, so that large images don't hold up the jobs of other printers
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    tokio::task::spawn_blocking(work).await?
+}
+
+/// The width an image is scaled to, at most the paper width
+fn image_width(options: &ImageOptions, width_px: u32) -> u32 {
+    options.width.unwrap_or(width_px).min(width_px).max(1)
+}
+
+fn is_font_file(font: &str) -> bool {
+    std::path::Path::new(font)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ["ttf", "otf", "ttc"].contains(&extension.to_lowercase().as_str()))
+}
+
+async fn load(source: &ImageSource, image_dir: Option<&Path>) -> Result<Vec<u8>, Error> {
+    match source {
+        ImageSource::Base64(data) => {
+            // Accept data URLs like `data:image/png;base64,...`
+            let data = data.split_once(',').map_or(data.as_str(), |(_, data)| data);
+            let data: String = data.chars().filter(|c| !c.is_whitespace()).collect();
+            Ok(base64::engine::general_purpose::STANDARD.decode(data)?)
+        }
+        ImageSource::Url(url) => download(url).await,
+        ImageSource::File(path) => {
+            let dir = image_dir.ok_or(Error::NoDirectory)?;
+            let resolved = resolve(dir, path)
+                .await?
+                .ok_or_else(|| Error::OutsideDirectory(path.clone()))?;
+            Ok(tokio::fs::read(resolved).await?)
+        }
+    }
+}
+
+/// Downloads an image of at most [`MAX_IMAGE_BYTES`]
+async fn download(url: &str) -> Result<Vec<u8>, Error> {
+    let mut response = CLIENT.get(url).send().await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_IMAGE_BYTES as u64)
+    {
+        return Err(Error::TooLarge);
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > MAX_IMAGE_BYTES {
+            return Err(Error::TooLarge);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Resolves `path` relative to `dir`, or `None` if it leads out of it, also
+/// through `..` or symbolic links
+async fn resolve(dir: &Path, path: &str) -> std::io::Result<Option<PathBuf>> {
+    let dir = tokio::fs::canonicalize(dir).await?;
+    let resolved = tokio::fs::canonicalize(dir.join(path)).await?;
+    Ok(resolved.starts_with(&dir).then_some(resolved))
+}
+
+/// Scales the image to `width`, keeping its aspect ratio
+fn scale(image: &DynamicImage, width: u32) -> DynamicImage {
+    let height = (u64::from(image.height()) * u64::from(width) / u64::from(image.width().max(1)))
+        .max(1) as u32;
+    DynamicImage::ImageRgba8(imageops::resize(
+        &image.to_rgba8(),
+        width,
+        height,
+        FilterType::Triangle,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::image::RgbaImage;
+
+    #[test]
+    fn test_scale_flattens_alpha() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 2, |x, _| {
+            if x < 2 {
+                ::image::Rgba([0, 0, 0, 255])
+            } else {
+                ::image::Rgba([0, 0, 0, 0])
+            }
+        }));
+
+        let scaled = dither::grayscale(&scale(&image, 8));
+        assert_eq!(scaled.dimensions(), (8, 4));
+        assert_eq!(scaled.get_pixel(0, 0).0, [0]);
+        assert_eq!(scaled.get_pixel(7, 3).0, [255]);
+    }
+
+    #[tokio::test]
+    async fn test_base64_image() {
+        let source = ImageSource::Base64(String::from(
+            "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAAAAAA6fptVAAAACklEQVR4nGNgAAAAAgABSK+kcQAAAABJRU5ErkJggg==",
+        ));
+        let commands = make_image(
+            &source,
+            &ImageOptions {
+                width: Some(16),
+                ..ImageOptions::default()
+            },
+            512,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let [printer::Command::BitImageFromBytesWithWidth(png, 16)] = commands.as_slice() else {
+            panic!("expected a single image, got {:?}", commands);
+        };
+        let bitmap = ::image::load_from_memory(png).unwrap().to_luma8();
+        assert_eq!(bitmap.dimensions(), (16, 16));
+        assert!(bitmap
+            .pixels()
+            .all(|pixel| pixel.0 == [0] || pixel.0 == [255]));
+
+        let invalid = ImageSource::Base64(String::from("not an image"));
+        assert!(matches!(
+            make_image(&invalid, &ImageOptions::default(), 512, None).await,
+            Err(Error::Base64(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_image_file_confined_to_directory() {
+        let root = std::env::temp_dir().join(format!("escpos2mqtt-images-{}", std::process::id()));
+        let dir = root.join("images");
+        std::fs::create_dir_all(&dir).unwrap();
+        let png = base64::engine::general_purpose::STANDARD
+            .decode("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAAAAAA6fptVAAAACklEQVR4nGNgAAAAAgABSK+kcQAAAABJRU5ErkJggg==")
+            .unwrap();
+        std::fs::write(dir.join("dot.png"), &png).unwrap();
+        std::fs::write(root.join("secret.png"), &png).unwrap();
+
+        let image = |path: &str| ImageSource::File(String::from(path));
+        let options = ImageOptions::default();
+        assert!(make_image(&image("dot.png"), &options, 8, Some(&dir))
+            .await
+            .is_ok());
+        let absolute = dir.join("dot.png");
+        assert!(
+            make_image(&image(absolute.to_str().unwrap()), &options, 8, Some(&dir))
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            make_image(&image("../secret.png"), &options, 8, Some(&dir)).await,
+            Err(Error::OutsideDirectory(_))
+        ));
+        assert!(matches!(
+            make_image(&image("dot.png"), &options, 8, None).await,
+            Err(Error::NoDirectory)
+        ));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_width_clamped_to_paper() {
+        let source = ImageSource::Base64(String::from(
+            "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAAAAAA6fptVAAAACklEQVR4nGNgAAAAAgABSK+kcQAAAABJRU5ErkJggg==",
+        ));
+        let options = ImageOptions {
+            width: Some(60000),
+            ..ImageOptions::default()
+        };
+        let commands = make_image(&source, &options, 64, None).await.unwrap();
+        assert!(matches!(
+            commands.as_slice(),
+            [printer::Command::BitImageFromBytesWithWidth(_, 64)]
+        ));
+
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"/>"#;
+        let commands = make_svg(svg, &options, 180, 64).await.unwrap();
+        assert!(matches!(
+            commands.as_slice(),
+            [printer::Command::BitImageFromBytesWithWidth(_, 64)]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_svg() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="20mm" height="10mm"><rect width="10mm" height="10mm"/></svg>"#;
+        let commands = make_svg(svg, &ImageOptions::default(), 180, 64)
+            .await
+            .unwrap();
+
+        let [printer::Command::BitImageFromBytesWithWidth(png, 64)] = commands.as_slice() else {
+            panic!("expected a single image, got {:?}", commands);
+        };
+        let bitmap = ::image::load_from_memory(png).unwrap().to_luma8();
+        assert_eq!(bitmap.dimensions(), (64, 32));
+        assert_eq!(bitmap.get_pixel(8, 8).0, [0]);
+        assert_eq!(bitmap.get_pixel(56, 8).0, [255]);
+    }
+
+    #[tokio::test]
+    async fn test_text_image() {
+        let options = TextImageOptions::default();
+        let commands = make_text_image("Hello", &options, 180, 512, None)
+            .await
+            .unwrap();
+        let [printer::Command::BitImageFromBytesWithWidth(png, width)] = commands.as_slice() else {
+            panic!("expected a single image, got {:?}", commands);
+        };
+        let bitmap = ::image::load_from_memory(png).unwrap().to_luma8();
+        assert_eq!(bitmap.width(), *width);
+        // 10 points at 180 dpi are 25 pixels per em
+        assert!((25..40).contains(&bitmap.height()));
+        assert!(bitmap.pixels().any(|pixel| pixel.0 == [0]));
+
+        let font = |path: &str| TextImageOptions {
+            font: Some(String::from(path)),
+            ..TextImageOptions::default()
+        };
+        let root = std::env::temp_dir().join(format!("escpos2mqtt-fonts-{}", std::process::id()));
+        let dir = root.join("fonts");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(root.join("secret.ttf"), b"secret").unwrap();
+        assert!(matches!(
+            make_text_image("Hello", &font("nonexistent.ttf"), 180, 512, Some(&dir)).await,
+            Err(Error::File(_))
+        ));
+        assert!(matches!(
+            make_text_image("Hello", &font("../secret.ttf"), 180, 512, Some(&dir)).await,
+            Err(Error::OutsideFontDirectory(_))
+        ));
+        assert!(matches!(
+            make_text_image("Hello", &font("../secret.ttf"), 180, 512, None).await,
+            Err(Error::NoFontDirectory)
+        ));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::program::template::Variables;
+use crate::program::{Command, Diagnostic, Program};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("unknown template '{0}'")]
+    UnknownTemplate(String),
+    #[error("include cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("template '{0}': {1}")]
+    Parse(String, Diagnostic),
+    #[error("templates are nested more than {} levels deep", MAX_DEPTH)]
+    TooDeep,
+    #[error("templates expand to more than {} commands", MAX_COMMANDS)]
+    TooLarge,
+}
+
+/// Levels of templates including each other
+const MAX_DEPTH: usize = 16;
+/// Commands and includes a program may expand to, so that templates including
+/// others several times can't blow up exponentially
+const MAX_COMMANDS: usize = 100_000;
+
+/// Replaces every `include` in `program` with the commands of the named template
+///
+/// Included templates are compiled with `variables` (if any), so they may use
+/// blocks and placeholders, and may include further templates. Include cycles
+/// are reported as [`Error::Cycle`], programs exceeding [`MAX_DEPTH`] or
+/// [`MAX_COMMANDS`] as [`Error::TooDeep`] and [`Error::TooLarge`].
+pub fn resolve_includes(
+    program: Program,
+    templates: &HashMap<String, String>,
+    variables: Option<&Variables>,
+) -> Result<Program, Error> {
+    let mut resolver = Resolver {
+        templates,
+        variables,
+        compiled: HashMap::new(),
+        stack: Vec::new(),
+        count: 0,
+    };
+    Ok(Program {
+        commands: resolver.resolve(program.commands)?,
+    })
+}
+
+struct Resolver<'a> {
+    templates: &'a HashMap<String, String>,
+    variables: Option<&'a Variables>,
+    /// Templates compiled so far, as they are the same on every include
+    compiled: HashMap<String, Vec<Command>>,
+    /// Templates currently being included
+    stack: Vec<String>,
+    /// Commands resolved so far
+    count: usize,
+}
+
+impl Resolver<'_> {
+    fn resolve(&mut self, commands: Vec<Command>) -> Result<Vec<Command>, Error> {
+        let mut resolved = Vec::with_capacity(commands.len());
+
+        for command in commands {
+            self.count += 1;
+            if self.count > MAX_COMMANDS {
+                return Err(Error::TooLarge);
+            }
+
+            let Command::Include(name) = command else {
+                resolved.push(command);
+                continue;
+            };
+
+            if self.stack.contains(&name) {
+                let mut cycle = self.stack.clone();
+                cycle.push(name);
+                return Err(Error::Cycle(cycle));
+            }
+            if self.stack.len() >= MAX_DEPTH {
+                return Err(Error::TooDeep);
+            }
+
+            let included = self.compile(&name)?;
+            self.stack.push(name);
+            resolved.extend(self.resolve(included)?);
+            self.stack.pop();
+        }
+
+        Ok(resolved)
+    }
+
+    fn compile(&mut self, name: &str) -> Result<Vec<Command>, Error> {
+        if let Some(commands) = self.compiled.get(name) {
+            return Ok(commands.clone());
+        }
+
+        let source = self
+            .templates
+            .get(name)
+            .ok_or_else(|| Error::UnknownTemplate(name.to_string()))?;
+        let included = Program::compile(source, self.variables)
+            .map_err(|err| Error::Parse(name.to_string(), err))?;
+        self.compiled
+            .insert(name.to_string(), included.commands.clone());
+
+        Ok(included.commands)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer;
+
+    fn templates(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_nested_includes() {
+        let templates = templates(&[
+            ("header", "bold true\ninclude \"title\"\nbold false"),
+            ("title", "writeln \"Shopping\""),
+        ]);
+        let program = Program::parse_complete("include \"header\"\ncut").unwrap();
+
+        assert_eq!(
+            resolve_includes(program, &templates, None),
+            Ok(Program {
+                commands: vec![
+                    Command::Raw(printer::Command::Bold(true)),
+                    Command::Raw(printer::Command::Write(String::from("Shopping\n"))),
+                    Command::Raw(printer::Command::Bold(false)),
+                    Command::Raw(printer::Command::Cut),
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn test_include_errors() {
+        let templates = templates(&[("a", "include \"b\""), ("b", "include \"a\"")]);
+
+        let program = Program::parse_complete("include \"a\"").unwrap();
+        assert_eq!(
+            resolve_includes(program, &templates, None),
+            Err(Error::Cycle(vec![
+                String::from("a"),
+                String::from("b"),
+                String::from("a")
+            ]))
+        );
+
+        let program = Program::parse_complete("include \"missing\"").unwrap();
+        assert_eq!(
+            resolve_includes(program, &templates, None),
+            Err(Error::UnknownTemplate(String::from("missing")))
+        );
+    }
+
+    #[test]
+    fn test_include_limits() {
+        let chain: Vec<(String, String)> = (0..20)
+            .map(|level| (level.to_string(), format!("include \"{}\"", level + 1)))
+            .chain([(String::from("20"), String::from("feed"))])
+            .collect();
+        let chain = chain.into_iter().collect();
+        let program = Program::parse_complete("include \"0\"").unwrap();
+        assert_eq!(resolve_includes(program, &chain, None), Err(Error::TooDeep));
+
+        // Every level includes the next one ten times
+        let diamond: HashMap<String, String> = (0..8)
+            .map(|level| {
+                (
+                    level.to_string(),
+                    format!("repeat 10 {{\n  include \"{}\"\n}}", level + 1),
+                )
+            })
+            .chain([(String::from("8"), String::from("feed"))])
+            .collect();
+        let program = Program::parse_complete("include \"0\"").unwrap();
+        assert_eq!(
+            resolve_includes(program, &diamond, None),
+            Err(Error::TooLarge)
+        );
+    }
+}
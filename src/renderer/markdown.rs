@@ -0,0 +1,448 @@
+//! Rendering of Markdown text to receipt formatting
+use escpos::utils::{Font, JustifyMode, UnderlineMode};
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+use unicode_width::UnicodeWidthStr;
+
+use super::state::State;
+use crate::printer;
+
+/// Inline text style, mapped to the printer's bold, underline and font settings
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Style {
+    bold: bool,
+    underline: bool,
+    code: bool,
+}
+
+/// A word made of differently styled pieces, e.g. `**bold**,`
+type Word = Vec<(Style, String)>;
+
+enum Token {
+    Word(Word),
+    Break,
+}
+
+struct ListLevel {
+    /// Number of the next item of an ordered list
+    next: Option<u64>,
+    /// Indentation of the item's continuation lines
+    indent: usize,
+}
+
+struct Renderer {
+    columns: usize,
+    commands: Vec<printer::Command>,
+    /// Style currently set on the printer
+    printer_style: Style,
+    /// Style of the inline text being collected
+    style: Style,
+    /// Styled text of the current paragraph, heading or list item
+    inline: Vec<(Style, String)>,
+    lists: Vec<ListLevel>,
+    /// Bullet or number of a list item whose first line is not yet written
+    prefix: Option<String>,
+    /// Size set on the printer outside of headings
+    size: (u8, u8),
+    /// Width multiplier of the current heading
+    scale: usize,
+    in_code_block: bool,
+    /// Whether a blank line separates the next block from the previous one
+    separate: bool,
+}
+
+/// Renders Markdown to printer commands, wrapping text at `columns_per_line`
+///
+/// Headings are printed enlarged and bold, `**strong**` text bold, `_emphasis_`
+/// underlined and code in font B. List items are wrapped with an indentation
+/// like todo items, and `---` prints a rule over the full width. The text is
+/// justified left, and the justification, bold text, font and size of `state`
+/// are restored afterwards.
+pub fn make_markdown(source: &str, state: &State, columns_per_line: u32) -> Vec<printer::Command> {
+    let initial = Style {
+        bold: state.bold,
+        underline: false,
+        code: state.font == Font::B,
+    };
+    let mut renderer = Renderer {
+        columns: columns_per_line.max(1) as usize,
+        commands: vec![printer::Command::Justify(JustifyMode::LEFT)],
+        printer_style: initial,
+        style: Style::default(),
+        inline: vec![],
+        lists: vec![],
+        prefix: None,
+        size: state.size,
+        scale: usize::from(state.size.0.max(1)),
+        in_code_block: false,
+        separate: false,
+    };
+
+    for event in Parser::new(source) {
+        renderer.event(event);
+    }
+    renderer.flush();
+    renderer.set_style(initial);
+    if state.justify != JustifyMode::LEFT {
+        renderer
+            .commands
+            .push(printer::Command::Justify(state.justify));
+    }
+
+    renderer.commands
+}
+
+impl Renderer {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) if self.in_code_block => {
+                self.set_style(Style {
+                    code: true,
+                    ..Style::default()
+                });
+                self.commands
+                    .push(printer::Command::Write(text.to_string()));
+            }
+            Event::Text(text) => self.inline.push((self.style, text.to_string())),
+            Event::Code(code) => self.inline.push((
+                Style {
+                    code: true,
+                    ..self.style
+                },
+                code.to_string(),
+            )),
+            Event::SoftBreak => self.inline.push((self.style, String::from(" "))),
+            Event::HardBreak => self.inline.push((self.style, String::from("\n"))),
+            Event::Rule => {
+                self.begin_block();
+                self.set_style(Style::default());
+                self.commands
+                    .push(printer::Command::Write("-".repeat(self.columns) + "\n"));
+                self.separate = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph if self.lists.is_empty() => self.begin_block(),
+            Tag::Heading { level, .. } => {
+                self.begin_block();
+                let (width, height) = match level {
+                    HeadingLevel::H1 => (2, 2),
+                    HeadingLevel::H2 => (1, 2),
+                    _ => (1, 1),
+                };
+                self.commands.push(printer::Command::Size(width, height));
+                self.scale = width as usize;
+                self.style.bold = true;
+            }
+            Tag::CodeBlock(_) => {
+                self.flush();
+                self.begin_block();
+                self.in_code_block = true;
+            }
+            Tag::List(first) => {
+                self.flush();
+                if self.lists.is_empty() {
+                    self.begin_block();
+                }
+                let indent = self.lists.last().map_or(0, |level| level.indent);
+                self.lists.push(ListLevel {
+                    next: first,
+                    indent,
+                });
+            }
+            Tag::Item => {
+                self.flush();
+                let parent = self
+                    .lists
+                    .len()
+                    .checked_sub(2)
+                    .map_or(0, |i| self.lists[i].indent);
+                if let Some(level) = self.lists.last_mut() {
+                    let bullet = match &mut level.next {
+                        Some(number) => {
+                            *number += 1;
+                            format!("{}. ", *number - 1)
+                        }
+                        None => String::from("- "),
+                    };
+                    level.indent = parent + bullet.width();
+                    self.prefix = Some(" ".repeat(parent) + &bullet);
+                }
+            }
+            Tag::Strong => self.style.bold = true,
+            Tag::Emphasis => self.style.underline = true,
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => {
+                self.flush();
+                self.separate = self.lists.is_empty();
+            }
+            TagEnd::Heading(_) => {
+                self.flush();
+                self.set_style(Style::default());
+                self.commands.push(match self.size {
+                    (1, 1) => printer::Command::ResetSize,
+                    (width, height) => printer::Command::Size(width, height),
+                });
+                self.scale = usize::from(self.size.0.max(1));
+                self.style = Style::default();
+                self.separate = true;
+            }
+            TagEnd::CodeBlock => {
+                self.in_code_block = false;
+                self.set_style(Style::default());
+                self.separate = true;
+            }
+            TagEnd::List(_) => {
+                self.flush();
+                self.lists.pop();
+                self.separate = self.lists.is_empty();
+            }
+            TagEnd::Item => self.flush(),
+            TagEnd::Strong => self.style.bold = false,
+            TagEnd::Emphasis => self.style.underline = false,
+            _ => {}
+        }
+    }
+
+    /// Separates a new block from the previous one by a blank line
+    fn begin_block(&mut self) {
+        if self.separate {
+            self.set_style(Style::default());
+            self.commands
+                .push(printer::Command::Write(String::from("\n")));
+            self.separate = false;
+        }
+    }
+
+    /// Emits the commands changing the printer's style to `style`
+    fn set_style(&mut self, style: Style) {
+        let current = self.printer_style;
+        if style.bold != current.bold {
+            self.commands.push(printer::Command::Bold(style.bold));
+        }
+        if style.underline != current.underline {
+            self.commands
+                .push(printer::Command::Underline(if style.underline {
+                    UnderlineMode::Single
+                } else {
+                    UnderlineMode::None
+                }));
+        }
+        if style.code != current.code {
+            self.commands.push(printer::Command::Font(if style.code {
+                Font::B
+            } else {
+                Font::A
+            }));
+        }
+        self.printer_style = style;
+    }
+
+    /// Wraps and writes the collected inline text
+    fn flush(&mut self) {
+        let inline = std::mem::take(&mut self.inline);
+        let prefix = self.prefix.take();
+        if inline.iter().all(|(_, text)| text.trim().is_empty()) {
+            if let Some(prefix) = prefix {
+                self.set_style(Style::default());
+                self.commands.push(printer::Command::Write(prefix + "\n"));
+            }
+            return;
+        }
+
+        let indent = self.lists.last().map_or(0, |level| level.indent);
+        // Columns are counted in the size outside of headings
+        let width = (self.columns * usize::from(self.size.0.max(1)) / self.scale).max(1);
+        let mut first = prefix.unwrap_or_else(|| " ".repeat(indent));
+        let mut line: Vec<Word> = vec![];
+        let mut used = first.width();
+
+        for token in tokens(&inline) {
+            match token {
+                Token::Word(word) => {
+                    let word_width: usize = word.iter().map(|(_, text)| text.width()).sum();
+                    if !line.is_empty() && used + 1 + word_width > width {
+                        self.write_line(&first, &line);
+                        first = " ".repeat(indent);
+                        used = indent;
+                        line.clear();
+                    }
+                    used += word_width + usize::from(!line.is_empty());
+                    line.push(word);
+                }
+                Token::Break => {
+                    self.write_line(&first, &line);
+                    first = " ".repeat(indent);
+                    used = indent;
+                    line.clear();
+                }
+            }
+        }
+        if !line.is_empty() {
+            self.write_line(&first, &line);
+        }
+    }
+
+    fn write_line(&mut self, indent: &str, words: &[Word]) {
+        let mut pending = String::from(indent);
+        let mut style = self.printer_style;
+        if !indent.is_empty() && style != Style::default() {
+            self.set_style(Style::default());
+            style = Style::default();
+        }
+
+        for (i, word) in words.iter().enumerate() {
+            for (j, (piece_style, text)) in word.iter().enumerate() {
+                // Spaces between words only keep the style if it continues
+                if i > 0 && j == 0 {
+                    if *piece_style != style && style != Style::default() {
+                        self.write(&mut pending);
+                        self.set_style(Style::default());
+                        style = Style::default();
+                    }
+                    pending.push(' ');
+                }
+                if *piece_style != style {
+                    self.write(&mut pending);
+                    self.set_style(*piece_style);
+                    style = *piece_style;
+                }
+                pending.push_str(text);
+            }
+        }
+        pending.push('\n');
+        self.write(&mut pending);
+    }
+
+    fn write(&mut self, pending: &mut String) {
+        if !pending.is_empty() {
+            self.commands
+                .push(printer::Command::Write(std::mem::take(pending)));
+        }
+    }
+}
+
+/// Splits styled text into words and forced line breaks
+fn tokens(inline: &[(Style, String)]) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut word: Word = vec![];
+
+    for (style, text) in inline {
+        for c in text.chars() {
+            if c.is_whitespace() {
+                if !word.is_empty() {
+                    tokens.push(Token::Word(std::mem::take(&mut word)));
+                }
+                if c == '\n' {
+                    tokens.push(Token::Break);
+                }
+                continue;
+            }
+            match word.last_mut() {
+                Some((last, piece)) if last == style => piece.push(c),
+                _ => word.push((*style, c.to_string())),
+            }
+        }
+    }
+    if !word.is_empty() {
+        tokens.push(Token::Word(word));
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use printer::Command;
+
+    fn write(text: &str) -> Command {
+        Command::Write(String::from(text))
+    }
+
+    #[test]
+    fn test_heading_and_inline_styles() {
+        assert_eq!(
+            make_markdown(
+                "# Hi\n\nSome **bold**, _under_ and `code`",
+                &State::default(),
+                40
+            ),
+            vec![
+                Command::Justify(JustifyMode::LEFT),
+                Command::Size(2, 2),
+                Command::Bold(true),
+                write("Hi\n"),
+                Command::Bold(false),
+                Command::ResetSize,
+                write("\n"),
+                write("Some "),
+                Command::Bold(true),
+                write("bold"),
+                Command::Bold(false),
+                write(", "),
+                Command::Underline(UnderlineMode::Single),
+                write("under"),
+                Command::Underline(UnderlineMode::None),
+                write(" and "),
+                Command::Font(Font::B),
+                write("code\n"),
+                Command::Font(Font::A),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lists_and_rule() {
+        assert_eq!(
+            make_markdown(
+                "1. Buy fresh milk\n   - soy\n2. Bread\n\n---",
+                &State::default(),
+                14
+            ),
+            vec![
+                Command::Justify(JustifyMode::LEFT),
+                write("1. Buy fresh\n"),
+                write("   milk\n"),
+                write("   - soy\n"),
+                write("2. Bread\n"),
+                write("\n"),
+                write("--------------\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_restores_state() {
+        let state = State {
+            justify: JustifyMode::CENTER,
+            size: (2, 1),
+            bold: true,
+            ..State::default()
+        };
+        assert_eq!(
+            make_markdown("## Tea time\n\nMilk", &state, 12),
+            vec![
+                Command::Justify(JustifyMode::LEFT),
+                Command::Size(1, 2),
+                write("Tea time\n"),
+                Command::Bold(false),
+                Command::Size(2, 1),
+                write("\n"),
+                write("Milk\n"),
+                Command::Bold(true),
+                Command::Justify(JustifyMode::CENTER),
+            ]
+        );
+    }
+}
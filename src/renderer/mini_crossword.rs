@@ -1,3 +1,4 @@
+use crate::dither;
 use crate::mini_crossword::{self, MiniCrosswordOptions};
 use crate::printer::Command;
 use escpos::utils::JustifyMode;
@@ -28,8 +29,9 @@ pub async fn make_mini_crossword(
     columns_per_line: u32,
 ) -> Vec<Command> {
     let cw = mini_crossword::get(MiniCrosswordOptions {
-        dpi: dpi,
-        target_width: target_width,
+        dpi,
+        target_width,
+        dither: dither::Options::threshold(),
     })
     .await
     .expect("Could not get crossword");
@@ -1,60 +1,214 @@
+use crate::codepage;
 use crate::printer;
+use crate::printer::codes_2d::Code2d;
 use crate::program::{Command, Program};
+use crate::settings::PrinterSettings;
+use escpos::utils::JustifyMode;
+use state::State;
+use std::path::Path;
+use thiserror::Error;
+mod codes;
+mod image;
+mod include;
+mod markdown;
 mod mini_crossword;
+mod preview;
+mod state;
 mod sudoku;
+mod table;
+mod validation;
+
+pub use include::{resolve_includes, Error as IncludeError};
+pub use preview::{preview, Png};
+pub use validation::{validate, Issue, Policy, Rejected, Severity, UnknownPolicy};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("image: {0}")]
+    Image(#[from] image::Error),
+    #[error("{0}")]
+    Codes(#[from] codes::Error),
+    #[error("{profile} does not support {feature}")]
+    Unsupported {
+        profile: String,
+        feature: &'static str,
+    },
+    #[error("{profile} has no code page {name}")]
+    CodePage { profile: String, name: String },
+}
 
 const DEFAULT_DPI: u16 = 180;
-const DEFAULT_COLUMNS_PER_LINE: u8 = 42;
 const DEFAULT_PIXELS_PER_LINE: u16 = 512;
 
-pub async fn render<'a>(program: Program, profile: &escpos_db::Profile<'a>) -> printer::Program {
-    let columns_per_line = profile
-        .fonts
-        .get(0) // todo: use currently active font
-        .and_then(|font| Some(font.columns))
-        .unwrap_or(DEFAULT_COLUMNS_PER_LINE);
-
-    let dpi = profile.media.dpi.unwrap_or(DEFAULT_DPI);
-    let width_px = profile
+/// Printable width of the paper in pixels
+fn paper_width_px(profile: &escpos_db::Profile) -> u32 {
+    profile
         .media
         .width
         .as_ref()
-        .and_then(|m| Some(m.px))
-        .unwrap_or(DEFAULT_PIXELS_PER_LINE);
+        .map(|m| m.px)
+        .unwrap_or(DEFAULT_PIXELS_PER_LINE) as u32
+}
 
-    printer::Program(
-        futures::future::join_all(program.commands.iter().map(async |command| {
+/// The printer a program is rendered for
+struct Target<'a, 'b> {
+    profile: &'a escpos_db::Profile<'b>,
+    dpi: u16,
+    width_px: u32,
+    /// Whether barcodes are sent as images
+    rasterize_barcodes: bool,
+    /// Whether QR codes are sent as images
+    rasterize_qr_codes: bool,
+    /// Directory image files are read from
+    image_dir: Option<&'a Path>,
+    /// Directory font files are read from
+    font_dir: Option<&'a Path>,
+}
+
+pub async fn render<'a>(
+    program: Program,
+    profile: &escpos_db::Profile<'a>,
+    settings: &PrinterSettings,
+) -> Result<printer::Program, Error> {
+    let target = Target {
+        profile,
+        dpi: profile.media.dpi.unwrap_or(DEFAULT_DPI),
+        width_px: paper_width_px(profile),
+        // Codes the printer can't print natively are sent as images
+        rasterize_barcodes: settings.rasterize_codes || !profile.features.barcode_b(),
+        rasterize_qr_codes: settings.rasterize_codes || !profile.features.qr_code(),
+        image_dir: settings.image_dir.as_deref(),
+        font_dir: settings.font_dir.as_deref(),
+    };
+
+    // Commands are rendered in program order, tracking the formatting they set
+    // and encoding all text for the code pages of the printer
+    let mut state = State::default();
+    let mut encoder = codepage::Encoder::new(profile);
+    let mut commands = Vec::new();
+    for command in &program.commands {
+        if let Command::CodePage(name) = command {
+            if !encoder.force(name.as_deref()) {
+                return Err(Error::CodePage {
+                    profile: profile.name.to_string(),
+                    name: name.clone().unwrap_or_default(),
+                });
+            }
+        }
+        for command in render_command(command, &state, &target).await? {
+            state.apply(&command);
             match command {
-                Command::Raw(cmd) => vec![cmd.clone()],
-                Command::Sudoku => sudoku::make_sudoku().await,
-                Command::MiniCrossword => {
-                    mini_crossword::make_mini_crossword(
-                        dpi,
-                        width_px as u32,
-                        columns_per_line as u32,
-                    )
-                    .await
-                }
-                Command::ToDo(item) => {
-                    let prefix = "- [ ] ";
-                    std::iter::once(printer::Command::Justify(escpos::utils::JustifyMode::LEFT))
-                        .chain(
-                            textwrap::wrap(
-                                item,
-                                textwrap::Options::new(columns_per_line as usize)
-                                    .initial_indent(prefix)
-                                    .subsequent_indent(&" ".repeat(prefix.len())),
-                            )
-                            .into_iter()
-                            .map(|line| printer::Command::Write(format!("{}\n", line))),
-                        )
-                        .collect()
-                }
+                printer::Command::Write(text) => commands.extend(encoder.encode(&text)),
+                command => commands.push(command),
             }
-        }))
-        .await
-        .iter()
-        .flat_map(|f| f.clone())
-        .collect(),
-    )
+        }
+    }
+
+    Ok(printer::Program(commands))
+}
+
+/// Renders a single command with the formatting `state` set on the printer
+async fn render_command(
+    command: &Command,
+    state: &State,
+    target: &Target<'_, '_>,
+) -> Result<Vec<printer::Command>, Error> {
+    let profile = target.profile;
+    let columns_per_line = state.columns(profile);
+
+    Ok(match command {
+        Command::Raw(printer::Command::Barcode(barcode)) if target.rasterize_barcodes => {
+            codes::make_barcode(barcode, target.width_px)?
+        }
+        Command::Raw(printer::Command::Code2d(Code2d::QrCode(data, options)))
+            if target.rasterize_qr_codes =>
+        {
+            codes::make_qr_code(data, options, target.width_px)?
+        }
+        Command::Raw(printer::Command::Code2d(code)) if !code.supported(&profile.features) => {
+            return Err(Error::Unsupported {
+                profile: profile.name.to_string(),
+                feature: code.name(),
+            })
+        }
+        Command::Raw(cmd) => vec![cmd.clone()],
+        Command::Sudoku => sudoku::make_sudoku().await,
+        Command::MiniCrossword => {
+            // The crossword resets the size before writing its clues
+            let columns_per_line = State {
+                size: (1, 1),
+                ..*state
+            }
+            .columns(profile);
+            mini_crossword::make_mini_crossword(target.dpi, target.width_px, columns_per_line)
+                .await
+        }
+        Command::ToDo(item) => {
+            let prefix = "- [ ] ";
+            let justify = (state.justify != JustifyMode::LEFT)
+                .then_some(printer::Command::Justify(JustifyMode::LEFT));
+            justify
+                .into_iter()
+                .chain(
+                    textwrap::wrap(
+                        item,
+                        textwrap::Options::new(columns_per_line as usize)
+                            .initial_indent(prefix)
+                            .subsequent_indent(&" ".repeat(prefix.len())),
+                    )
+                    .into_iter()
+                    .map(|line| printer::Command::Write(format!("{}\n", line))),
+                )
+                .collect()
+        }
+        Command::Markdown(source) => markdown::make_markdown(source, state, columns_per_line),
+        Command::Table { columns, rows } => {
+            table::make_table(columns, rows, state, columns_per_line)
+        }
+        Command::Image { source, options } => {
+            image::make_image(source, options, target.width_px, target.image_dir).await?
+        }
+        Command::Svg { source, options } => {
+            image::make_svg(source, options, target.dpi, target.width_px).await?
+        }
+        Command::TextImage { text, options } => {
+            image::make_text_image(text, options, target.dpi, target.width_px, target.font_dir)
+                .await?
+        }
+        Command::Include(name) => {
+            log::warn!("Skipping unresolved include of template '{}'", name);
+            vec![]
+        }
+        // Applied while encoding the text in render
+        Command::CodePage(_) => vec![],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_wraps_at_active_size() {
+        let program = Program {
+            commands: vec![
+                Command::Raw(printer::Command::Size(2, 2)),
+                Command::ToDo(String::from("Water the plants on the balcony")),
+            ],
+        };
+        let rendered = render(program, &escpos_db::DEFAULT, &PrinterSettings::default())
+            .await
+            .unwrap();
+
+        // 42 columns of font A at double width
+        assert_eq!(
+            rendered.0,
+            vec![
+                printer::Command::Size(2, 2),
+                printer::Command::EncodedText(b"- [ ] Water the\n".to_vec()),
+                printer::Command::EncodedText(b"      plants on the\n".to_vec()),
+                printer::Command::EncodedText(b"      balcony\n".to_vec()),
+            ]
+        );
+    }
 }
@@ -0,0 +1,529 @@
+//! Emulation of a printer, drawing rendered programs as images to preview
+//! receipts without wasting paper
+use std::convert::Infallible;
+
+use ::image::imageops::{self, FilterType};
+use ::image::{GrayImage, Luma};
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::geometry::{OriginDimensions, Point, Size};
+use embedded_graphics::mono_font::{iso_8859_1, MonoFont, MonoTextStyle};
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::text::{Baseline, Text};
+use embedded_graphics::{Drawable, Pixel};
+use escpos::utils::{JustifyMode, UnderlineMode};
+
+use super::state::State;
+use super::{codes, paper_width_px};
+use crate::codepage;
+use crate::dither;
+use crate::printer;
+
+/// Bitmap fonts by width, the largest fitting into the characters of a printer
+/// font is drawn for it
+const FONTS: [&MonoFont; 9] = [
+    &iso_8859_1::FONT_4X6,
+    &iso_8859_1::FONT_5X8,
+    &iso_8859_1::FONT_6X10,
+    &iso_8859_1::FONT_6X13,
+    &iso_8859_1::FONT_7X14,
+    &iso_8859_1::FONT_8X13,
+    &iso_8859_1::FONT_9X15,
+    &iso_8859_1::FONT_9X18,
+    &iso_8859_1::FONT_10X20,
+];
+
+/// White space around the printable area of the paper
+const MARGIN: u32 = 16;
+/// Height of the mark of a cut
+const CUT_HEIGHT: u32 = 16;
+/// Characters between tab stops, as after printer initialization
+const TAB_WIDTH: u32 = 8;
+
+const BLACK: Luma<u8> = Luma([0]);
+const WHITE: Luma<u8> = Luma([255]);
+
+/// A PNG image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Png(pub Vec<u8>);
+
+/// Draws a rendered program as it would be printed on the paper of a profile
+///
+/// Text is drawn with a bitmap font matching the columns of the profile's
+/// fonts, in the formatting set by the program. Cuts are marked with a dashed
+/// line across the paper. Codes that can't be drawn, like PDF417 codes, are
+/// drawn as a box with their name.
+pub fn preview(program: &printer::Program, profile: &escpos_db::Profile) -> Png {
+    let mut emulator = Emulator::new(profile);
+    for command in &program.0 {
+        emulator.command(command);
+    }
+    let image = emulator.finish();
+    Png(dither::encode_png(image).expect("encoding an image in memory can't fail"))
+}
+
+/// A part of the receipt, from top to bottom
+enum Strip {
+    Print(GrayImage),
+    Cut,
+}
+
+/// A line of text being collected until it is printed
+struct Line {
+    cells: Vec<GrayImage>,
+    width: u32,
+    /// Justification and upside-down mode when the line was started
+    justify: JustifyMode,
+    flip: bool,
+}
+
+struct Emulator<'a, 'b> {
+    profile: &'a escpos_db::Profile<'b>,
+    width_px: u32,
+    state: State,
+    underline: UnderlineMode,
+    double_strike: bool,
+    reverse: bool,
+    flip: bool,
+    code_page: u8,
+    /// Height of an empty line
+    line_spacing: u32,
+    line: Line,
+    strips: Vec<Strip>,
+}
+
+impl<'a, 'b> Emulator<'a, 'b> {
+    fn new(profile: &'a escpos_db::Profile<'b>) -> Self {
+        let state = State::default();
+        let mut emulator = Emulator {
+            profile,
+            width_px: paper_width_px(profile),
+            state,
+            underline: UnderlineMode::None,
+            double_strike: false,
+            reverse: false,
+            flip: false,
+            code_page: 0,
+            line_spacing: 0,
+            line: Line {
+                cells: Vec::new(),
+                width: 0,
+                justify: state.justify,
+                flip: false,
+            },
+            strips: Vec::new(),
+        };
+        // About 1/6 inch for font A, as after initialization
+        let (_, height) = emulator.cell_size();
+        emulator.line_spacing = height + height / 4;
+        emulator
+    }
+
+    fn command(&mut self, command: &printer::Command) {
+        use printer::Command::*;
+        self.state.apply(command);
+        match command {
+            Write(text) => self.text(text.chars()),
+            EncodedText(bytes) => {
+                let text = codepage::decode(self.profile, self.code_page, bytes);
+                self.text(text.chars());
+            }
+            CodePage(number) => self.code_page = *number,
+            Underline(mode) => self.underline = *mode,
+            DoubleStrike(double_strike) => self.double_strike = *double_strike,
+            Flip(flip) => self.flip = *flip,
+            Reverse(reverse) => self.reverse = *reverse,
+            Feed(lines) => self.feed(*lines),
+            Barcode(barcode) => match codes::make_barcode(barcode, self.width_px) {
+                Ok(commands) => commands.iter().for_each(|command| self.command(command)),
+                Err(_) => self.placeholder("barcode"),
+            },
+            Code2d(printer::codes_2d::Code2d::QrCode(data, options)) => {
+                match codes::make_qr_code(data, options, self.width_px) {
+                    Ok(commands) => commands.iter().for_each(|command| self.command(command)),
+                    Err(_) => self.placeholder("QR code"),
+                }
+            }
+            Code2d(code) => self.placeholder(code.singular_name()),
+            Cut => {
+                if !self.line.cells.is_empty() {
+                    self.print_line();
+                }
+                self.strips.push(Strip::Cut);
+            }
+            BitImageFromBytesWithWidth(png, width) => match ::image::load_from_memory(png) {
+                Ok(image) => {
+                    let image = image.to_luma8();
+                    let height = (u64::from(image.height()) * u64::from(*width)
+                        / u64::from(image.width().max(1)))
+                    .max(1) as u32;
+                    let mut image = imageops::resize(&image, *width, height, FilterType::Nearest);
+                    // Printers print dots, either black or white
+                    for pixel in image.pixels_mut() {
+                        *pixel = if pixel.0[0] < 128 { BLACK } else { WHITE };
+                    }
+                    self.image(&image);
+                }
+                Err(_) => self.placeholder("image"),
+            },
+            Bold(_) | Font(_) | Justify(_) | Size(..) | ResetSize | Bytes(_) => {}
+        }
+    }
+
+    /// Width and height of a character in the current font, without the size
+    fn cell_size(&self) -> (u32, u32) {
+        let columns = State {
+            size: (1, 1),
+            ..self.state
+        }
+        .columns(self.profile);
+        let width = (self.width_px / columns).max(1);
+        (width, 2 * width)
+    }
+
+    fn text(&mut self, text: impl Iterator<Item = char>) {
+        for character in text {
+            match character {
+                '\n' => self.print_line(),
+                '\t' => {
+                    let (width, _) = self.cell_size();
+                    let column = self.line.width / width;
+                    for _ in column % TAB_WIDTH..TAB_WIDTH {
+                        self.character(' ');
+                    }
+                }
+                character if character.is_control() => {}
+                character => self.character(character),
+            }
+        }
+    }
+
+    /// Adds a character to the line, printing the line first if it is full
+    fn character(&mut self, character: char) {
+        let (width, height) = self.cell_size();
+        let font = FONTS
+            .iter()
+            .rev()
+            .find(|font| font.character_size.width < width && font.character_size.height <= height)
+            .unwrap_or(&FONTS[0]);
+
+        let mut canvas = Canvas(GrayImage::from_pixel(width, height, WHITE));
+        let position = Point::new(
+            (width.saturating_sub(font.character_size.width) / 2) as i32,
+            (height.saturating_sub(font.character_size.height) / 2) as i32,
+        );
+        let mut buffer = [0; 4];
+        let _ = Text::with_baseline(
+            character.encode_utf8(&mut buffer),
+            position,
+            MonoTextStyle::new(font, BinaryColor::On),
+            Baseline::Top,
+        )
+        .draw(&mut canvas);
+        let mut cell = canvas.0;
+
+        if self.state.bold || self.double_strike {
+            // Every dot is printed twice, the second time shifted to the right
+            for y in 0..height {
+                for x in (1..width).rev() {
+                    if cell.get_pixel(x - 1, y) == &BLACK {
+                        cell.put_pixel(x, y, BLACK);
+                    }
+                }
+            }
+        }
+
+        let (size_x, size_y) = self.state.size;
+        let mut cell = imageops::resize(
+            &cell,
+            width * u32::from(size_x.max(1)),
+            height * u32::from(size_y.max(1)),
+            FilterType::Nearest,
+        );
+
+        let thickness = match self.underline {
+            UnderlineMode::None => 0,
+            UnderlineMode::Single => 1,
+            UnderlineMode::Double => 2,
+        };
+        // Cells of tiny fonts may be lower than the underline
+        let bottom = cell.height().saturating_sub(1);
+        for y in bottom.saturating_sub(thickness)..bottom {
+            for x in 0..cell.width() {
+                cell.put_pixel(x, y, BLACK);
+            }
+        }
+        if self.reverse {
+            imageops::invert(&mut cell);
+        }
+
+        if self.line.width + cell.width() > self.width_px && !self.line.cells.is_empty() {
+            self.print_line();
+        }
+        if self.line.cells.is_empty() {
+            self.line.justify = self.state.justify;
+            self.line.flip = self.flip;
+        }
+        self.line.width += cell.width();
+        self.line.cells.push(cell);
+    }
+
+    /// Prints the line and advances the paper by a line, like a line feed
+    fn print_line(&mut self) {
+        let line = std::mem::replace(
+            &mut self.line,
+            Line {
+                cells: Vec::new(),
+                width: 0,
+                justify: self.state.justify,
+                flip: self.flip,
+            },
+        );
+
+        let gap = self.line_spacing / 5;
+        let text_height = line.cells.iter().map(GrayImage::height).max().unwrap_or(0);
+        let height = (text_height + gap).max(self.line_spacing);
+        let mut strip = GrayImage::from_pixel(self.width_px, height, WHITE);
+        let mut x = self.offset(line.justify, line.width);
+        for cell in &line.cells {
+            // Characters of different heights share the baseline
+            imageops::replace(
+                &mut strip,
+                cell,
+                i64::from(x),
+                i64::from(height - gap - cell.height()),
+            );
+            x += cell.width();
+        }
+        if line.flip {
+            imageops::rotate180_in_place(&mut strip);
+        }
+        self.strips.push(Strip::Print(strip));
+    }
+
+    /// Feeds the paper, printing the line first
+    fn feed(&mut self, lines: u8) {
+        let mut lines = u32::from(lines);
+        if !self.line.cells.is_empty() {
+            self.print_line();
+            lines = lines.saturating_sub(1);
+        }
+        if lines > 0 {
+            let height = lines * self.line_spacing;
+            self.strips.push(Strip::Print(GrayImage::from_pixel(
+                self.width_px,
+                height,
+                WHITE,
+            )));
+        }
+    }
+
+    /// Prints a raster image at the current justification
+    fn image(&mut self, image: &GrayImage) {
+        if !self.line.cells.is_empty() {
+            self.print_line();
+        }
+        let mut strip = GrayImage::from_pixel(self.width_px, image.height(), WHITE);
+        let x = self.offset(self.state.justify, image.width());
+        imageops::replace(&mut strip, image, i64::from(x), 0);
+        self.strips.push(Strip::Print(strip));
+    }
+
+    /// Marks something that is printed, but can't be drawn, with a box of its name
+    fn placeholder(&mut self, name: &str) {
+        let size = (self.width_px / 3).max(1);
+        let mut placeholder = GrayImage::from_fn(size, size, |x, y| {
+            let border = x < 2 || y < 2 || x + 2 >= size || y + 2 >= size;
+            if border {
+                BLACK
+            } else {
+                WHITE
+            }
+        });
+        let mut canvas = Canvas(placeholder);
+        let font = &iso_8859_1::FONT_6X10;
+        let width = font.character_size.width * name.chars().count() as u32;
+        let _ = Text::with_baseline(
+            name,
+            Point::new(
+                (size.saturating_sub(width) / 2) as i32,
+                (size.saturating_sub(font.character_size.height) / 2) as i32,
+            ),
+            MonoTextStyle::new(font, BinaryColor::On),
+            Baseline::Top,
+        )
+        .draw(&mut canvas);
+        placeholder = canvas.0;
+        self.image(&placeholder);
+    }
+
+    /// Horizontal position of something `width` wide, justified on the paper
+    fn offset(&self, justify: JustifyMode, width: u32) -> u32 {
+        let space = self.width_px.saturating_sub(width);
+        match justify {
+            JustifyMode::LEFT => 0,
+            JustifyMode::CENTER => space / 2,
+            JustifyMode::RIGHT => space,
+        }
+    }
+
+    /// Draws the receipt on the paper, with a margin around it
+    fn finish(mut self) -> GrayImage {
+        if !self.line.cells.is_empty() {
+            self.print_line();
+        }
+
+        let height = self
+            .strips
+            .iter()
+            .map(|strip| match strip {
+                Strip::Print(image) => image.height(),
+                Strip::Cut => CUT_HEIGHT,
+            })
+            .sum::<u32>();
+        let width = self.width_px + 2 * MARGIN;
+        let mut paper = GrayImage::from_pixel(width, height + 2 * MARGIN, WHITE);
+        let mut y = MARGIN;
+        for strip in &self.strips {
+            match strip {
+                Strip::Print(image) => {
+                    imageops::replace(&mut paper, image, i64::from(MARGIN), i64::from(y));
+                    y += image.height();
+                }
+                Strip::Cut => {
+                    // A dashed line across the whole paper
+                    for x in (0..width).filter(|x| x % 8 < 5) {
+                        paper.put_pixel(x, y + CUT_HEIGHT / 2, BLACK);
+                    }
+                    y += CUT_HEIGHT;
+                }
+            }
+        }
+        paper
+    }
+}
+
+/// A target for the drawing of bitmap fonts
+struct Canvas(GrayImage);
+
+impl OriginDimensions for Canvas {
+    fn size(&self) -> Size {
+        Size::new(self.0.width(), self.0.height())
+    }
+}
+
+impl DrawTarget for Canvas {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (Ok(x), Ok(y)) = (u32::try_from(point.x), u32::try_from(point.y)) else {
+                continue;
+            };
+            if color.is_on() && x < self.0.width() && y < self.0.height() {
+                self.0.put_pixel(x, y, BLACK);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::printer::codes_2d::{self, QrOptions};
+    use printer::Command::*;
+
+    fn draw(commands: Vec<printer::Command>, profile: &escpos_db::Profile) -> GrayImage {
+        let Png(png) = preview(&printer::Program(commands), profile);
+        ::image::load_from_memory(&png).unwrap().to_luma8()
+    }
+
+    /// Columns of the paper with black dots
+    fn inked_columns(image: &GrayImage) -> Vec<u32> {
+        (0..image.width())
+            .filter(|x| (0..image.height()).any(|y| image.get_pixel(*x, y) == &BLACK))
+            .collect()
+    }
+
+    #[test]
+    fn test_text() {
+        let profile = &escpos_db::ZJ_5870;
+        let image = draw(vec![Write(String::from("Hi\n"))], profile);
+        // 384 dots of 32 columns, characters are 12 dots wide and 24 high
+        assert_eq!(image.width(), 384 + 2 * MARGIN);
+        assert_eq!(image.height(), 30 + 2 * MARGIN);
+        let columns = inked_columns(&image);
+        assert!(columns.first() >= Some(&MARGIN) && columns.last() < Some(&(MARGIN + 24)));
+
+        let centered = draw(
+            vec![Justify(JustifyMode::CENTER), Write(String::from("Hi\n"))],
+            profile,
+        );
+        let columns = inked_columns(&centered);
+        assert!(columns.first() >= Some(&(MARGIN + 180)));
+
+        // Lines wrap at the paper width
+        let wrapped = draw(vec![Write("x".repeat(33))], profile);
+        assert_eq!(wrapped.height(), 2 * 30 + 2 * MARGIN);
+    }
+
+    #[test]
+    fn test_formatting() {
+        let profile = &escpos_db::ZJ_5870;
+        let plain = draw(vec![Write(String::from("Ab\n"))], profile);
+        let flipped = draw(vec![Flip(true), Write(String::from("Ab\n"))], profile);
+        let mut rotated = plain.clone();
+        imageops::rotate180_in_place(&mut rotated);
+        assert_eq!(flipped, rotated);
+
+        let bold = draw(vec![Bold(true), Write(String::from("Ab\n"))], profile);
+        let black = |image: &GrayImage| image.pixels().filter(|p| **p == BLACK).count();
+        assert!(black(&bold) > black(&plain));
+
+        let reversed = draw(vec![Reverse(true), Write(String::from(" \n"))], profile);
+        assert_eq!(black(&reversed), 12 * 24);
+
+        let large = draw(vec![Size(2, 2), Write(String::from("A\n"))], profile);
+        assert_eq!(large.height(), 48 + 6 + 2 * MARGIN);
+    }
+
+    #[test]
+    fn test_underline_of_tiny_cells() {
+        // 42 columns on 40 dots make cells of 1 by 2 dots
+        let mut profile = escpos_db::Profile::new("Tiny".into(), "Test".into());
+        profile.media = escpos_db::Media::new(None, Some(escpos_db::Width::new(5.0, 40)));
+        let image = draw(
+            vec![
+                Underline(UnderlineMode::Double),
+                Write(String::from("Ab\n")),
+            ],
+            &profile,
+        );
+        assert_eq!(image.width(), 40 + 2 * MARGIN);
+    }
+
+    #[test]
+    fn test_codes_and_cuts() {
+        let profile = &escpos_db::DEFAULT;
+        let options = QrOptions {
+            size: 4,
+            ..QrOptions::default()
+        };
+        let image = draw(
+            vec![
+                Code2d(codes_2d::Code2d::QrCode(String::from("hello"), options)),
+                Cut,
+            ],
+            profile,
+        );
+        // A version 1 QR code of 21 modules with a quiet zone of 4 modules
+        assert_eq!(image.height(), 29 * 4 + CUT_HEIGHT + 2 * MARGIN);
+        let cut = image.height() - MARGIN - CUT_HEIGHT / 2;
+        assert_eq!(image.get_pixel(0, cut), &BLACK);
+        assert_eq!(image.get_pixel(6, cut), &WHITE);
+    }
+}
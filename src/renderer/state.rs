@@ -0,0 +1,82 @@
+//! Text formatting of the printer while a program is rendered
+use escpos::utils::{Font, JustifyMode};
+
+use crate::printer;
+
+const DEFAULT_COLUMNS_PER_LINE: u8 = 42;
+
+/// Formatting set on the printer at a point of a program, as after
+/// initialization until commands change it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct State {
+    pub font: Font,
+    /// Character width and height multipliers
+    pub size: (u8, u8),
+    pub justify: JustifyMode,
+    pub bold: bool,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            font: Font::A,
+            size: (1, 1),
+            justify: JustifyMode::LEFT,
+            bold: false,
+        }
+    }
+}
+
+impl State {
+    /// Updates the state with a command sent to the printer
+    pub fn apply(&mut self, command: &printer::Command) {
+        match command {
+            printer::Command::Font(font) => self.font = *font,
+            printer::Command::Size(width, height) => self.size = (*width, *height),
+            printer::Command::ResetSize => self.size = (1, 1),
+            printer::Command::Justify(mode) => self.justify = *mode,
+            printer::Command::Bold(bold) => self.bold = *bold,
+            _ => {}
+        }
+    }
+
+    /// Characters per line in the current font and width
+    ///
+    /// Falls back to the first font of the profile if it lacks the current one.
+    pub fn columns(&self, profile: &escpos_db::Profile) -> u32 {
+        let font = match self.font {
+            Font::A => 0,
+            Font::B => 1,
+            Font::C => 2,
+        };
+        let columns = profile
+            .fonts
+            .get(font)
+            .or_else(|| profile.fonts.get(0))
+            .map(|font| font.columns)
+            .unwrap_or(DEFAULT_COLUMNS_PER_LINE);
+        (u32::from(columns) / u32::from(self.size.0.max(1))).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_columns() {
+        let profile = &escpos_db::DEFAULT;
+        let mut state = State::default();
+        assert_eq!(state.columns(profile), 42);
+
+        state.apply(&printer::Command::Font(Font::B));
+        assert_eq!(state.columns(profile), 56);
+        state.apply(&printer::Command::Size(2, 3));
+        assert_eq!(state.columns(profile), 28);
+        // Font C is not listed for the profile
+        state.apply(&printer::Command::Font(Font::C));
+        assert_eq!(state.columns(profile), 21);
+        state.apply(&printer::Command::ResetSize);
+        assert_eq!(state.columns(profile), 42);
+    }
+}
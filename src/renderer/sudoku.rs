@@ -24,12 +24,12 @@ fn render_sudoku(board: rustoku_lib::core::Board) -> String {
                 }
             }
             s.push_str(chars[3]);
-            s.push_str("\n");
+            s.push('\n');
         }
 
         if row < 9 {
             // Data row
-            s.push_str("|");
+            s.push('|');
             for col in 0..9 {
                 let n = board.get(row, col);
                 s.push_str(&format!(
@@ -46,7 +46,7 @@ fn render_sudoku(board: rustoku_lib::core::Board) -> String {
                     "│"
                 });
             }
-            s.push_str("\n");
+            s.push('\n');
 
             // Separator
             if row < 8 {
@@ -63,7 +63,7 @@ fn render_sudoku(board: rustoku_lib::core::Board) -> String {
                     }
                 }
                 s.push_str(chars[3]);
-                s.push_str("\n");
+                s.push('\n');
             }
         }
     }
@@ -0,0 +1,252 @@
+//! Layout of `row` and `table` cells in columns
+use escpos::utils::JustifyMode;
+use unicode_width::UnicodeWidthStr;
+
+use super::state::State;
+use crate::printer;
+use crate::program::{Align, Column, ColumnWidth};
+
+/// Characters between two columns
+const GAP: usize = 1;
+
+/// Lays out rows of cells in `columns` over `columns_per_line` characters
+///
+/// Cells that don't fit their column are wrapped onto further lines. Fill
+/// columns share the width left by the fixed columns, and the space next to the
+/// text of a column with dot leaders is filled with dots. Fixed columns wider
+/// than the line together are narrowed, the widest first. The rows are
+/// justified left, and the justification of `state` is restored afterwards.
+pub fn make_table(
+    columns: &[Column],
+    rows: &[Vec<String>],
+    state: &State,
+    columns_per_line: u32,
+) -> Vec<printer::Command> {
+    let widths = widths(columns, columns_per_line as usize);
+    let justified = state.justify != JustifyMode::LEFT;
+
+    justified
+        .then_some(printer::Command::Justify(JustifyMode::LEFT))
+        .into_iter()
+        .chain(rows.iter().flat_map(|row| {
+            layout_row(columns, &widths, row)
+                .into_iter()
+                .map(|line| printer::Command::Write(line + "\n"))
+        }))
+        .chain(justified.then_some(printer::Command::Justify(state.justify)))
+        .collect()
+}
+
+/// Width of every column in characters
+fn widths(columns: &[Column], total: usize) -> Vec<usize> {
+    let mut fixed: Vec<Option<usize>> = columns
+        .iter()
+        .map(|column| match column.width {
+            ColumnWidth::Fixed(width) => Some(width as usize),
+            ColumnWidth::Fill => None,
+        })
+        .collect();
+    let fills = fixed.iter().filter(|width| width.is_none()).count();
+    let gaps = GAP * columns.len().saturating_sub(1);
+
+    // Leave every fill column at least one character
+    let budget = total.saturating_sub(gaps + fills);
+    let mut excess = fixed.iter().flatten().sum::<usize>().saturating_sub(budget);
+    while excess > 0 {
+        match fixed.iter_mut().flatten().filter(|width| **width > 1).max() {
+            Some(widest) => *widest -= 1,
+            // Too many columns for the line even one character wide
+            None => break,
+        }
+        excess -= 1;
+    }
+
+    let available = total.saturating_sub(fixed.iter().flatten().sum::<usize>() + gaps);
+    let mut fill_index = 0;
+    fixed
+        .iter()
+        .map(|width| match width {
+            Some(width) => *width,
+            None => {
+                // The last fill column also takes the remainder of the division
+                fill_index += 1;
+                let width = if fill_index == fills {
+                    available - available / fills * (fills - 1)
+                } else {
+                    available / fills
+                };
+                width.max(1)
+            }
+        })
+        .collect()
+}
+
+/// The printed lines of a single row
+fn layout_row(columns: &[Column], widths: &[usize], row: &[String]) -> Vec<String> {
+    let cells: Vec<Vec<String>> = row
+        .iter()
+        .zip(widths)
+        .map(|(cell, width)| {
+            textwrap::wrap(cell, *width)
+                .into_iter()
+                .map(|line| line.into_owned())
+                .collect()
+        })
+        .collect();
+    let height = cells.iter().map(Vec::len).max().unwrap_or(0).max(1);
+
+    (0..height)
+        .map(|index| {
+            let texts: Vec<&str> = cells
+                .iter()
+                .map(|lines| lines.get(index).map_or("", String::as_str))
+                .collect();
+            layout_line(columns, widths, &texts)
+        })
+        .collect()
+}
+
+/// Joins the texts of one line of a row, padding them to their column widths
+fn layout_line(columns: &[Column], widths: &[usize], texts: &[&str]) -> String {
+    // The space before the text of every column and after the last one
+    let mut runs = vec![0; texts.len() + 1];
+    for (i, ((column, width), text)) in columns.iter().zip(widths).zip(texts).enumerate() {
+        let padding = width.saturating_sub(text.width());
+        match column.align {
+            Align::Left => runs[i + 1] += padding,
+            Align::Right => runs[i] += padding,
+        }
+        if i > 0 {
+            runs[i] += GAP;
+        }
+    }
+
+    let mut line = String::new();
+    for (i, text) in texts.iter().enumerate() {
+        let dotted = !text.is_empty()
+            && columns[i].dots
+            && columns[i].align == Align::Right
+            && (i == 0 || !texts[i - 1].is_empty());
+        line.push_str(&run(runs[i], dotted, i == 0, false));
+        line.push_str(text);
+
+        if columns[i].dots && columns[i].align == Align::Left && !text.is_empty() {
+            // Dots lead up to the next text, which claims the space before it
+            let next = i + 1;
+            line.push_str(&run(runs[next], true, false, next == texts.len()));
+            runs[next] = 0;
+        }
+    }
+    line.push_str(&run(runs[texts.len()], false, false, true));
+
+    line.trim_end().to_string()
+}
+
+/// Space of `length` characters, filled with dots (keeping a space next to
+/// text) if `dotted`. Runs at the start or end of the line have text on one
+/// side only.
+fn run(length: usize, dotted: bool, starts_line: bool, ends_line: bool) -> String {
+    let spaces = usize::from(!starts_line) + usize::from(!ends_line);
+    if !dotted || length <= spaces {
+        return " ".repeat(length);
+    }
+
+    let mut run = String::with_capacity(length);
+    if !starts_line {
+        run.push(' ');
+    }
+    run.push_str(&".".repeat(length - spaces));
+    if !ends_line {
+        run.push(' ');
+    }
+    run
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(width: ColumnWidth, align: Align, dots: bool) -> Column {
+        Column { width, align, dots }
+    }
+
+    fn lines(columns: &[Column], rows: &[&[&str]], columns_per_line: u32) -> Vec<String> {
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect();
+        make_table(columns, &rows, &State::default(), columns_per_line)
+            .into_iter()
+            .map(|command| match command {
+                printer::Command::Write(line) => line,
+                other => panic!("unexpected command {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_dot_leaders() {
+        let columns = [
+            column(ColumnWidth::Fill, Align::Left, true),
+            column(ColumnWidth::Fixed(8), Align::Right, false),
+        ];
+        assert_eq!(
+            lines(&columns, &[&["Coffee", "$3.50"], &["Tea", ""]], 24),
+            vec!["Coffee ........... $3.50\n", "Tea ...................\n"]
+        );
+
+        let columns = [
+            column(ColumnWidth::Fixed(5), Align::Left, false),
+            column(ColumnWidth::Fill, Align::Right, true),
+        ];
+        assert_eq!(
+            lines(&columns, &[&["Tax", "$0.84"]], 20),
+            vec!["Tax .......... $0.84\n"]
+        );
+    }
+
+    #[test]
+    fn test_widths_and_wrapping() {
+        let columns = [
+            column(ColumnWidth::Fixed(3), Align::Left, false),
+            column(ColumnWidth::Fill, Align::Left, false),
+            column(ColumnWidth::Fill, Align::Right, false),
+        ];
+        assert_eq!(widths(&columns, 32), vec![3, 13, 14]);
+        assert_eq!(
+            lines(&columns, &[&["2x", "Bagel with cream cheese", "$7.00"]], 32),
+            vec!["2x  Bagel with             $7.00\n", "    cream cheese\n"]
+        );
+    }
+
+    #[test]
+    fn test_fixed_widths_clamped_to_line() {
+        let fixed = |width| column(ColumnWidth::Fixed(width), Align::Left, false);
+        let fill = column(ColumnWidth::Fill, Align::Left, false);
+        assert_eq!(widths(&[fixed(30), fixed(10)], 32), vec![21, 10]);
+        assert_eq!(widths(&[fixed(20), fill, fixed(20)], 32), vec![15, 1, 14]);
+        assert_eq!(widths(&[fixed(3), fixed(3), fixed(3)], 4), vec![1, 1, 1]);
+        assert_eq!(
+            lines(&[fixed(12), fixed(12)], &[&["Cappuccino", "$4.20"]], 16),
+            vec!["Cappucci $4.20\n", "no\n"]
+        );
+    }
+
+    #[test]
+    fn test_restores_justification() {
+        let columns = [column(ColumnWidth::Fill, Align::Left, false)];
+        let rows = vec![vec![String::from("Tea")]];
+        let state = State {
+            justify: JustifyMode::CENTER,
+            ..State::default()
+        };
+        assert_eq!(
+            make_table(&columns, &rows, &state, 8),
+            vec![
+                printer::Command::Justify(JustifyMode::LEFT),
+                printer::Command::Write(String::from("Tea\n")),
+                printer::Command::Justify(JustifyMode::CENTER),
+            ]
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Checks of programs against the capabilities of the target printer
+use std::fmt;
+use std::str::FromStr;
+
+use escpos::utils::Font;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::codepage;
+use crate::printer;
+use crate::printer::codes_2d::Code2d;
+use crate::program::{Command, Program};
+
+/// Lines fed instead of a cut, so the receipt can be torn off
+const TEAR_OFF_FEED: u8 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The command misbehaves, but can be adapted to the printer
+    Warning,
+    /// The command can't be printed
+    Error,
+}
+
+/// A problem of a command of a program on the target printer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub severity: Severity,
+    /// Index of the command in the program
+    pub command: usize,
+    pub message: String,
+    /// Command replacing the offending one to adapt the program to the printer
+    pub fix: Option<Command>,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} (command {})",
+            self.severity,
+            self.message,
+            self.command + 1
+        )
+    }
+}
+
+/// How jobs with issues are handled for a printer
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Policy {
+    /// Jobs with any issue are not printed
+    Reject,
+    /// Commands are adapted to the printer where possible, jobs with errors are
+    /// not printed
+    #[default]
+    Adapt,
+    /// Jobs are printed as they are
+    Ignore,
+}
+
+#[derive(Debug, Error)]
+#[error("unknown validation policy '{0}', expected reject, adapt or ignore")]
+pub struct UnknownPolicy(String);
+
+impl FromStr for Policy {
+    type Err = UnknownPolicy;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy.trim().to_lowercase().as_str() {
+            "reject" => Ok(Policy::Reject),
+            "adapt" => Ok(Policy::Adapt),
+            "ignore" => Ok(Policy::Ignore),
+            _ => Err(UnknownPolicy(policy.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("job rejected: {}", .0.iter().map(Issue::to_string).collect::<Vec<_>>().join("; "))]
+pub struct Rejected(pub Vec<Issue>);
+
+impl Policy {
+    /// Applies the policy to a program with the issues found by [`validate`]
+    pub fn apply(&self, mut program: Program, issues: Vec<Issue>) -> Result<Program, Rejected> {
+        let rejected = match self {
+            Policy::Reject => !issues.is_empty(),
+            Policy::Adapt => issues.iter().any(|issue| issue.severity == Severity::Error),
+            Policy::Ignore => false,
+        };
+        if rejected {
+            return Err(Rejected(issues));
+        }
+
+        if *self == Policy::Adapt {
+            for issue in issues {
+                if let Some(fix) = issue.fix {
+                    program.commands[issue.command] = fix;
+                }
+            }
+        }
+        Ok(program)
+    }
+}
+
+/// Checks every command of a program against the features, fonts, code pages
+/// and paper width of a printer profile
+pub fn validate(program: &Program, profile: &escpos_db::Profile) -> Vec<Issue> {
+    let features = &profile.features;
+    let width_px = super::paper_width_px(profile);
+    let mut encoder = codepage::Encoder::new(profile);
+
+    let mut issues = Vec::new();
+    for (index, command) in program.commands.iter().enumerate() {
+        let mut issue = |severity, message: String, fix| {
+            issues.push(Issue {
+                severity,
+                command: index,
+                message,
+                fix,
+            })
+        };
+        match command {
+            Command::Raw(printer::Command::Cut)
+                if !features.paper_full_cut() && !features.paper_part_cut() =>
+            {
+                issue(
+                    Severity::Warning,
+                    format!("{} has no cutter", profile.name),
+                    Some(Command::Raw(printer::Command::Feed(TEAR_OFF_FEED))),
+                );
+            }
+            Command::Raw(printer::Command::Font(font)) => {
+                let number = match font {
+                    Font::A => 0,
+                    Font::B => 1,
+                    Font::C => 2,
+                };
+                // Profiles without fonts don't tell which ones exist
+                if profile.fonts.iter().next().is_some() && profile.fonts.get(number).is_none() {
+                    issue(
+                        Severity::Warning,
+                        format!("{} has no {}", profile.name, font),
+                        Some(Command::Raw(printer::Command::Font(Font::A))),
+                    );
+                }
+            }
+            Command::Raw(printer::Command::Size(width, height))
+                if !(1..=8).contains(width) || !(1..=8).contains(height) =>
+            {
+                issue(
+                    Severity::Warning,
+                    format!("size {},{} is outside of 1-8", width, height),
+                    Some(Command::Raw(printer::Command::Size(
+                        (*width).clamp(1, 8),
+                        (*height).clamp(1, 8),
+                    ))),
+                );
+            }
+            Command::Raw(printer::Command::Code2d(code))
+                if !matches!(code, Code2d::QrCode(..)) && !code.supported(features) =>
+            {
+                issue(
+                    Severity::Error,
+                    format!("{} does not support {}", profile.name, code.name()),
+                    None,
+                );
+            }
+            Command::Image { .. } | Command::Svg { .. } | Command::TextImage { .. }
+                if !features.bit_image_raster() =>
+            {
+                issue(
+                    Severity::Error,
+                    format!("{} can't print images", profile.name),
+                    None,
+                );
+            }
+            Command::Image { source, options } => {
+                if let Some(width) = options.width.filter(|width| *width > width_px) {
+                    let mut options = options.clone();
+                    options.width = Some(width_px);
+                    issue(
+                        Severity::Warning,
+                        format!(
+                            "image width {} is wider than the paper ({})",
+                            width, width_px
+                        ),
+                        Some(Command::Image {
+                            source: source.clone(),
+                            options,
+                        }),
+                    );
+                }
+            }
+            Command::Svg { source, options } => {
+                if let Some(width) = options.width.filter(|width| *width > width_px) {
+                    let mut options = options.clone();
+                    options.width = Some(width_px);
+                    issue(
+                        Severity::Warning,
+                        format!(
+                            "image width {} is wider than the paper ({})",
+                            width, width_px
+                        ),
+                        Some(Command::Svg {
+                            source: source.clone(),
+                            options,
+                        }),
+                    );
+                }
+            }
+            Command::CodePage(Some(name)) if !encoder.force(Some(name)) => {
+                issue(
+                    Severity::Warning,
+                    format!("{} has no code page {}", profile.name, name),
+                    Some(Command::CodePage(None)),
+                );
+            }
+            _ => {}
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::{ImageOptions, ImageSource};
+
+    fn program(commands: Vec<Command>) -> Program {
+        Program { commands }
+    }
+
+    #[test]
+    fn test_validate() {
+        let image = |width| Command::Image {
+            source: ImageSource::File(String::from("logo.png")),
+            options: ImageOptions {
+                width: Some(width),
+                ..ImageOptions::default()
+            },
+        };
+        let program = program(vec![
+            Command::Raw(printer::Command::Font(Font::B)),
+            image(384),
+            image(500),
+            Command::Raw(printer::Command::Cut),
+        ]);
+
+        assert_eq!(validate(&program, &escpos_db::DEFAULT), vec![]);
+
+        let issues = validate(&program, &escpos_db::ZJ_5870);
+        assert_eq!(
+            issues.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "warning: ZJ-5870 Thermal Receipt Printer has no font B (command 1)",
+                "warning: image width 500 is wider than the paper (384) (command 3)",
+                "warning: ZJ-5870 Thermal Receipt Printer has no cutter (command 4)",
+            ]
+        );
+        assert_eq!(issues[2].fix, Some(Command::Raw(printer::Command::Feed(4))));
+    }
+
+    #[test]
+    fn test_policies() {
+        let cut = program(vec![Command::Raw(printer::Command::Cut)]);
+        let issues = validate(&cut, &escpos_db::ZJ_5870);
+
+        assert!(Policy::Reject.apply(cut.clone(), issues.clone()).is_err());
+        assert_eq!(
+            Policy::Ignore.apply(cut.clone(), issues.clone()).unwrap(),
+            cut
+        );
+        assert_eq!(
+            Policy::Adapt.apply(cut.clone(), issues).unwrap(),
+            program(vec![Command::Raw(printer::Command::Feed(4))])
+        );
+
+        let pdf417 = program(vec![Command::Raw(printer::Command::Code2d(
+            Code2d::Pdf417(String::from("x"), Default::default()),
+        ))]);
+        let issues = validate(&pdf417, &escpos_db::ZJ_5870);
+        assert_eq!(issues[0].severity, Severity::Error);
+        assert!(Policy::Adapt.apply(pdf417, issues).is_err());
+
+        assert_eq!("Reject".parse::<Policy>().unwrap(), Policy::Reject);
+        assert!("maybe".parse::<Policy>().is_err());
+    }
+}
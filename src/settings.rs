@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use crate::renderer::{Policy, UnknownPolicy};
+
+/// Settings that change how jobs are rendered for a single printer
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PrinterSettings {
+    /// Render barcodes and QR codes as images even if the profile lists native
+    /// support, for printers that silently ignore the commands
+    pub rasterize_codes: bool,
+    /// How jobs that don't fit the printer's profile are handled
+    pub validation: Policy,
+    /// Directory `image file` commands may read from, file images are
+    /// rejected without one
+    pub image_dir: Option<PathBuf>,
+    /// Directory `text_image` fonts may be read from, font files are
+    /// rejected without one
+    pub font_dir: Option<PathBuf>,
+}
+
+/// Settings of all printers, looked up by printer id
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    rasterize_codes: Selection,
+    validation: Policies,
+    image_dir: Option<PathBuf>,
+    font_dir: Option<PathBuf>,
+}
+
+/// Printers a setting is enabled for
+#[derive(Debug, Clone, Default, PartialEq)]
+enum Selection {
+    #[default]
+    None,
+    All,
+    Printers(HashSet<String>),
+}
+
+impl Selection {
+    /// Parses a comma separated list of printer ids, or `all`
+    fn parse(list: &str) -> Self {
+        let ids: HashSet<String> = list
+            .split(',')
+            .map(|id| id.trim().to_lowercase())
+            .filter(|id| !id.is_empty())
+            .collect();
+        if ids.contains("all") {
+            Selection::All
+        } else if ids.is_empty() {
+            Selection::None
+        } else {
+            Selection::Printers(ids)
+        }
+    }
+
+    fn contains(&self, printer_id: &str) -> bool {
+        match self {
+            Selection::None => false,
+            Selection::All => true,
+            Selection::Printers(ids) => ids.contains(&printer_id.to_lowercase()),
+        }
+    }
+}
+
+/// A validation policy for all printers and the policies of single printers
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Policies {
+    default: Policy,
+    printers: HashMap<String, Policy>,
+}
+
+impl Policies {
+    /// Parses a comma separated list of a policy and `<printer id>=<policy>`
+    /// entries, e.g. `adapt,kitchen=reject`
+    fn parse(list: &str) -> Result<Self, UnknownPolicy> {
+        let mut policies = Policies::default();
+        for entry in list.split(',').filter(|entry| !entry.trim().is_empty()) {
+            match entry.split_once('=') {
+                Some((id, policy)) => {
+                    policies
+                        .printers
+                        .insert(id.trim().to_lowercase(), policy.parse()?);
+                }
+                None => policies.default = entry.parse()?,
+            }
+        }
+        Ok(policies)
+    }
+
+    fn get(&self, printer_id: &str) -> Policy {
+        self.printers
+            .get(&printer_id.to_lowercase())
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+impl Settings {
+    /// Settings from the `RASTERIZE_CODES` list of printer ids and the
+    /// `VALIDATION_POLICY` list of policies
+    pub fn new(rasterize_codes: &str, validation: &str) -> Result<Self, UnknownPolicy> {
+        Ok(Settings {
+            rasterize_codes: Selection::parse(rasterize_codes),
+            validation: Policies::parse(validation)?,
+            image_dir: None,
+            font_dir: None,
+        })
+    }
+
+    /// Allows `image file` commands to read images from `dir` (`IMAGE_DIR`)
+    pub fn with_image_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.image_dir = dir;
+        self
+    }
+
+    /// Allows `text_image` commands to read font files from `dir` (`FONT_DIR`)
+    pub fn with_font_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.font_dir = dir;
+        self
+    }
+
+    pub fn printer(&self, printer_id: &str) -> PrinterSettings {
+        PrinterSettings {
+            rasterize_codes: self.rasterize_codes.contains(printer_id),
+            validation: self.validation.get(printer_id),
+            image_dir: self.image_dir.clone(),
+            font_dir: self.font_dir.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_printer_selection() {
+        let settings = Settings::new("manual, Kitchen", "").unwrap();
+        assert!(settings.printer("manual").rasterize_codes);
+        assert!(settings.printer("kitchen").rasterize_codes);
+        assert!(!settings.printer("office").rasterize_codes);
+
+        assert!(
+            Settings::new("all", "")
+                .unwrap()
+                .printer("office")
+                .rasterize_codes
+        );
+        assert_eq!(
+            Settings::new(" ", "").unwrap().printer("manual"),
+            PrinterSettings::default()
+        );
+    }
+
+    #[test]
+    fn test_validation_policies() {
+        let settings = Settings::new("", "ignore, Kitchen=reject").unwrap();
+        assert_eq!(settings.printer("kitchen").validation, Policy::Reject);
+        assert_eq!(settings.printer("office").validation, Policy::Ignore);
+        assert_eq!(
+            Settings::new("", "").unwrap().printer("office").validation,
+            Policy::Adapt
+        );
+        assert!(Settings::new("", "kitchen=shred").is_err());
+    }
+}
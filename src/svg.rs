@@ -0,0 +1,55 @@
+//! Rasterization of SVG graphics
+use resvg::{tiny_skia, usvg};
+use thiserror::Error;
+
+use crate::truetype;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid SVG: {0}")]
+    Parse(#[from] usvg::Error),
+    #[error("SVG has no visible size")]
+    Empty,
+}
+
+/// Renders an SVG document scaled to `width` pixels, keeping its aspect ratio
+///
+/// `dpi` is used to convert physical units like `mm` in the document to pixels.
+/// System fonts are available for text.
+pub fn rasterize(svg: &str, dpi: u16, width: u32) -> Result<tiny_skia::Pixmap, Error> {
+    let opt = usvg::Options {
+        dpi: dpi as f32,
+        shape_rendering: usvg::ShapeRendering::CrispEdges,
+        fontdb: truetype::SYSTEM_FONTS.clone(),
+        ..Default::default()
+    };
+    let svg = usvg::Tree::from_str(svg, &opt)?;
+    let size = svg.size();
+
+    let scale = width as f32 / size.width();
+    let canvas_size = usvg::Size::from_wh(width as f32, size.height() * scale)
+        .ok_or(Error::Empty)?
+        .to_int_size();
+    let trans = usvg::Transform::from_scale(scale, scale);
+
+    let mut pixmap =
+        tiny_skia::Pixmap::new(canvas_size.width(), canvas_size.height()).ok_or(Error::Empty)?;
+    resvg::render(&svg, trans, &mut pixmap.as_mut());
+    Ok(pixmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rasterize_scales_to_width() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="5"><rect width="5" height="5"/></svg>"#;
+        let pixmap = rasterize(svg, 180, 40).unwrap();
+        assert_eq!((pixmap.width(), pixmap.height()), (40, 20));
+        assert_eq!(pixmap.pixel(0, 0).unwrap().alpha(), 255);
+        assert_eq!(pixmap.pixel(39, 0).unwrap().alpha(), 0);
+
+        assert!(matches!(rasterize("<svg", 180, 40), Err(Error::Parse(_))));
+    }
+}
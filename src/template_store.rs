@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Thread-safe store for named program templates
+///
+/// Templates are fed by retained messages on `escpos/templates/{name}` and can be
+/// referenced from programs with `include "<name>"`.
+#[derive(Clone, Default)]
+pub struct TemplateStore {
+    templates: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl TemplateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add or replace a template. An empty source removes the template, matching
+    /// how retained MQTT messages are cleared.
+    pub async fn set(&self, name: &str, source: &str) {
+        let mut templates = self.templates.write().await;
+        if source.is_empty() {
+            if templates.remove(name).is_some() {
+                log::info!("Removed template: {}", name);
+            }
+        } else {
+            templates.insert(name.to_string(), source.to_string());
+            log::info!("Stored template: {}", name);
+        }
+    }
+
+    /// Get the source of a template
+    pub async fn get(&self, name: &str) -> Option<String> {
+        let templates = self.templates.read().await;
+        templates.get(name).cloned()
+    }
+
+    /// Copy of all templates, used to resolve includes without holding the lock
+    pub async fn snapshot(&self) -> HashMap<String, String> {
+        let templates = self.templates.read().await;
+        templates.clone()
+    }
+}
@@ -0,0 +1,384 @@
+//! Rendering of text with TrueType and OpenType fonts, for characters like
+//! emoji and CJK that the fonts of receipt printers lack
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use resvg::tiny_skia::{self, FillRule, Paint, PathBuilder, Pixmap, PixmapPaint, Transform};
+use resvg::usvg::fontdb::{Database, Family, Query, Source, ID};
+use textwrap::core::Fragment;
+use textwrap::WordSeparator;
+use thiserror::Error;
+use ttf_parser::{GlyphId, OutlineBuilder, RasterImageFormat};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("no font named {0}")]
+    UnknownFont(String),
+    #[error("invalid font file")]
+    InvalidFont,
+    #[error("no fonts installed")]
+    NoFonts,
+    #[error("text has no visible size")]
+    Empty,
+}
+
+/// Fonts installed on the system, loaded on first use and shared by all
+/// renderings
+pub static SYSTEM_FONTS: Lazy<Arc<Database>> = Lazy::new(|| {
+    let mut database = Database::new();
+    database.load_system_fonts();
+    Arc::new(database)
+});
+
+/// Families tried in order for text without a font
+const DEFAULT_FAMILIES: [Family; 4] = [
+    Family::SansSerif,
+    Family::Name("Noto Sans"),
+    Family::Name("DejaVu Sans"),
+    Family::Name("FreeSans"),
+];
+
+/// Font used for text, characters it lacks are taken from the system fonts
+#[derive(Debug, Clone)]
+pub enum FontSource<'a> {
+    /// A sans-serif system font
+    Default,
+    /// Family name of a system font
+    Family(&'a str),
+    /// Contents of a font file
+    Data(Vec<u8>),
+}
+
+/// Renders text in black at `size_px` pixels per em, wrapped to lines of at
+/// most `width` pixels
+///
+/// Lines are broken at the line break opportunities of Unicode, which includes
+/// every character of CJK text, and at `\n`. The pixmap is as wide as the
+/// longest line. Glyphs are not shaped, so ligatures and emoji sequences are
+/// drawn as their single characters.
+pub fn rasterize(text: &str, font: FontSource, size_px: f32, width: u32) -> Result<Pixmap, Error> {
+    let mut fonts = Fonts::new(font)?;
+    let (ascender, line_height) = fonts.line_metrics(size_px).ok_or(Error::InvalidFont)?;
+
+    let lines: Vec<String> = text
+        .split('\n')
+        .flat_map(|paragraph| fonts.wrap(paragraph, size_px, width as f32))
+        .collect();
+    let canvas_width = lines
+        .iter()
+        .map(|line| fonts.measure(line, size_px))
+        .fold(0.0, f32::max)
+        .ceil()
+        .min(width as f32) as u32;
+    let canvas_height = (line_height * lines.len() as f32).ceil() as u32;
+    let mut pixmap = Pixmap::new(canvas_width, canvas_height).ok_or(Error::Empty)?;
+
+    for (number, line) in lines.iter().enumerate() {
+        let baseline = ascender + line_height * number as f32;
+        let mut x = 0.0;
+        for c in line.chars() {
+            let glyph = fonts.glyph(c);
+            fonts.draw(&mut pixmap, &glyph, x, baseline, size_px);
+            x += glyph.advance * size_px;
+        }
+    }
+    Ok(pixmap)
+}
+
+/// A face of the system fonts or of the font file
+#[derive(Debug, Clone, Copy)]
+enum FaceId {
+    System(ID),
+    File(ID),
+}
+
+/// A glyph of the face that draws a character
+#[derive(Debug, Clone, Copy)]
+struct Glyph {
+    face: FaceId,
+    id: GlyphId,
+    /// Horizontal advance in ems
+    advance: f32,
+}
+
+/// The shared system fonts and the faces of a font file
+struct Databases {
+    system: Arc<Database>,
+    file: Database,
+}
+
+impl Databases {
+    fn with_face_data<T>(&self, face: FaceId, f: impl FnOnce(&[u8], u32) -> T) -> Option<T> {
+        match face {
+            FaceId::System(id) => self.system.with_face_data(id, f),
+            FaceId::File(id) => self.file.with_face_data(id, f),
+        }
+    }
+}
+
+/// The font of the text and the system fonts used as fallback
+struct Fonts {
+    databases: Databases,
+    primary: FaceId,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl Fonts {
+    fn new(font: FontSource) -> Result<Self, Error> {
+        let system = SYSTEM_FONTS.clone();
+        let mut file = Database::new();
+        let query = |families| Query {
+            families,
+            ..Query::default()
+        };
+        let primary = match font {
+            FontSource::Default => system
+                .query(&query(&DEFAULT_FAMILIES))
+                .or_else(|| system.faces().next().map(|face| face.id))
+                .map(FaceId::System)
+                .ok_or(Error::NoFonts)?,
+            FontSource::Family(name) => system
+                .query(&query(&[Family::Name(name)]))
+                .map(FaceId::System)
+                .ok_or_else(|| Error::UnknownFont(name.to_string()))?,
+            FontSource::Data(data) => file
+                .load_font_source(Source::Binary(Arc::new(data)))
+                .first()
+                .copied()
+                .map(FaceId::File)
+                .ok_or(Error::InvalidFont)?,
+        };
+        Ok(Fonts {
+            databases: Databases { system, file },
+            primary,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    /// Ascender and line height of the primary font in pixels
+    fn line_metrics(&self, size_px: f32) -> Option<(f32, f32)> {
+        self.databases.with_face_data(self.primary, |data, index| {
+            let face = ttf_parser::Face::parse(data, index).ok()?;
+            let scale = size_px / f32::from(face.units_per_em());
+            let height = face.height() + face.line_gap();
+            Some((
+                f32::from(face.ascender()) * scale,
+                f32::from(height) * scale,
+            ))
+        })?
+    }
+
+    /// The glyph for a character from the primary font, or the first system
+    /// font that has one. Falls back to the missing glyph of the primary font.
+    fn glyph(&mut self, c: char) -> Glyph {
+        let Fonts {
+            databases,
+            primary,
+            glyphs,
+        } = self;
+        let lookup = |face: FaceId| {
+            databases.with_face_data(face, |data, index| {
+                let face_data = ttf_parser::Face::parse(data, index).ok()?;
+                let id = face_data.glyph_index(c)?;
+                let advance = face_data.glyph_hor_advance(id).unwrap_or(0);
+                Some(Glyph {
+                    face,
+                    id,
+                    advance: f32::from(advance) / f32::from(face_data.units_per_em()),
+                })
+            })?
+        };
+        *glyphs.entry(c).or_insert_with(|| {
+            lookup(*primary)
+                .or_else(|| {
+                    databases
+                        .system
+                        .faces()
+                        .find_map(|face| lookup(FaceId::System(face.id)))
+                })
+                .unwrap_or(Glyph {
+                    face: *primary,
+                    id: GlyphId(0),
+                    advance: 0.5,
+                })
+        })
+    }
+
+    /// Width of text in pixels
+    fn measure(&mut self, text: &str, size_px: f32) -> f32 {
+        text.chars().map(|c| self.glyph(c).advance * size_px).sum()
+    }
+
+    /// Splits a paragraph into lines of at most `width` pixels
+    fn wrap(&mut self, paragraph: &str, size_px: f32, width: f32) -> Vec<String> {
+        let mut words = Vec::new();
+        for word in WordSeparator::UnicodeBreakProperties.find_words(paragraph) {
+            // Words wider than the paper are broken between their characters
+            let mut start = 0;
+            let mut word_width = 0.0;
+            for (i, c) in word.word.char_indices() {
+                let char_width = self.glyph(c).advance * size_px;
+                if word_width + char_width > width && i > start {
+                    words.push(Word {
+                        text: &word.word[start..i],
+                        whitespace: "",
+                        width: word_width,
+                        whitespace_width: 0.0,
+                    });
+                    start = i;
+                    word_width = 0.0;
+                }
+                word_width += char_width;
+            }
+            words.push(Word {
+                text: &word.word[start..],
+                whitespace: word.whitespace,
+                width: word_width,
+                whitespace_width: self.measure(word.whitespace, size_px),
+            });
+        }
+
+        let lines = textwrap::wrap_algorithms::wrap_first_fit(&words, &[f64::from(width)]);
+        if lines.is_empty() {
+            return vec![String::new()];
+        }
+        lines
+            .iter()
+            .map(|line| {
+                let mut text = String::new();
+                for (i, word) in line.iter().enumerate() {
+                    text.push_str(word.text);
+                    if i + 1 < line.len() {
+                        text.push_str(word.whitespace);
+                    }
+                }
+                text
+            })
+            .collect()
+    }
+
+    /// Draws a glyph with its origin at `x` on the baseline
+    fn draw(&self, pixmap: &mut Pixmap, glyph: &Glyph, x: f32, baseline: f32, size_px: f32) {
+        self.databases.with_face_data(glyph.face, |data, index| {
+            let face = ttf_parser::Face::parse(data, index).ok()?;
+
+            // Bitmap fonts like Noto Color Emoji have no outlines
+            if let Some(image) = face.glyph_raster_image(glyph.id, size_px as u16) {
+                if image.format == RasterImageFormat::PNG {
+                    let bitmap = Pixmap::decode_png(image.data).ok()?;
+                    let scale = size_px / f32::from(image.pixels_per_em);
+                    let top = f32::from(image.y) + f32::from(image.height);
+                    let transform = Transform::from_row(
+                        scale,
+                        0.0,
+                        0.0,
+                        scale,
+                        x + f32::from(image.x) * scale,
+                        baseline - top * scale,
+                    );
+                    let paint = PixmapPaint {
+                        quality: tiny_skia::FilterQuality::Bilinear,
+                        ..PixmapPaint::default()
+                    };
+                    pixmap.draw_pixmap(0, 0, bitmap.as_ref(), &paint, transform, None);
+                    return Some(());
+                }
+            }
+
+            let mut outline = Outline(PathBuilder::new());
+            face.outline_glyph(glyph.id, &mut outline)?;
+            let path = outline.0.finish()?;
+            let scale = size_px / f32::from(face.units_per_em());
+            // Font units point up, pixels point down
+            let transform = Transform::from_row(scale, 0.0, 0.0, -scale, x, baseline);
+            let mut paint = Paint::default();
+            paint.set_color_rgba8(0, 0, 0, 255);
+            pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+            Some(())
+        });
+    }
+}
+
+/// A piece of text up to a line break opportunity, measured in pixels
+#[derive(Debug)]
+struct Word<'a> {
+    text: &'a str,
+    whitespace: &'a str,
+    width: f32,
+    whitespace_width: f32,
+}
+
+impl Fragment for Word<'_> {
+    fn width(&self) -> f64 {
+        f64::from(self.width)
+    }
+
+    fn whitespace_width(&self) -> f64 {
+        f64::from(self.whitespace_width)
+    }
+
+    fn penalty_width(&self) -> f64 {
+        0.0
+    }
+}
+
+/// Collects the outline of a glyph into a path
+struct Outline(PathBuilder);
+
+impl OutlineBuilder for Outline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.0.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn black_pixels(pixmap: &Pixmap) -> usize {
+        pixmap.pixels().iter().filter(|p| p.alpha() > 128).count()
+    }
+
+    #[test]
+    fn test_rasterize_wraps_to_width() {
+        let line = rasterize("Hello", FontSource::Default, 24.0, 512).unwrap();
+        assert!(line.width() < 100);
+        assert!(black_pixels(&line) > 0);
+
+        let wrapped = rasterize("Hello Hello Hello", FontSource::Default, 24.0, 100).unwrap();
+        assert!(wrapped.width() <= 100);
+        assert_eq!(wrapped.height(), 3 * line.height());
+
+        let paragraphs = rasterize("Hello\n\nHello", FontSource::Default, 24.0, 512).unwrap();
+        assert_eq!(paragraphs.height(), 3 * line.height());
+    }
+
+    #[test]
+    fn test_fonts() {
+        assert!(matches!(
+            rasterize("x", FontSource::Data(vec![0; 16]), 24.0, 512),
+            Err(Error::InvalidFont)
+        ));
+        assert!(matches!(
+            rasterize("x", FontSource::Family("No Such Font"), 24.0, 512),
+            Err(Error::UnknownFont(_))
+        ));
+    }
+}